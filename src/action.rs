@@ -0,0 +1,277 @@
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use super::approval::{self, CampaignMode};
+use super::campaign::{Campaign, Campaigns, with_campaign};
+use super::context;
+use super::discord_data_structs::{Interaction, ResponseOject};
+use super::history::HistoryKind;
+use super::i18n::{self, Locale};
+use super::intent::{self, Intent};
+use super::item::ItemCatalog;
+use super::narrator::{Narration, Narrator, NarratorError, NarrationRequest};
+use super::round;
+use super::stream::{MessageStream, EDIT_INTERVAL};
+use super::snowflake::Snowflake;
+use super::AppState;
+
+/// chat starting with one of these is out of character and stays out of the story
+pub const OUT_OF_CHARACTER_PREFIXES: &[&str] = &["((", "//", "ooc:", "[ooc"];
+
+/// records a player's action in the campaign and, with a narrator configured, defers the
+/// response until the narration is ready. with a human GM the action is queued instead,
+/// and during a play-by-post round it is kept for the round
+pub fn action_command(state: &Arc<AppState>, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let (user_id, text) = match (interaction.user_id(), interaction.option_str("action")) {
+        (Some(u), Some(t)) => (u, t.trim().to_string()),
+        _ => return ResponseOject::ephemeral(locale.text("action.required"))
+    };
+
+    let mut pending: Option<(u32, String, usize)> = None;
+    let mut narrator: Option<Arc<dyn Narrator>> = None;
+    let mut round_complete: Option<u32> = None;
+    let response = with_campaign(state, interaction, |campaign| {
+        if !campaign.active {
+            return ResponseOject::ephemeral(locale.text("campaign.not_started"))
+        }
+
+        let player = match campaign.player(user_id) {
+            Some(p) => p.name.clone(),
+            None => return ResponseOject::ephemeral(locale.text("player.not_joined"))
+        };
+
+        if campaign.round.is_some() {
+            let (response, complete) = round::submit_action(state, &locale, campaign, user_id, &player, &text);
+            if complete {
+                round_complete = Some(campaign.id);
+            }
+            return response
+        }
+
+        if campaign.mode == CampaignMode::HumanGm {
+            let id = approval::queue_action(campaign, user_id, &player, &text);
+            return ResponseOject::ephemeral(locale.format("action.queued", &[("id", &id)]))
+        }
+
+        pending = Some((campaign.id, player.clone(), campaign.turns().len()));
+        narrator = state.narrator_for(campaign);
+        campaign.record(HistoryKind::Action, Some(user_id), &text);
+
+        ResponseOject::new(format!("**{}**: {}", player, text))
+    });
+
+    if let Some(campaign_id) = round_complete {
+        round::resolve_round(state, campaign_id);
+    }
+
+    let ((campaign_id, player, before), narrator) = match (pending, narrator) {
+        (Some(p), Some(n)) => (p, n),
+        _ => return response
+    };
+
+    let state = state.clone();
+    let application_id = interaction.application_id;
+    let token = interaction.token.clone();
+
+    tokio::spawn(async move {
+        context::fold_history(&state, &narrator, campaign_id, before).await;
+
+        let request = match state.campaigns.lock() {
+            Ok(lock) => lock.get(campaign_id)
+                .map(|c| context::build_request(c, &state.catalog, &state.context, before, &player, &text)),
+            Err(e) => {
+                log::error!("unable to obtain lock for app state\n{}", e);
+                None
+            }
+        };
+
+        let mut stream = MessageStream::new(state.discord.clone(), application_id, &token, &format!("**{}**: {}\n\n", player, text));
+        if let Some(request) = request {
+            narrate(&state, narrator.as_ref(), &locale, campaign_id, &request, &mut stream).await;
+        }
+        stream.flush().await;
+    });
+
+    ResponseOject::deferred()
+}
+
+/// streams the narrator's response into the deferred message, followed by the outcomes
+/// of its intents, falling back to a notice when it fails
+async fn narrate(state: &AppState, narrator: &dyn Narrator, locale: &Locale, campaign_id: u32, request: &NarrationRequest, stream: &mut MessageStream) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let (outcomes, ()) = tokio::join!(
+        narrate_resolved(&state.campaigns, &state.catalog, locale, narrator, campaign_id, request, sender),
+        stream.follow(receiver, EDIT_INTERVAL)
+    );
+
+    match outcomes {
+        Ok(outcomes) => {
+            for outcome in outcomes {
+                stream.push(&format!("\n> {}", outcome));
+            }
+        },
+        Err(e) => {
+            log::error!("narrator failed for campaign {}\n{}", campaign_id, e);
+            stream.push(&format!("\n{}", locale.text("narrator.unavailable")));
+        }
+    }
+}
+
+/// streams what the narrator says happens through `sender`. the narrator holds its text
+/// back once it asks for an intent, and when it asked for any, they are resolved and it
+/// narrates again knowing their outcomes, so the rest of the story matches what the rules
+/// applied. what went through `sender` goes into the history. returns the outcomes
+pub async fn narrate_resolved(campaigns: &Mutex<Campaigns>, catalog: &ItemCatalog, locale: &Locale, narrator: &dyn Narrator, campaign_id: u32, request: &NarrationRequest, sender: UnboundedSender<String>) -> Result<Vec<String>, NarratorError> {
+    let (first, mut shown) = forward(narrator, request, &sender).await;
+    let first = first?;
+    if first.intents.is_empty() {
+        record_narration(campaigns, campaign_id, shown.trim());
+        return Ok(Vec::new())
+    }
+
+    let outcomes = resolve_intents(campaigns, catalog, locale, campaign_id, &first.intents);
+    let told = NarrationRequest {
+        outcomes: Some(outcomes.clone()),
+        ..request.clone()
+    };
+    let (narration, rest) = forward(narrator, &told, &sender).await;
+    narration?;
+    if !shown.trim().is_empty() && !rest.trim().is_empty() {
+        shown.push(' ');
+    }
+    shown.push_str(rest.trim());
+    record_narration(campaigns, campaign_id, shown.trim());
+
+    Ok(outcomes)
+}
+
+/// streams one narration through `sender`, also returning the text that went through
+async fn forward(narrator: &dyn Narrator, request: &NarrationRequest, sender: &UnboundedSender<String>) -> (Result<Narration, NarratorError>, String) {
+    let (inner, mut receiver) = mpsc::unbounded_channel::<String>();
+    let mut shown = String::new();
+    let (narration, ()) = tokio::join!(
+        narrator.narrate_stream(request, inner),
+        async {
+            while let Some(text) = receiver.recv().await {
+                shown.push_str(&text);
+                let _ = sender.send(text);
+            }
+        }
+    );
+
+    (narration, shown)
+}
+
+/// applies the narrator's intents to the campaign, returning what the rules allowed
+fn resolve_intents(campaigns: &Mutex<Campaigns>, catalog: &ItemCatalog, locale: &Locale, campaign_id: u32, intents: &[Intent]) -> Vec<String> {
+    match campaigns.lock() {
+        Ok(mut lock) => match lock.get_mut(campaign_id) {
+            Some(campaign) => intent::apply_intents(campaign, catalog, locale, intents),
+            None => Vec::new()
+        },
+        Err(e) => {
+            log::error!("unable to obtain lock for app state\n{}", e);
+            Vec::new()
+        }
+    }
+}
+
+fn record_narration(campaigns: &Mutex<Campaigns>, campaign_id: u32, text: &str) {
+    if text.is_empty() {
+        return
+    }
+
+    match campaigns.lock() {
+        Ok(mut lock) => if let Some(campaign) = lock.get_mut(campaign_id) {
+            campaign.record(HistoryKind::Narration, None, text);
+        },
+        Err(e) => log::error!("unable to obtain lock for app state\n{}", e)
+    }
+}
+
+/// records what a player wrote in the campaign's channel as an action, so the narrator
+/// and the journal see in-character chat too. returns whether it went into the story
+pub fn record_chat(campaign: &mut Campaign, user_id: Snowflake, content: &str) -> bool {
+    let text = content.trim();
+    let out_of_character = OUT_OF_CHARACTER_PREFIXES.iter().any(|p| text.to_lowercase().starts_with(p));
+    if !campaign.active || text.is_empty() || out_of_character || campaign.player(user_id).is_none() {
+        return false
+    }
+
+    campaign.record(HistoryKind::Action, Some(user_id), text);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::narrator::MockNarrator;
+    use crate::player::Player;
+    use crate::ruleset::RulesetId;
+
+    #[tokio::test]
+    async fn narration_without_intents_is_streamed_once() {
+        let campaign = Campaign::new(Snowflake::new(10), RulesetId::D20);
+        let request = context::build_request(&campaign, &ItemCatalog::default(), &context::ContextConfig::default(), 0, "Ayla", "look around");
+
+        let mut campaigns = Campaigns::new();
+        let campaign_id = campaigns.insert(campaign);
+        let campaigns = Mutex::new(campaigns);
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let outcomes = narrate_resolved(&campaigns, &ItemCatalog::default(), &Locale::new(None, None), &MockNarrator::default(), campaign_id, &request, sender).await.unwrap();
+
+        let mut streamed = String::new();
+        while let Some(text) = receiver.recv().await {
+            streamed.push_str(&text);
+        }
+
+        let lock = campaigns.lock().unwrap();
+        let turns = lock.get(campaign_id).unwrap().turns();
+        assert!(outcomes.is_empty());
+        assert!(streamed.starts_with("Ayla tries to look around."));
+        assert_eq!(turns.len(), 1);
+        assert_eq!(turns[0].text, streamed);
+    }
+
+    #[tokio::test]
+    async fn narration_tells_the_outcome_the_rules_applied() {
+        let mut campaign = Campaign::new(Snowflake::new(10), RulesetId::D20);
+        campaign.players.push(Player::new(Snowflake::new(1), "Ayla", "Fighter", RulesetId::D20.get()));
+        let before = campaign.turns().len();
+        let request = context::build_request(&campaign, &ItemCatalog::default(), &context::ContextConfig::default(), before, "Ayla", "kick the door");
+
+        let mut campaigns = Campaigns::new();
+        let campaign_id = campaigns.insert(campaign);
+        let campaigns = Mutex::new(campaigns);
+
+        let narrator = MockNarrator {
+            intents: vec![
+                Intent::ApplyDamage { player: String::from("Ayla"), damage: String::from("4"), source: Some(String::from("a splinter")) },
+                Intent::GiveItem { player: String::from("Ayla"), item: String::from("vorpal sword"), quantity: 1 }
+            ]
+        };
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let outcomes = narrate_resolved(&campaigns, &ItemCatalog::default(), &Locale::new(None, None), &narrator, campaign_id, &request, sender).await.unwrap();
+
+        let mut streamed = String::new();
+        while let Some(text) = receiver.recv().await {
+            streamed.push_str(&text);
+        }
+
+        let lock = campaigns.lock().unwrap();
+        let campaign = lock.get(campaign_id).unwrap();
+        let ayla = &campaign.players[0];
+
+        assert_eq!(ayla.health, ayla.max_health - 4);
+        assert_eq!(outcomes.len(), 1);
+        assert!(outcomes[0].ends_with(&format!("from a splinter ({}/{} HP)", ayla.health, ayla.max_health)));
+        assert!(streamed.ends_with(&outcomes[0]));
+        assert!(!streamed.contains("vorpal sword"));
+
+        let kinds: Vec<HistoryKind> = campaign.turns().iter().map(|h| h.kind).collect();
+        assert_eq!(kinds, vec![HistoryKind::Resolution, HistoryKind::Narration]);
+        assert_eq!(campaign.turns().last().unwrap().text, streamed);
+    }
+}
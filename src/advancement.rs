@@ -0,0 +1,400 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use super::campaign::{Campaign, campaign_ruleset, with_campaign};
+use super::discord_data_structs::{
+    AutocompleteResponse,
+    CommandOptionChoice,
+    Component,
+    Interaction,
+    MessageObject,
+    ResponseOject,
+    BUTTON_STYLE_PRIMARY,
+    EPHEMERAL_FLAG,
+    MAX_AUTOCOMPLETE_CHOICES
+};
+use super::history::HistoryKind;
+use super::player::Player;
+use super::ruleset::{Ruleset, RulesetId};
+use super::snowflake::Snowflake;
+use super::i18n::{self, Locale};
+use super::AppState;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ClassFeature {
+    pub level: u8,
+    pub name: String,
+}
+
+/// a choice a player has to make when reaching `level`, such as a fighting style
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LevelChoice {
+    pub level: u8,
+    pub prompt: String,
+    pub options: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ClassDefinition {
+    pub name: String,
+
+    /// die rolled for health in rulesets where it grows with level
+    #[serde(default)]
+    pub hit_die: u8,
+
+    #[serde(default)]
+    pub features: Vec<ClassFeature>,
+
+    #[serde(default)]
+    pub choices: Vec<LevelChoice>,
+}
+
+/// the classes players of one ruleset can pick. xp thresholds and what a level grants
+/// come from the ruleset itself
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+pub struct ClassCatalog {
+    pub classes: Vec<ClassDefinition>,
+}
+
+impl ClassCatalog {
+    pub fn class(&self, name: &str) -> Option<&ClassDefinition> {
+        self.classes.iter().find(|c| c.name.eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+pub struct AdvancementConfig {
+    pub rulesets: HashMap<RulesetId, ClassCatalog>
+}
+
+impl AdvancementConfig {
+    pub fn load(path: &Path) -> Result<AdvancementConfig, Box<dyn Error + Send + Sync>> {
+        let config_string: String = fs::read_to_string(path)?;
+        let config: AdvancementConfig = serde_json::from_str(&config_string)?;
+
+        log::info!("loaded advancement rules for {} rulesets from {}", config.rulesets.len(), path.display());
+        Ok(config)
+    }
+
+    pub fn classes(&self, ruleset: RulesetId) -> Option<&ClassCatalog> {
+        self.rulesets.get(&ruleset)
+    }
+}
+
+/// gives a player xp and applies every level gained. returns a summary line per change
+pub fn award_xp(campaign: &mut Campaign, classes: Option<&ClassCatalog>, locale: &Locale, player_id: Snowflake, amount: u32) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let ruleset = campaign.ruleset.get();
+
+    let player = match campaign.player_mut(player_id) {
+        Some(p) => p,
+        None => return lines
+    };
+
+    player.xp = player.xp.saturating_add(amount);
+    lines.push(locale.format("xp.gain", &[("name", &player.name), ("amount", &amount), ("total", &player.xp)]));
+    let mut history: Vec<(HistoryKind, String)> = vec![(HistoryKind::Experience, lines[0].clone())];
+
+    let new_level = ruleset.level_for(player.xp);
+    while player.level < new_level {
+        let line = level_up(player, ruleset, classes, locale);
+        history.push((HistoryKind::LevelUp, line.clone()));
+        lines.push(line);
+    }
+
+    for (kind, text) in history {
+        campaign.record(kind, Some(player_id), &text);
+    }
+
+    lines
+}
+
+/// raises a player one level by the rules of the ruleset and describes what it brought
+fn level_up(player: &mut Player, ruleset: &dyn Ruleset, classes: Option<&ClassCatalog>, locale: &Locale) -> String {
+    let class = classes.and_then(|c| c.class(&player.class));
+    let gained = ruleset.level_up(player, class);
+
+    let mut line = locale.format("xp.level_up", &[("name", &player.name), ("level", &gained.level)]);
+    if gained.health > 0 {
+        line.push_str(&locale.format("xp.max_hp", &[("hp", &gained.health), ("max", &player.max_health)]));
+    }
+    for feature in &gained.features {
+        line.push_str(&locale.format("xp.feature", &[("feature", &locale.term(feature))]));
+    }
+
+    if !player.pending_choices.is_empty() {
+        line.push_str(&locale.text("xp.choices_pending"));
+    }
+
+    line
+}
+
+pub fn xp_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    match interaction.subcommand() {
+        Some("award") => award(state, interaction),
+        Some("split") => split(state, interaction),
+        Some("show") => show(state, interaction),
+        _ => ResponseOject::ephemeral(i18n::locale(state, interaction).format("error.unknown_command", &[("command", &"xp")]))
+    }
+}
+
+fn award(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let (target, amount) = match (interaction.option_snowflake("player"), interaction.option_i64("amount")) {
+        (Some(t), Some(a)) if a > 0 => (t, a as u32),
+        _ => return ResponseOject::ephemeral(locale.text("xp.award_required"))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("xp.not_gm"))
+        }
+
+        if campaign.player(target).is_none() {
+            return ResponseOject::ephemeral(locale.text("player.other_not_joined"))
+        }
+
+        let classes = state.advancement.classes(campaign.ruleset);
+        ResponseOject::new(award_xp(campaign, classes, &locale, target, amount).join("\n"))
+    })
+}
+
+fn split(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let amount = match interaction.option_i64("amount") {
+        Some(a) if a > 0 => a as u32,
+        _ => return ResponseOject::ephemeral(locale.text("xp.amount_required"))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("xp.not_gm"))
+        }
+
+        if campaign.players.is_empty() {
+            return ResponseOject::ephemeral(locale.text("campaign.no_players"))
+        }
+
+        let share = amount / campaign.players.len() as u32;
+        let ids: Vec<Snowflake> = campaign.players.iter().map(|p| p.id).collect();
+
+        let classes = state.advancement.classes(campaign.ruleset);
+        let mut lines: Vec<String> = vec![locale.format("xp.split", &[("amount", &amount), ("players", &ids.len())])];
+        for id in ids {
+            lines.extend(award_xp(campaign, classes, &locale, id, share));
+        }
+
+        ResponseOject::new(lines.join("\n"))
+    })
+}
+
+fn show(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let user_id = match interaction.option_snowflake("player").or(interaction.user_id()) {
+        Some(u) => u,
+        None => return ResponseOject::ephemeral(locale.text("error.no_user"))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        let player = match campaign.player(user_id) {
+            Some(p) => p,
+            None => return ResponseOject::ephemeral(locale.text("player.other_not_joined"))
+        };
+
+        let next = campaign.ruleset.get().next_threshold(player.level)
+            .map(|t| locale.format("xp.next", &[("xp", &t)]))
+            .unwrap_or_default();
+
+        let mut message = locale.format("xp.show", &[
+            ("name", &player.name),
+            ("level", &player.level),
+            ("class", &locale.term(&player.class)),
+            ("xp", &player.xp),
+            ("next", &next)
+        ]);
+        if !player.features.is_empty() {
+            let features: Vec<String> = player.features.iter().map(|f| locale.term(f)).collect();
+            message.push_str(&format!("\n{}", locale.format("xp.features", &[("features", &features.join(", "))])));
+        }
+
+        ResponseOject::ephemeral(message)
+    })
+}
+
+fn choice_prompt(player: &Player, locale: &Locale) -> ResponseOject {
+    let choice = match player.pending_choices.first() {
+        Some(c) => c,
+        None => return ResponseOject::ephemeral(locale.text("levelup.none"))
+    };
+
+    let buttons: Vec<Component> = choice.options.iter()
+        .enumerate()
+        .map(|(i, option)| Component::button(BUTTON_STYLE_PRIMARY, option, &format!("levelup:{}:{}", player.id, i)))
+        .collect();
+
+    // discord allows at most five buttons per action row
+    let rows: Vec<Component> = buttons.chunks(5)
+        .map(|chunk| Component::action_row(chunk.to_vec()))
+        .collect();
+
+    let mut response = ResponseOject::with_components(locale.format("levelup.prompt", &[("level", &choice.level), ("prompt", &choice.prompt)]), rows);
+    if let Some(message) = response.message_mut() {
+        message.flags = Some(EPHEMERAL_FLAG);
+    }
+    response
+}
+
+pub fn levelup_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let user_id = match interaction.user_id() {
+        Some(u) => u,
+        None => return ResponseOject::ephemeral(locale.text("error.no_user"))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        match campaign.player(user_id) {
+            Some(p) => choice_prompt(p, &locale),
+            None => ResponseOject::ephemeral(locale.text("player.not_joined"))
+        }
+    })
+}
+
+/// resolves the player's oldest pending choice with the option behind the clicked button
+pub fn levelup_button(state: &AppState, interaction: &Interaction, player_id: Snowflake, option: usize) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    if interaction.user_id() != Some(player_id) {
+        return ResponseOject::ephemeral(locale.text("levelup.not_yours"))
+    }
+
+    with_campaign(state, interaction, |campaign| {
+        let player = match campaign.player_mut(player_id) {
+            Some(p) => p,
+            None => return ResponseOject::ephemeral(locale.text("player.not_joined"))
+        };
+
+        let picked = match player.pending_choices.first().and_then(|c| c.options.get(option)) {
+            Some(o) => o.clone(),
+            None => return ResponseOject::update(locale.text("levelup.already_made"), Vec::new())
+        };
+
+        let choice = player.pending_choices.remove(0);
+        player.features.push(picked.clone());
+        let line = locale.format("levelup.chose", &[("name", &player.name), ("option", &picked), ("prompt", &choice.prompt)]);
+        let next = choice_prompt(player, &locale);
+
+        campaign.record(HistoryKind::LevelUp, Some(player_id), &line);
+
+        match next {
+            ResponseOject::Message(MessageObject { content, components: Some(components), .. }) => {
+                ResponseOject::update(format!("{}\n\n{}", line, content), components)
+            },
+            _ => ResponseOject::update(line, Vec::new())
+        }
+    })
+}
+
+/// suggests class names from the ruleset for /join
+pub fn class_autocomplete(state: &AppState, interaction: &Interaction) -> AutocompleteResponse {
+    let partial = interaction.focused_option()
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .to_lowercase();
+
+    let ruleset = campaign_ruleset(&state.campaigns, interaction);
+    let choices: Vec<CommandOptionChoice> = match state.advancement.classes(ruleset) {
+        Some(rules) => rules.classes.iter()
+            .filter(|c| c.name.to_lowercase().contains(&partial))
+            .take(MAX_AUTOCOMPLETE_CHOICES)
+            .map(|c| i18n::choice(serde_json::Value::String(c.name.clone()), |locale| locale.term(&c.name)))
+            .collect(),
+        None => Vec::new()
+    };
+
+    AutocompleteResponse::new(choices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AYLA: Snowflake = Snowflake::new(1);
+
+    fn config() -> AdvancementConfig {
+        serde_json::from_str(include_str!("../advancement.json")).unwrap()
+    }
+
+    fn campaign(ruleset: RulesetId, class: &str) -> Campaign {
+        let mut campaign = Campaign::new(Snowflake::new(10), ruleset);
+        campaign.players.push(Player::new(AYLA, "Ayla", class, ruleset.get()));
+        campaign
+    }
+
+    #[test]
+    fn levels_follow_the_thresholds_of_the_ruleset() {
+        let d20 = RulesetId::D20.get();
+        assert_eq!(d20.level_for(0), 1);
+        assert_eq!(d20.level_for(299), 1);
+        assert_eq!(d20.level_for(300), 2);
+        assert_eq!(d20.level_for(2700), 4);
+        assert_eq!(d20.level_for(u32::MAX), 20);
+        assert_eq!(d20.next_threshold(1), Some(300));
+        assert_eq!(d20.next_threshold(19), Some(355000));
+        assert_eq!(d20.next_threshold(20), None);
+
+        let pbta = RulesetId::Pbta.get();
+        assert_eq!(pbta.level_for(4), 1);
+        assert_eq!(pbta.level_for(5), 2);
+        assert_eq!(pbta.level_for(50), 11);
+        assert_eq!(pbta.next_threshold(11), None);
+    }
+
+    #[test]
+    fn awarding_xp_applies_every_level_gained() {
+        let config = config();
+        let locale = Locale::new(None, None);
+        let mut campaign = campaign(RulesetId::D20, "Fighter");
+
+        let lines = award_xp(&mut campaign, config.classes(RulesetId::D20), &locale, AYLA, 2700);
+        assert_eq!(lines.len(), 4);
+
+        let player = campaign.player(AYLA).unwrap();
+        assert_eq!((player.level, player.xp), (4, 2700));
+        assert_eq!((player.health, player.max_health), (28, 28));
+        assert_eq!(player.features, vec![String::from("Action Surge")]);
+        let levels: Vec<u8> = player.pending_choices.iter().map(|c| c.level).collect();
+        assert_eq!(levels, vec![3, 4]);
+
+        let kinds: Vec<HistoryKind> = campaign.history.iter().map(|h| h.kind).collect();
+        assert_eq!(kinds, vec![HistoryKind::Experience, HistoryKind::LevelUp, HistoryKind::LevelUp, HistoryKind::LevelUp]);
+
+        assert_eq!(award_xp(&mut campaign, config.classes(RulesetId::D20), &locale, AYLA, 100).len(), 1);
+        assert_eq!(campaign.player(AYLA).unwrap().level, 4);
+    }
+
+    #[test]
+    fn classes_outside_the_config_use_the_default_hit_die() {
+        let locale = Locale::new(None, None);
+        let mut campaign = campaign(RulesetId::D20, "Bard");
+
+        award_xp(&mut campaign, None, &locale, AYLA, 300);
+        let player = campaign.player(AYLA).unwrap();
+        assert_eq!((player.level, player.max_health), (2, 15));
+        assert!(player.features.is_empty() && player.pending_choices.is_empty());
+    }
+
+    #[test]
+    fn pbta_advances_without_more_harm_boxes() {
+        let config = config();
+        let locale = Locale::new(None, None);
+        let mut campaign = campaign(RulesetId::Pbta, "The Hunter");
+
+        award_xp(&mut campaign, config.classes(RulesetId::Pbta), &locale, AYLA, 5);
+        let player = campaign.player(AYLA).unwrap();
+        assert_eq!((player.level, player.max_health), (2, 6));
+        assert_eq!(player.pending_choices.len(), 1);
+        assert_eq!(player.pending_choices[0].prompt, "choose an advance");
+    }
+}
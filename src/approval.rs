@@ -0,0 +1,431 @@
+use serde::{Deserialize, Serialize};
+use super::campaign::{Campaign, with_campaign};
+use super::discord_data_structs::{
+    Component,
+    Interaction,
+    ResponseOject,
+    MAX_MESSAGE_LENGTH,
+    BUTTON_STYLE_DANGER,
+    BUTTON_STYLE_PRIMARY,
+    BUTTON_STYLE_SUCCESS,
+    TEXT_INPUT_PARAGRAPH
+};
+use super::history::{self, HistoryKind};
+use super::i18n::{self, Locale};
+use super::snowflake::Snowflake;
+use super::AppState;
+
+/// who resolves player actions
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CampaignMode {
+    /// actions are posted right away and answered by the narrator, if one is configured
+    #[default]
+    Narrator,
+
+    /// actions wait in a queue until the GM approves them
+    HumanGm,
+}
+
+impl CampaignMode {
+    pub fn from_key(key: &str) -> Option<CampaignMode> {
+        match key.trim().to_lowercase().as_str() {
+            "narrator" => Some(CampaignMode::Narrator),
+            "human_gm" => Some(CampaignMode::HumanGm),
+            _ => None
+        }
+    }
+}
+
+/// discord allows five action rows on a message, one per pending action
+pub const MAX_PANEL_ACTIONS: usize = 5;
+
+/// longest an action is shown on the panel, so every shown action and the line about the
+/// rest fit in one message
+pub const MAX_PANEL_ACTION_LENGTH: usize = (MAX_MESSAGE_LENGTH - 200) / MAX_PANEL_ACTIONS;
+
+/// an action waiting for the GM
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PendingAction {
+    pub id: u32,
+    pub player_id: Snowflake,
+    pub player: String,
+    pub text: String,
+
+    /// narration the GM attached while editing
+    pub narration: Option<String>,
+
+    /// unix timestamp in seconds of when the action was submitted
+    pub submitted: u64,
+}
+
+impl PendingAction {
+    pub fn describe(&self) -> String {
+        let mut description = format!("#{} **{}**: {}", self.id, self.player, self.text);
+        if let Some(narration) = &self.narration {
+            description.push_str(&format!("\n> {}", narration.replace('\n', "\n> ")));
+        }
+        description
+    }
+
+    fn buttons(&self, locale: &Locale) -> Component {
+        Component::action_row(vec![
+            Component::button(BUTTON_STYLE_SUCCESS, &locale.format("approval.approve", &[("id", &self.id)]), &format!("approval:approve:{}", self.id)),
+            Component::button(BUTTON_STYLE_PRIMARY, &locale.text("approval.edit"), &format!("approval:edit:{}", self.id)),
+            Component::button(BUTTON_STYLE_DANGER, &locale.text("approval.reject"), &format!("approval:reject:{}", self.id))
+        ])
+    }
+}
+
+/// puts an action into the GM's queue instead of resolving it
+pub fn queue_action(campaign: &mut Campaign, player_id: Snowflake, player: &str, text: &str) -> u32 {
+    let id = campaign.next_action_id;
+    campaign.next_action_id += 1;
+
+    campaign.pending_actions.push(PendingAction {
+        id,
+        player_id,
+        player: player.to_string(),
+        text: text.to_string(),
+        narration: None,
+        submitted: history::now()
+    });
+    id
+}
+
+/// the GM's private view of the queue: the oldest actions, cut short where they're long,
+/// with their buttons and a count of the rest
+fn panel(campaign: &Campaign, locale: &Locale) -> (String, Vec<Component>) {
+    if campaign.pending_actions.is_empty() {
+        return (locale.text("approval.empty"), Vec::new())
+    }
+
+    let mut lines: Vec<String> = campaign.pending_actions.iter()
+        .take(MAX_PANEL_ACTIONS)
+        .map(|a| {
+            let description = a.describe();
+            match description.char_indices().nth(MAX_PANEL_ACTION_LENGTH - 1) {
+                Some((end, _)) => format!("{}…", &description[..end]),
+                None => description
+            }
+        })
+        .collect();
+    if campaign.pending_actions.len() > MAX_PANEL_ACTIONS {
+        lines.push(locale.format("approval.more", &[("count", &(campaign.pending_actions.len() - MAX_PANEL_ACTIONS))]));
+    }
+
+    let buttons: Vec<Component> = campaign.pending_actions.iter()
+        .take(MAX_PANEL_ACTIONS)
+        .map(|a| a.buttons(locale))
+        .collect();
+
+    (lines.join("\n\n"), buttons)
+}
+
+pub fn gm_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    match interaction.subcommand() {
+        Some("mode") => mode(state, interaction),
+        Some("queue") => queue(state, interaction),
+        Some("add") => add_gm(state, interaction),
+        Some("remove") => remove_gm(state, interaction),
+        Some("role") => gm_role(state, interaction),
+        _ => ResponseOject::ephemeral(i18n::locale(state, interaction).format("error.unknown_command", &[("command", &"gm")]))
+    }
+}
+
+/// switches who resolves actions. turning on human GM mode in a campaign without GMs makes
+/// the caller its GM
+fn mode(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let user_id = match interaction.user_id() {
+        Some(u) => u,
+        None => return ResponseOject::ephemeral(locale.text("error.no_user"))
+    };
+    let mode = match interaction.option_str("mode").and_then(CampaignMode::from_key) {
+        Some(m) => m,
+        None => return ResponseOject::ephemeral(locale.text("gm.mode_required"))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("gm.not_gm_mode"))
+        }
+
+        campaign.mode = mode;
+        match mode {
+            CampaignMode::HumanGm => {
+                if !campaign.has_gm() {
+                    campaign.gm_ids.push(user_id);
+                }
+                ResponseOject::new(locale.text("gm.mode_human"))
+            },
+            CampaignMode::Narrator => {
+                let waiting = campaign.pending_actions.len();
+                campaign.pending_actions.clear();
+                ResponseOject::new(locale.format("gm.mode_narrator", &[("count", &waiting)]))
+            }
+        }
+    })
+}
+
+fn add_gm(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let user = match interaction.option_snowflake("user") {
+        Some(u) => u,
+        None => return ResponseOject::ephemeral(locale.text("gm.user_required"))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("gm.not_gm_add"))
+        }
+        if campaign.gm_ids.contains(&user) {
+            return ResponseOject::ephemeral(locale.format("gm.already", &[("user", &user)]))
+        }
+
+        campaign.gm_ids.push(user);
+        ResponseOject::new(locale.format("gm.added", &[("user", &user), ("campaign", &campaign.name)]))
+    })
+}
+
+fn remove_gm(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let user = match interaction.option_snowflake("user") {
+        Some(u) => u,
+        None => return ResponseOject::ephemeral(locale.text("gm.user_required"))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.is_gm(interaction) {
+            return ResponseOject::ephemeral(locale.text("gm.not_gm_remove"))
+        }
+        if !campaign.gm_ids.contains(&user) {
+            return ResponseOject::ephemeral(locale.format("gm.not_a_gm", &[("user", &user)]))
+        }
+        if campaign.gm_ids.len() == 1 && campaign.gm_role.is_none() {
+            return ResponseOject::ephemeral(locale.text("gm.last"))
+        }
+
+        campaign.gm_ids.retain(|g| *g != user);
+        ResponseOject::new(locale.format("gm.removed", &[("user", &user), ("campaign", &campaign.name)]))
+    })
+}
+
+/// sets the role whose members are GMs, or clears it when no role is given
+fn gm_role(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let role = interaction.option_snowflake("role");
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("gm.not_gm_role"))
+        }
+
+        match role {
+            Some(r) => {
+                campaign.gm_role = Some(r);
+                ResponseOject::new(locale.format("gm.role_set", &[("role", &r), ("campaign", &campaign.name)]))
+            },
+            None if campaign.gm_ids.is_empty() => ResponseOject::ephemeral(locale.text("gm.role_needed")),
+            None => {
+                campaign.gm_role = None;
+                ResponseOject::new(locale.format("gm.role_cleared", &[("campaign", &campaign.name)]))
+            }
+        }
+    })
+}
+
+fn queue(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.is_gm(interaction) {
+            return ResponseOject::ephemeral(locale.text("gm.not_gm_queue"))
+        }
+
+        let (content, buttons) = panel(campaign, &locale);
+        let mut response = ResponseOject::ephemeral(content);
+        if let Some(message) = response.message_mut() {
+            message.components = Some(buttons);
+        }
+        response
+    })
+}
+
+/// handles the approve, edit and reject buttons of the GM panel
+pub fn approval_button(state: &AppState, interaction: &Interaction, action: &str, id: u32) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.is_gm(interaction) {
+            return ResponseOject::ephemeral(locale.text("gm.not_gm_resolve"))
+        }
+
+        let index = match campaign.pending_actions.iter().position(|a| a.id == id) {
+            Some(i) => i,
+            None => {
+                let (content, buttons) = panel(campaign, &locale);
+                return ResponseOject::update(content, buttons)
+            }
+        };
+
+        match action {
+            "approve" => {
+                if let Some(content) = approve_action(campaign, id) {
+                    state.discord.spawn_long_message(campaign.announce_channel(), &content);
+                }
+            },
+            "reject" => {
+                reject_action(campaign, id);
+                log::info!("GM rejected action {} in channel {}", id, campaign.channel_id);
+            },
+            "edit" => {
+                let pending = &campaign.pending_actions[index];
+                return ResponseOject::modal(&format!("approval:edited:{}", pending.id), &locale.format("approval.modal_title", &[("id", &pending.id), ("player", &pending.player)]), vec![
+                    Component::action_row(vec![Component::text_input(TEXT_INPUT_PARAGRAPH, &locale.text("approval.action"), "action", &pending.text, true)]),
+                    Component::action_row(vec![Component::text_input(TEXT_INPUT_PARAGRAPH, &locale.text("approval.narration"), "narration", pending.narration.as_deref().unwrap_or(""), false)])
+                ])
+            },
+            _ => return ResponseOject::ephemeral(locale.text("error.unable"))
+        }
+
+        let (content, buttons) = panel(campaign, &locale);
+        ResponseOject::update(content, buttons)
+    })
+}
+
+/// saves the GM's edits to an action and refreshes the panel
+pub fn approval_modal(state: &AppState, interaction: &Interaction, id: u32) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let text = interaction.text_input("action").map(|t| t.trim().to_string()).unwrap_or_default();
+    let narration = interaction.text_input("narration")
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty());
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.is_gm(interaction) {
+            return ResponseOject::ephemeral(locale.text("gm.not_gm_edit"))
+        }
+
+        edit_action(campaign, id, &text, narration.clone());
+
+        let (content, buttons) = panel(campaign, &locale);
+        ResponseOject::update(content, buttons)
+    })
+}
+
+/// takes an approved action out of the queue and applies it to the campaign. returns what
+/// to post to the channel
+fn approve_action(campaign: &mut Campaign, id: u32) -> Option<String> {
+    let index = campaign.pending_actions.iter().position(|a| a.id == id)?;
+    let pending = campaign.pending_actions.remove(index);
+    campaign.record(HistoryKind::Action, Some(pending.player_id), &pending.text);
+
+    let mut content = format!("**{}**: {}", pending.player, pending.text);
+    if let Some(narration) = &pending.narration {
+        campaign.record(HistoryKind::Narration, None, narration);
+        content.push_str(&format!("\n\n{}", narration));
+    }
+
+    Some(content)
+}
+
+/// takes a rejected action out of the queue without applying it
+fn reject_action(campaign: &mut Campaign, id: u32) -> Option<PendingAction> {
+    let index = campaign.pending_actions.iter().position(|a| a.id == id)?;
+    Some(campaign.pending_actions.remove(index))
+}
+
+/// saves the GM's edits to a queued action. an empty text keeps the player's
+fn edit_action(campaign: &mut Campaign, id: u32, text: &str, narration: Option<String>) {
+    if let Some(pending) = campaign.pending_actions.iter_mut().find(|a| a.id == id) {
+        if !text.is_empty() {
+            pending.text = text.to_string();
+        }
+        pending.narration = narration;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ruleset::RulesetId;
+
+    const AYLA: Snowflake = Snowflake::new(1);
+
+    fn campaign() -> Campaign {
+        let mut campaign = Campaign::new(Snowflake::new(10), RulesetId::D20);
+        campaign.mode = CampaignMode::HumanGm;
+        queue_action(&mut campaign, AYLA, "Ayla", "pick the lock");
+        queue_action(&mut campaign, AYLA, "Ayla", "climb the wall");
+        campaign
+    }
+
+    #[test]
+    fn approving_applies_the_action_with_its_narration() {
+        let mut campaign = campaign();
+        edit_action(&mut campaign, 0, "", Some(String::from("the lock clicks open")));
+
+        let content = approve_action(&mut campaign, 0).unwrap();
+        assert_eq!(content, "**Ayla**: pick the lock\n\nthe lock clicks open");
+        assert_eq!(campaign.pending_actions.len(), 1);
+        assert_eq!(campaign.pending_actions[0].id, 1);
+
+        let kinds: Vec<HistoryKind> = campaign.history.iter().map(|h| h.kind).collect();
+        assert_eq!(kinds, vec![HistoryKind::Action, HistoryKind::Narration]);
+        assert_eq!(campaign.history[0].player_id, Some(AYLA));
+        assert!(approve_action(&mut campaign, 0).is_none());
+    }
+
+    #[test]
+    fn rejecting_drops_the_action_without_a_trace() {
+        let mut campaign = campaign();
+
+        assert_eq!(reject_action(&mut campaign, 1).unwrap().text, "climb the wall");
+        assert!(reject_action(&mut campaign, 1).is_none());
+        assert_eq!(campaign.pending_actions.len(), 1);
+        assert!(campaign.history.is_empty());
+    }
+
+    #[test]
+    fn editing_keeps_the_action_queued() {
+        let mut campaign = campaign();
+
+        edit_action(&mut campaign, 1, "climb the north wall", Some(String::from("it's slick with rain")));
+        edit_action(&mut campaign, 0, "", None);
+        edit_action(&mut campaign, 7, "dance", None);
+
+        let texts: Vec<&str> = campaign.pending_actions.iter().map(|a| a.text.as_str()).collect();
+        assert_eq!(texts, vec!["pick the lock", "climb the north wall"]);
+        assert_eq!(campaign.pending_actions[1].narration.as_deref(), Some("it's slick with rain"));
+        assert!(campaign.history.is_empty());
+
+        edit_action(&mut campaign, 1, "", None);
+        assert_eq!(campaign.pending_actions[1].text, "climb the north wall");
+        assert!(campaign.pending_actions[1].narration.is_none());
+    }
+
+    #[test]
+    fn the_panel_offers_buttons_for_the_oldest_actions() {
+        let mut campaign = campaign();
+        let locale = Locale::new(None, None);
+        for i in 0..5 {
+            queue_action(&mut campaign, AYLA, "Ayla", &format!("wait {}", i));
+        }
+
+        let (content, buttons) = panel(&campaign, &locale);
+        assert_eq!(buttons.len(), MAX_PANEL_ACTIONS);
+        assert!(content.starts_with("#0 **Ayla**: pick the lock"));
+        assert!(!content.contains("wait 4"));
+        assert!(content.ends_with(&locale.format("approval.more", &[("count", &(campaign.pending_actions.len() - MAX_PANEL_ACTIONS))])));
+
+        for action in campaign.pending_actions.iter_mut() {
+            action.text = "ä".repeat(4000);
+        }
+        let (content, _) = panel(&campaign, &locale);
+        assert!(content.chars().count() <= MAX_MESSAGE_LENGTH);
+
+        campaign.pending_actions.clear();
+        let (content, buttons) = panel(&campaign, &locale);
+        assert_eq!(content, locale.text("approval.empty"));
+        assert!(buttons.is_empty());
+    }
+}
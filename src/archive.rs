@@ -0,0 +1,312 @@
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use super::campaign::{self, Campaign, Campaigns, with_campaign};
+use super::discord_data_structs::{Interaction, MessageObject, ResponseOject};
+use super::history;
+use super::i18n::{self, Locale};
+use super::snowflake::Snowflake;
+use super::thread;
+use super::AppState;
+
+/// version written into new archives. bump it and add a step to `MIGRATIONS` whenever a
+/// change to `Campaign` can't be read by falling back to defaults
+pub const ARCHIVE_VERSION: u64 = 2;
+
+/// largest archive accepted for import
+pub const MAX_ARCHIVE_BYTES: u64 = 8 * 1024 * 1024;
+
+type Migration = fn(&mut Value) -> Result<(), String>;
+
+/// `MIGRATIONS[n]` upgrades an archive from version n + 1 to n + 2
+const MIGRATIONS: &[Migration] = &[gm_ids_from_gm_id];
+
+/// version 2 lets a campaign have several GMs, the single `gm_id` becomes the list `gm_ids`
+fn gm_ids_from_gm_id(value: &mut Value) -> Result<(), String> {
+    let campaign = value.get_mut("campaign")
+        .and_then(|c| c.as_object_mut())
+        .ok_or(String::from("there is no campaign in it"))?;
+
+    let gm_ids = match campaign.remove("gm_id") {
+        Some(Value::String(id)) => vec![Value::String(id)],
+        Some(Value::Null) | None => Vec::new(),
+        Some(other) => return Err(format!("gm_id {} is not a user id", other))
+    };
+    campaign.insert(String::from("gm_ids"), Value::Array(gm_ids));
+    Ok(())
+}
+
+/// a whole campaign as written to a file: players, inventories, world notes, history and settings
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CampaignArchive {
+    pub version: u64,
+
+    /// unix timestamp in seconds
+    pub exported: u64,
+    pub campaign: Campaign,
+}
+
+pub fn write_archive(campaign: &Campaign, locale: &Locale) -> Result<String, String> {
+    let archive = CampaignArchive {
+        version: ARCHIVE_VERSION,
+        exported: history::now(),
+        campaign: campaign.clone()
+    };
+
+    serde_json::to_string_pretty(&archive).map_err(|e| {
+        log::error!("could not serialize campaign {}\n{}", campaign.channel_id, e);
+        locale.text("archive.write_failed")
+    })
+}
+
+/// reads an archive of any version this bot knows, upgrading older ones first
+pub fn read_archive(content: &[u8], locale: &Locale) -> Result<Campaign, String> {
+    let mut value: Value = serde_json::from_slice(content)
+        .map_err(|e| locale.format("archive.not_json", &[("error", &e)]))?;
+
+    let version = match value.get("version").and_then(|v| v.as_u64()) {
+        Some(v) if v >= 1 => v,
+        _ => return Err(locale.text("archive.not_archive"))
+    };
+    if version > ARCHIVE_VERSION {
+        return Err(locale.format("archive.too_new", &[("version", &version), ("supported", &ARCHIVE_VERSION)]))
+    }
+
+    migrate(&mut value, version, locale)?;
+
+    let archive: CampaignArchive = serde_json::from_value(value)
+        .map_err(|e| locale.format("archive.damaged", &[("error", &e)]))?;
+    Ok(archive.campaign)
+}
+
+fn migrate(value: &mut Value, from: u64, locale: &Locale) -> Result<(), String> {
+    for (step, migration) in MIGRATIONS.iter().enumerate().skip(from as usize - 1) {
+        migration(value).map_err(|e| locale.format("archive.upgrade_failed", &[("version", &(step + 1)), ("error", &e)]))?;
+        value["version"] = Value::from(step as u64 + 2);
+    }
+    Ok(())
+}
+
+/// adds an archived campaign to a server under a new id and makes it the one `channel_id`
+/// plays. whatever the channel played before stays available through /campaign switch
+pub fn import_campaign(campaigns: &mut Campaigns, mut campaign: Campaign, channel_id: Snowflake, guild_id: Option<Snowflake>, locale: &Locale) -> String {
+    campaign.channel_id = channel_id;
+    campaign.guild_id = guild_id;
+
+    // threads belong to the channel the archive came from
+    campaign.thread_id = None;
+    campaign.sidequests.clear();
+    let summary = locale.format("archive.imported", &[
+        ("name", &campaign.name),
+        ("players", &campaign.players.len()),
+        ("entries", &campaign.history.len())
+    ]);
+
+    let id = campaigns.insert(campaign);
+    log::info!("imported campaign {} into channel {}", id, channel_id);
+    summary
+}
+
+pub fn archive_command(state: &Arc<AppState>, interaction: &Interaction) -> ResponseOject {
+    match interaction.subcommand() {
+        Some("export") => export(state, interaction),
+        Some("import") => import(state, interaction),
+        _ => ResponseOject::ephemeral(i18n::locale(state, interaction).format("error.unknown_command", &[("command", &"campaign")]))
+    }
+}
+
+/// answers with a deferred response and uploads the archive into it. the archive holds
+/// lore secrets, so it is only for the GM's eyes
+fn export(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let mut archive: Option<String> = None;
+    let mut filename = String::new();
+    let response = with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("archive.not_gm"))
+        }
+
+        match write_archive(campaign, &locale) {
+            Ok(a) => {
+                archive = Some(a);
+                filename = format!("campaign-{}.json", campaign.id);
+                ResponseOject::deferred_ephemeral()
+            },
+            Err(e) => ResponseOject::ephemeral(e)
+        }
+    });
+
+    let archive = match archive {
+        Some(a) => a,
+        None => return response
+    };
+
+    let discord = state.discord.clone();
+    let application_id = interaction.application_id;
+    let token = interaction.token.clone();
+
+    tokio::spawn(async move {
+        let message = MessageObject {
+            content: locale.text("archive.exported"),
+            ..Default::default()
+        };
+
+        if let Err(e) = discord.edit_original_response_with_file(application_id, &token, message, &filename, archive.into_bytes()).await {
+            log::error!("could not upload campaign archive\n{}", e);
+        }
+    });
+
+    response
+}
+
+/// downloads the uploaded archive and loads it into this channel or the chosen one
+fn import(state: &Arc<AppState>, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let attachment = match interaction.option_attachment("file") {
+        Some(a) => a.clone(),
+        None => return ResponseOject::ephemeral(locale.text("archive.file_required"))
+    };
+    if attachment.size > MAX_ARCHIVE_BYTES {
+        return ResponseOject::ephemeral(locale.format("archive.too_large", &[("size", &(MAX_ARCHIVE_BYTES / 1024 / 1024))]))
+    }
+
+    let channel_id = match interaction.option_snowflake("channel").or(interaction.channel_id) {
+        Some(c) => c,
+        None => return ResponseOject::ephemeral(locale.text("error.no_channel_option"))
+    };
+    let guild_id = interaction.guild_id;
+    let user_id = interaction.user_id();
+
+    let config = match state.guild_configs.lock() {
+        Ok(lock) => lock.get(guild_id),
+        Err(e) => {
+            log::error!("unable to obtain lock for app state\n{}", e);
+            return ResponseOject::ephemeral(locale.text("error.retry"))
+        }
+    };
+
+    // threads count as the channel they were started in, as with /init
+    let parent = match interaction.option_channel("channel") {
+        Some(c) if c.r#type.is_thread() => c.parent_id.unwrap_or(c.id),
+        Some(c) => c.id,
+        None => thread::thread_parent(interaction).unwrap_or(channel_id)
+    };
+    if !config.allows_channel(parent) {
+        let channels: Vec<String> = config.allowed_channels.iter().map(|c| format!("<#{}>", c)).collect();
+        return ResponseOject::ephemeral(locale.format("init.channel_not_allowed", &[("channels", &channels.join(", "))]))
+    }
+
+    // importing replaces what the channel plays, so it takes the same rights as /init there
+    let allowed = match state.campaigns.lock() {
+        Ok(lock) => campaign::can_take_channel(interaction, config.gm_role, lock.in_channel(channel_id)),
+        Err(e) => {
+            log::error!("unable to obtain lock for app state\n{}", e);
+            return ResponseOject::ephemeral(locale.text("error.retry"))
+        }
+    };
+    if !allowed {
+        return ResponseOject::ephemeral(locale.format("archive.not_allowed", &[("channel", &channel_id)]))
+    }
+
+    let state = state.clone();
+    let application_id = interaction.application_id;
+    let token = interaction.token.clone();
+
+    tokio::spawn(async move {
+        let result = match state.discord.download_attachment(&attachment.url).await {
+            Ok(content) => read_archive(&content, &locale),
+            Err(e) => {
+                log::error!("could not download campaign archive {}\n{}", attachment.filename, e);
+                Err(locale.text("archive.download_failed"))
+            }
+        };
+
+        // whoever imports an archive without GMs runs it, it is never left to everyone
+        let result = result.map(|mut campaign| {
+            if !campaign.has_gm() {
+                campaign.gm_ids.extend(user_id);
+            }
+            campaign
+        });
+
+        let result = result.and_then(|campaign| match state.campaigns.lock() {
+            Ok(mut lock) => Ok(import_campaign(&mut lock, campaign, channel_id, guild_id, &locale)),
+            Err(e) => {
+                log::error!("unable to obtain lock for app state\n{}", e);
+                Err(locale.text("error.unable"))
+            }
+        });
+
+        let content = match result {
+            Ok(summary) => locale.format("archive.imported_into", &[("summary", &summary), ("channel", &channel_id)]),
+            Err(e) => e
+        };
+        let message = MessageObject { content, ..Default::default() };
+        if let Err(e) = state.discord.edit_original_response(application_id, &token, &message).await {
+            log::error!("could not answer campaign import\n{}", e);
+        }
+    });
+
+    ResponseOject::deferred_ephemeral()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snowflake::Snowflake;
+    use crate::player::Player;
+    use crate::ruleset::RulesetId;
+
+    const OLD: Snowflake = Snowflake::new(1183456098718179410);
+    const NEW: Snowflake = Snowflake::new(1183456098718179411);
+    const GUILD: Snowflake = Snowflake::new(1183456098227453962);
+
+    #[test]
+    fn archives_round_trip_into_a_new_channel() {
+        let mut campaign = Campaign::new(OLD, RulesetId::Pbta);
+        let player = Player::new(Snowflake::new(1), "Ayla", "", campaign.ruleset.get());
+        campaign.players.push(player);
+        campaign.world_facts.push(String::from("the river runs red"));
+
+        let locale = Locale::new(None, None);
+        let archive = write_archive(&campaign, &locale).unwrap();
+        let loaded = read_archive(archive.as_bytes(), &locale).unwrap();
+
+        let mut campaigns = Campaigns::new();
+        let first = campaigns.insert(Campaign::new(NEW, RulesetId::D20));
+        import_campaign(&mut campaigns, loaded, NEW, Some(GUILD), &locale);
+
+        let imported = campaigns.in_channel(NEW).unwrap();
+        assert_ne!(imported.id, first);
+        assert_eq!(imported.guild_id, Some(GUILD));
+        assert_eq!(imported.ruleset, RulesetId::Pbta);
+        assert_eq!(imported.players[0].name, "Ayla");
+        assert_eq!(imported.world_facts, vec![String::from("the river runs red")]);
+        assert!(campaigns.get(first).is_some());
+    }
+
+    #[test]
+    fn missing_fields_default_and_newer_versions_are_refused() {
+        let locale = Locale::new(None, None);
+        let old = serde_json::json!({
+            "version": 1,
+            "exported": 0,
+            "campaign": { "active": true, "channel_id": OLD, "ruleset": "d20" }
+        });
+        let campaign = read_archive(old.to_string().as_bytes(), &locale).unwrap();
+        assert!(campaign.active);
+        assert!(campaign.quests.is_empty());
+
+        let first_version = serde_json::json!({
+            "version": 1,
+            "exported": 0,
+            "campaign": { "channel_id": OLD, "gm_id": "42" }
+        });
+        let campaign = read_archive(first_version.to_string().as_bytes(), &locale).unwrap();
+        assert_eq!(campaign.gm_ids, vec![Snowflake::new(42)]);
+
+        let newer = serde_json::json!({ "version": ARCHIVE_VERSION + 1, "exported": 0, "campaign": {} });
+        assert!(read_archive(newer.to_string().as_bytes(), &locale).is_err());
+        assert!(read_archive(b"{\"players\": []}", &locale).is_err());
+    }
+}
@@ -1,19 +1,559 @@
-
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
 use super::player::Player;
-#[derive(Clone, Debug)]
+use super::shop::Shop;
+use super::trade::Trade;
+use super::history::{HistoryEntry, HistoryKind};
+use super::ruleset::RulesetId;
+use super::context::StorySummary;
+use super::combat::Combat;
+use super::approval::{CampaignMode, PendingAction};
+use super::round::Round;
+use super::session::Session;
+use super::lore::LoreEntry;
+use super::quest::Quest;
+use super::thread::Sidequest;
+use super::archive;
+use super::thread;
+use super::permissions::Permissions;
+use super::i18n;
+use super::snowflake::Snowflake;
+use super::discord_data_structs::{
+    AutocompleteResponse,
+    CommandOptionChoice,
+    Interaction,
+    ResponseOject,
+    MAX_AUTOCOMPLETE_CHOICES
+};
+use super::AppState;
+
+/// everything a campaign is made of. fields missing from an archive fall back to their
+/// defaults, so fields can be added without bumping the archive version
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Campaign {
+    /// assigned by `Campaigns` when the campaign is added, unique for this bot
+    pub id: u32,
+    pub name: String,
+
+    /// server the campaign belongs to, `None` in direct messages
+    pub guild_id: Option<Snowflake>,
     pub active: bool,
     pub players: Vec<Player>,
-    pub channel_id: String,
-    
+
+    /// channel the campaign was last played in, where its announcements go
+    pub channel_id: Snowflake,
+    pub ruleset: RulesetId,
+    pub shops: Vec<Shop>,
+    pub trades: Vec<Trade>,
+    pub next_trade_id: u32,
+    pub history: Vec<HistoryEntry>,
+
+    /// facts the GM wants the narrator to always keep in mind
+    pub world_facts: Vec<String>,
+
+    /// locations, NPCs, factions and lore of the campaign's world
+    pub lore: Vec<LoreEntry>,
+
+    /// rolling summaries of older turns, the last one is current
+    pub summaries: Vec<StorySummary>,
+
+    /// initiative order while a fight is going on
+    pub combat: Option<Combat>,
+
+    pub mode: CampaignMode,
+
+    /// whether actions are narrated, when a narrator is configured at all
+    pub narrator: bool,
+
+    /// most players that can join, `None` for no limit
+    pub max_players: Option<u32>,
+
+    /// users running the campaign. only they start and end it, spawn enemies, award XP
+    /// and edit its state, and in human GM mode they approve actions
+    pub gm_ids: Vec<Snowflake>,
+
+    /// members with this role are GMs as well
+    pub gm_role: Option<Snowflake>,
+
+    /// actions waiting for the GM, oldest first
+    pub pending_actions: Vec<PendingAction>,
+    pub next_action_id: u32,
+
+    /// the open play-by-post round, if any
+    pub round: Option<Round>,
+    pub round_number: u32,
+
+    /// scheduled sessions, past ones included
+    pub sessions: Vec<Session>,
+    pub next_session_id: u32,
+
+    /// every quest ever offered, finished ones included
+    pub quests: Vec<Quest>,
+    pub next_quest_id: u32,
+
+    /// thread the campaign is played in, started in `channel_id` by /init
+    pub thread_id: Option<Snowflake>,
+
+    /// private threads for part of the party, sharing the campaign's state and history
+    pub sidequests: Vec<Sidequest>,
+}
+
+impl Default for Campaign {
+    fn default() -> Campaign {
+        Campaign::new(Snowflake::default(), RulesetId::default())
+    }
 }
 
 impl Campaign {
-    pub fn new(channel_id: &str) -> Campaign {
+    pub fn new(channel_id: Snowflake, ruleset: RulesetId) -> Campaign {
         Campaign { 
+            id: 0,
+            name: String::new(),
+            guild_id: None,
             active: false, 
             players: Vec::new(), 
-            channel_id: channel_id.to_string() 
+            channel_id,
+            ruleset,
+            shops: Vec::new(),
+            trades: Vec::new(),
+            next_trade_id: 0,
+            history: Vec::new(),
+            world_facts: Vec::new(),
+            lore: Vec::new(),
+            summaries: Vec::new(),
+            combat: None,
+            mode: CampaignMode::default(),
+            narrator: true,
+            max_players: None,
+            gm_ids: Vec::new(),
+            gm_role: None,
+            pending_actions: Vec::new(),
+            next_action_id: 0,
+            round: None,
+            round_number: 0,
+            sessions: Vec::new(),
+            next_session_id: 0,
+            quests: Vec::new(),
+            next_quest_id: 0,
+            thread_id: None,
+            sidequests: Vec::new()
         }
     }
-}
\ No newline at end of file
+
+    /// where announcements such as round results and reminders go: the campaign's thread
+    /// if it has one, otherwise its channel
+    pub fn announce_channel(&self) -> Snowflake {
+        self.thread_id.unwrap_or(self.channel_id)
+    }
+
+    pub fn player(&self, id: Snowflake) -> Option<&Player> {
+        self.players.iter().find(|p| p.id == id)
+    }
+
+    pub fn player_mut(&mut self, id: Snowflake) -> Option<&mut Player> {
+        self.players.iter_mut().find(|p| p.id == id)
+    }
+
+    /// takes a user out of everything the campaign holds for them, such as when they leave
+    /// the server. their history stays. the last GM stays GM when there is no GM role, so
+    /// the campaign is never left without one. returns the player they played, if any
+    pub fn remove_player(&mut self, id: Snowflake) -> Option<Player> {
+        if self.gm_role.is_some() || self.gm_ids.iter().any(|g| *g != id) {
+            self.gm_ids.retain(|g| *g != id);
+        }
+        let index = self.players.iter().position(|p| p.id == id)?;
+
+        self.trades.retain(|t| t.from != id && t.to != id);
+        self.pending_actions.retain(|a| a.player_id != id);
+        if let Some(round) = self.round.as_mut() {
+            round.submissions.retain(|s| s.player_id != id);
+        }
+        if let Some(combat) = self.combat.as_mut() {
+            combat.order.retain(|c| c.player_id != Some(id));
+        }
+        for session in self.sessions.iter_mut() {
+            session.rsvps.retain(|(u, _)| *u != id);
+        }
+        for quest in self.quests.iter_mut() {
+            quest.participants.retain(|p| *p != id);
+        }
+        for sidequest in self.sidequests.iter_mut() {
+            sidequest.players.retain(|p| *p != id);
+        }
+
+        Some(self.players.remove(index))
+    }
+
+    pub fn record(&mut self, kind: HistoryKind, player_id: Option<Snowflake>, text: &str) {
+        log::debug!("campaign {} history: {}", self.channel_id, text);
+        self.history.push(HistoryEntry::new(kind, player_id, text));
+    }
+
+    /// whether the user behind the interaction is a GM of the campaign, by id, by the GM role
+    /// or because they manage the server
+    pub fn is_gm(&self, interaction: &Interaction) -> bool {
+        if let Some(user_id) = interaction.user_id()
+            && self.gm_ids.contains(&user_id) {
+            return true
+        }
+
+        if let Some(role) = &self.gm_role
+            && interaction.member_roles().contains(role) {
+            return true
+        }
+
+        interaction.member_permissions().contains(Permissions::MANAGE_GUILD)
+    }
+
+    pub fn has_gm(&self) -> bool {
+        !self.gm_ids.is_empty() || self.gm_role.is_some()
+    }
+
+    /// only GMs manage a campaign. one without GMs, such as an old archive, falls to the
+    /// members who manage the server, or to whoever plays it in direct messages
+    pub fn can_manage(&self, interaction: &Interaction) -> bool {
+        self.is_gm(interaction) || (!self.has_gm() && self.guild_id.is_none())
+    }
+
+    /// actions, narrations and their resolved mechanics, the parts of the history the narrator sees
+    pub fn turns(&self) -> Vec<&HistoryEntry> {
+        self.history.iter()
+            .filter(|h| matches!(h.kind, HistoryKind::Action | HistoryKind::Narration | HistoryKind::Resolution | HistoryKind::Roll | HistoryKind::Quest))
+            .collect()
+    }
+
+    pub fn shop(&self, name: &str) -> Option<&Shop> {
+        self.shops.iter().find(|s| s.name.eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn shop_mut(&mut self, name: &str) -> Option<&mut Shop> {
+        self.shops.iter_mut().find(|s| s.name.eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+/// every campaign of the bot by id, and which one each channel is playing
+#[derive(Debug, Default)]
+pub struct Campaigns {
+    by_id: HashMap<u32, Campaign>,
+
+    /// campaign id by channel id
+    playing: HashMap<Snowflake, u32>,
+    next_id: u32,
+}
+
+impl Campaigns {
+    pub fn new() -> Campaigns {
+        Campaigns::default()
+    }
+
+    /// gives the campaign a fresh id and makes it the one its channel plays
+    pub fn insert(&mut self, mut campaign: Campaign) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        campaign.id = id;
+        if campaign.name.trim().is_empty() {
+            campaign.name = format!("campaign #{}", id);
+        }
+        self.playing.insert(campaign.channel_id, id);
+        self.by_id.insert(id, campaign);
+        id
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Campaign> {
+        self.by_id.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut Campaign> {
+        self.by_id.get_mut(&id)
+    }
+
+    /// the campaign a channel is playing
+    pub fn in_channel(&self, channel_id: Snowflake) -> Option<&Campaign> {
+        self.playing.get(&channel_id).and_then(|id| self.by_id.get(id))
+    }
+
+    pub fn in_channel_mut(&mut self, channel_id: Snowflake) -> Option<&mut Campaign> {
+        self.playing.get(&channel_id).and_then(|id| self.by_id.get_mut(id))
+    }
+
+    /// campaigns of a server, or of direct messages for `None`, in the order they were created
+    pub fn in_guild(&self, guild_id: Option<Snowflake>) -> Vec<&Campaign> {
+        let mut campaigns: Vec<&Campaign> = self.by_id.values()
+            .filter(|c| c.guild_id == guild_id)
+            .collect();
+        campaigns.sort_by_key(|c| c.id);
+        campaigns
+    }
+
+    /// plays a campaign in one more channel without moving it, such as one of its threads
+    pub fn link(&mut self, channel_id: Snowflake, id: u32) {
+        if self.by_id.contains_key(&id) {
+            self.playing.insert(channel_id, id);
+        }
+    }
+
+    /// makes a campaign of the same server the one `channel_id` plays. it moves out of
+    /// the channel it was played in before, its threads stay linked
+    pub fn switch(&mut self, channel_id: Snowflake, guild_id: Option<Snowflake>, id: u32) -> Option<&Campaign> {
+        let campaign = match self.by_id.get_mut(&id) {
+            Some(c) if c.guild_id == guild_id => c,
+            _ => return None
+        };
+
+        if self.playing.get(&campaign.channel_id) == Some(&id) {
+            self.playing.remove(&campaign.channel_id);
+        }
+        self.playing.insert(channel_id, id);
+        campaign.channel_id = channel_id;
+        Some(campaign)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Campaign> {
+        self.by_id.values()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Campaign> {
+        self.by_id.values_mut()
+    }
+}
+
+/// locks the campaigns and runs `f` against the campaign the interaction's channel plays.
+/// answers with an ephemeral message if there is none
+pub fn with_campaign<F>(state: &AppState, interaction: &Interaction, f: F) -> ResponseOject
+where
+    F: FnOnce(&mut Campaign) -> ResponseOject
+{
+    let locale = i18n::locale(state, interaction);
+    let channel_id = match interaction.channel_id {
+        Some(c) => c,
+        None => return ResponseOject::ephemeral(locale.text("error.no_channel"))
+    };
+
+    let mut lock = match state.campaigns.lock() {
+        Ok(l) => l,
+        Err(e) => {
+            log::error!("unable to obtain lock for app state\n{}", e);
+            return ResponseOject::ephemeral(locale.text("error.retry"))
+        }
+    };
+
+    match lock.in_channel_mut(channel_id) {
+        Some(campaign) => f(campaign),
+        None => ResponseOject::ephemeral(locale.text("campaign.none"))
+    }
+}
+
+/// whether the user may change which campaign a channel plays: the GM of the one it plays
+/// now, a member with the server's GM role or someone who manages channels. outside of
+/// servers anyone may
+pub fn can_take_channel(interaction: &Interaction, gm_role: Option<Snowflake>, current: Option<&Campaign>) -> bool {
+    interaction.guild_id.is_none()
+        || gm_role.is_some_and(|r| interaction.member_roles().contains(&r))
+        || interaction.member_permissions().contains(Permissions::MANAGE_CHANNELS)
+        || current.is_some_and(|c| c.is_gm(interaction))
+}
+
+/// ruleset of the campaign in the interaction's channel, or the default if there is none
+pub fn campaign_ruleset(campaigns: &Mutex<Campaigns>, interaction: &Interaction) -> RulesetId {
+    match campaigns.lock() {
+        Ok(lock) => interaction.channel_id
+            .and_then(|c| lock.in_channel(c))
+            .map(|c| c.ruleset)
+            .unwrap_or_default(),
+        Err(e) => {
+            log::error!("unable to obtain lock for app state\n{}", e);
+            RulesetId::default()
+        }
+    }
+}
+
+pub fn campaign_command(state: &Arc<AppState>, interaction: &Interaction) -> ResponseOject {
+    match interaction.subcommand() {
+        Some("list") => list(state, interaction),
+        Some("switch") => switch(state, interaction),
+        Some("export") | Some("import") => archive::archive_command(state, interaction),
+        _ => ResponseOject::ephemeral(i18n::locale(state, interaction).format("error.unknown_command", &[("command", &"campaign")]))
+    }
+}
+
+fn list(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    match state.campaigns.lock() {
+        Ok(lock) => {
+            let playing = interaction.channel_id.and_then(|c| lock.in_channel(c)).map(|c| c.id);
+            let lines: Vec<String> = lock.in_guild(interaction.guild_id).iter()
+                .map(|c| {
+                    let mut line = locale.format("campaign.list_entry", &[("id", &c.id), ("name", &c.name), ("ruleset", &c.ruleset.get().name()), ("players", &c.players.len()), ("channel", &c.channel_id)]);
+                    if Some(c.id) == playing {
+                        line.push_str(&locale.text("campaign.list_playing"));
+                    }
+                    line
+                })
+                .collect();
+
+            if lines.is_empty() {
+                ResponseOject::ephemeral(locale.text("campaign.list_empty"))
+            } else {
+                ResponseOject::ephemeral(lines.join("\n"))
+            }
+        },
+        Err(e) => {
+            log::error!("unable to obtain lock for app state\n{}", e);
+            ResponseOject::ephemeral(locale.text("error.retry"))
+        }
+    }
+}
+
+/// picks which of the server's campaigns this channel plays
+fn switch(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let (channel_id, id) = match (interaction.channel_id, interaction.option_i64("campaign")) {
+        (Some(c), Some(i)) if i >= 0 => (c, i as u32),
+        (None, _) => return ResponseOject::ephemeral(locale.text("error.no_channel")),
+        _ => return ResponseOject::ephemeral(locale.text("campaign.required"))
+    };
+
+    let config = match state.guild_configs.lock() {
+        Ok(lock) => lock.get(interaction.guild_id),
+        Err(e) => {
+            log::error!("unable to obtain lock for app state\n{}", e);
+            return ResponseOject::ephemeral(locale.text("error.retry"))
+        }
+    };
+    if !config.allows_channel(thread::thread_parent(interaction).unwrap_or(channel_id)) {
+        let channels: Vec<String> = config.allowed_channels.iter().map(|c| format!("<#{}>", c)).collect();
+        return ResponseOject::ephemeral(locale.format("init.channel_not_allowed", &[("channels", &channels.join(", "))]))
+    }
+
+    let mut lock = match state.campaigns.lock() {
+        Ok(l) => l,
+        Err(e) => {
+            log::error!("unable to obtain lock for app state\n{}", e);
+            return ResponseOject::ephemeral(locale.text("error.retry"))
+        }
+    };
+
+    if let Some(current) = lock.in_channel(channel_id) && current.id != id && !current.can_manage(interaction) {
+        return ResponseOject::ephemeral(locale.text("campaign.switch_not_gm"))
+    }
+    // switching takes the campaign out of the channel it plays in, which is up to its GMs
+    if let Some(target) = lock.get(id) && target.guild_id == interaction.guild_id && !target.can_manage(interaction) {
+        return ResponseOject::ephemeral(locale.format("campaign.switch_target_not_gm", &[("id", &id)]))
+    }
+
+    match lock.switch(channel_id, interaction.guild_id, id) {
+        Some(campaign) => ResponseOject::new(locale.format("campaign.switched", &[("name", &campaign.name), ("players", &campaign.players.len())])),
+        None => ResponseOject::ephemeral(locale.format("campaign.unknown", &[("id", &id)]))
+    }
+}
+
+/// suggests the campaigns of the interaction's server
+pub fn campaign_autocomplete(state: &AppState, interaction: &Interaction) -> AutocompleteResponse {
+    let partial = interaction.focused_option()
+        .and_then(|o| o.value.as_ref())
+        .map(|v| v.as_str().map(|s| s.to_string()).unwrap_or(v.to_string()))
+        .unwrap_or_default()
+        .to_lowercase();
+
+    let choices: Vec<CommandOptionChoice> = match state.campaigns.lock() {
+        Ok(lock) => lock.in_guild(interaction.guild_id).iter()
+            .filter(|c| c.name.to_lowercase().contains(&partial) || c.id.to_string() == partial)
+            .take(MAX_AUTOCOMPLETE_CHOICES)
+            .map(|c| i18n::choice(serde_json::Value::from(c.id), |_| format!("#{} {}", c.id, c.name)))
+            .collect(),
+        Err(e) => {
+            log::error!("unable to obtain lock for app state\n{}", e);
+            Vec::new()
+        }
+    };
+
+    AutocompleteResponse::new(choices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUILD: Snowflake = Snowflake::new(1183456098227453962);
+    const ELSEWHERE: Snowflake = Snowflake::new(1183456098227453963);
+    const A: Snowflake = Snowflake::new(1183456098718179410);
+    const B: Snowflake = Snowflake::new(1183456098718179411);
+    const C: Snowflake = Snowflake::new(1183456098718179412);
+    const THREAD: Snowflake = Snowflake::new(1183456098718179413);
+
+    fn campaign(channel_id: Snowflake, guild_id: Snowflake) -> Campaign {
+        let mut campaign = Campaign::new(channel_id, RulesetId::D20);
+        campaign.guild_id = Some(guild_id);
+        campaign
+    }
+
+    #[test]
+    fn channels_switch_between_campaigns_of_their_server() {
+        let mut campaigns = Campaigns::new();
+        let first = campaigns.insert(campaign(A, GUILD));
+        let second = campaigns.insert(campaign(A, GUILD));
+        let other = campaigns.insert(campaign(C, ELSEWHERE));
+
+        assert_eq!(campaigns.in_channel(A).unwrap().id, second);
+        assert_eq!(campaigns.in_guild(Some(GUILD)).len(), 2);
+
+        campaigns.switch(A, Some(GUILD), first).unwrap();
+        assert_eq!(campaigns.in_channel(A).unwrap().id, first);
+
+        // a campaign only plays in one channel at a time, its threads come along
+        campaigns.link(THREAD, first);
+        campaigns.switch(B, Some(GUILD), first).unwrap();
+        assert!(campaigns.in_channel(A).is_none());
+        assert_eq!(campaigns.in_channel(THREAD).unwrap().id, first);
+        assert_eq!(campaigns.get(first).unwrap().channel_id, B);
+
+        assert!(campaigns.switch(A, Some(GUILD), other).is_none());
+    }
+
+    #[test]
+    fn players_leaving_are_removed_everywhere() {
+        let mut campaign = campaign(A, GUILD);
+        campaign.players.push(Player::new(B, "Ayla", "Fighter", RulesetId::D20.get()));
+        campaign.gm_ids.push(C);
+        campaign.combat = Some(Combat::start(&campaign, &[String::from("goblin")]));
+        campaign.record(HistoryKind::Action, Some(B), "open the door");
+
+        // the only GM leaving stays GM, with a second one they can go
+        assert!(campaign.remove_player(C).is_none());
+        assert_eq!(campaign.gm_ids, vec![C]);
+        campaign.gm_ids.push(A);
+        campaign.remove_player(C);
+        assert_eq!(campaign.gm_ids, vec![A]);
+
+        assert_eq!(campaign.remove_player(B).map(|p| p.name), Some(String::from("Ayla")));
+        assert!(campaign.players.is_empty());
+        assert_eq!(campaign.combat.as_ref().unwrap().order.len(), 1);
+        assert_eq!(campaign.history.len(), 1);
+    }
+
+    #[test]
+    fn only_gms_and_channel_managers_take_over_a_channel() {
+        let mut payload: serde_json::Value = serde_json::from_str(include_str!("../tests/fixtures/slash_command.json")).unwrap();
+        payload["member"]["permissions"] = serde_json::json!("0");
+        payload["member"]["roles"] = serde_json::json!([]);
+        let member: Interaction = serde_json::from_value(payload.clone()).unwrap();
+        let user = member.user_id().unwrap();
+
+        let mut current = campaign(A, GUILD);
+        assert!(!can_take_channel(&member, None, None));
+        assert!(!can_take_channel(&member, None, Some(&current)));
+        current.gm_ids.push(user);
+        assert!(can_take_channel(&member, None, Some(&current)));
+
+        let role = Snowflake::new(1183460234019713086);
+        payload["member"]["roles"] = serde_json::json!([role]);
+        assert!(can_take_channel(&serde_json::from_value(payload.clone()).unwrap(), Some(role), None));
+
+        payload["member"]["roles"] = serde_json::json!([]);
+        payload["member"]["permissions"] = serde_json::json!("16");
+        assert!(can_take_channel(&serde_json::from_value(payload.clone()).unwrap(), None, None));
+    }
+}
@@ -0,0 +1,260 @@
+use super::campaign::{campaign_ruleset, with_campaign};
+use super::dice;
+use super::history::HistoryKind;
+use super::discord_data_structs::{
+    AutocompleteResponse,
+    CommandOptionChoice,
+    Interaction,
+    MessageObject,
+    ResponseOject,
+    MAX_AUTOCOMPLETE_CHOICES
+};
+use super::ruleset::{Outcome, RulesetId};
+use super::i18n::{self, Locale};
+use super::AppState;
+
+fn outcome_suffix(outcome: Outcome, locale: &Locale) -> String {
+    match outcome {
+        Outcome::Unresolved => String::new(),
+        o => format!(" - {}", o.describe(locale))
+    }
+}
+
+pub fn check_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    match interaction.subcommand() {
+        Some("roll") => roll_own_check(state, interaction),
+        Some("group") => group_check(state, interaction),
+        _ => ResponseOject::ephemeral(i18n::locale(state, interaction).format("error.unknown_command", &[("command", &"check")]))
+    }
+}
+
+fn roll_own_check(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let (user_id, name) = match (interaction.user_id(), interaction.option_str("skill")) {
+        (Some(u), Some(n)) => (u, n),
+        _ => return ResponseOject::ephemeral(locale.text("check.skill_required"))
+    };
+
+    let save = interaction.option_bool("save").unwrap_or(false);
+    let dc = interaction.option_i64("dc");
+
+    with_campaign(state, interaction, |campaign| {
+        let player = match campaign.player(user_id) {
+            Some(p) => p,
+            None => return ResponseOject::ephemeral(locale.text("player.not_joined"))
+        };
+
+        match campaign.ruleset.get().resolve_check(player, name, save, dc) {
+            Ok(check) => {
+                let against = dc.map(|d| locale.format("check.against", &[("dc", &d)])).unwrap_or_default();
+                let message = locale.format("check.rolled", &[("player", &player.name), ("check", &check.label), ("against", &against), ("roll", &check.roll), ("outcome", &outcome_suffix(check.outcome, &locale))]);
+                campaign.record(HistoryKind::Roll, Some(user_id), &message);
+                ResponseOject::new(message)
+            },
+            Err(e) => ResponseOject::ephemeral(e.describe(&locale))
+        }
+    })
+}
+
+/// rolls the same check for every player. a secret DC is only shown to the GM, while
+/// the channel sees pass or fail
+fn group_check(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let name = match interaction.option_str("skill") {
+        Some(n) => n,
+        None => return ResponseOject::ephemeral(locale.text("check.skill_required"))
+    };
+    let dc = interaction.option_i64("dc");
+    let save = interaction.option_bool("save").unwrap_or(false);
+    let secret = interaction.option_bool("secret").unwrap_or(false);
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("check.not_gm_group"))
+        }
+        if campaign.players.is_empty() {
+            return ResponseOject::ephemeral(locale.text("campaign.no_players"))
+        }
+
+        let ruleset = campaign.ruleset.get();
+        let mut label = String::new();
+        let mut public: Vec<String> = Vec::new();
+        let mut gm: Vec<String> = Vec::new();
+        for player in &campaign.players {
+            let check = match ruleset.resolve_check(player, name, save, dc) {
+                Ok(c) => c,
+                Err(e) => return ResponseOject::ephemeral(e.describe(&locale))
+            };
+            public.push(format!("{}{}", player.name, outcome_suffix(check.outcome, &locale)));
+            gm.push(format!("{}: {}{}", player.name, check.roll, outcome_suffix(check.outcome, &locale)));
+            label = check.label;
+        }
+
+        let against = dc.map(|d| locale.format("check.against", &[("dc", &d)])).unwrap_or_default();
+        if !secret {
+            let message = locale.format("check.group", &[("check", &label), ("against", &against), ("results", &gm.join("\n"))]);
+            campaign.record(HistoryKind::Roll, None, &message);
+            return ResponseOject::new(message)
+        }
+
+        // the journal only keeps what the players saw
+        let content = locale.format("check.group", &[("check", &label), ("against", &""), ("results", &public.join("\n"))]);
+        campaign.record(HistoryKind::Roll, None, &content);
+        let message = MessageObject {
+            content,
+            ..Default::default()
+        };
+        // into the channel the check was called in, which may be a sidequest thread
+        let channel_id = interaction.channel_id.unwrap_or(campaign.announce_channel());
+        state.discord.spawn_message(channel_id, message);
+
+        ResponseOject::ephemeral(locale.format("check.group_secret", &[("check", &label), ("against", &against), ("results", &gm.join("\n"))]))
+    })
+}
+
+pub fn roll_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let expression = match interaction.option_str("dice") {
+        Some(d) => d,
+        None => return ResponseOject::ephemeral(locale.text("roll.dice_required"))
+    };
+
+    match dice::roll(expression) {
+        Ok(roll) => ResponseOject::new(locale.format("roll.rolled", &[("player", &interaction.user_name()), ("roll", &roll)])),
+        Err(e) => ResponseOject::ephemeral(e.describe(&locale))
+    }
+}
+
+pub fn stats_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    match interaction.subcommand() {
+        Some("show") => show_stats(state, interaction),
+        Some("set") => set_stat(state, interaction),
+        Some("proficient") => toggle_proficiency(state, interaction),
+        _ => ResponseOject::ephemeral(i18n::locale(state, interaction).format("error.unknown_command", &[("command", &"stats")]))
+    }
+}
+
+fn show_stats(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let user_id = match interaction.option_snowflake("player").or(interaction.user_id()) {
+        Some(u) => u,
+        None => return ResponseOject::ephemeral(locale.text("error.no_user"))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        let player = match campaign.player(user_id) {
+            Some(p) => p,
+            None => return ResponseOject::ephemeral(locale.text("player.other_not_joined"))
+        };
+
+        let ruleset = campaign.ruleset.get();
+        let scores: Vec<String> = ruleset.stats().iter()
+            .map(|stat| format!("{} {}", stat.key.to_uppercase(), player.stat(stat)))
+            .collect();
+
+        let mut message = locale.format("stats.sheet", &[("player", &player.name), ("ruleset", &ruleset.name()), ("scores", &scores.join(" | "))]);
+        if campaign.ruleset == RulesetId::D20 {
+            message.push_str(&locale.format("stats.proficiency_bonus", &[("bonus", &format!("{:+}", player.proficiency_bonus()))]));
+        }
+        if !player.proficiencies.is_empty() {
+            message.push_str(&locale.format("stats.proficient_in", &[("proficiencies", &player.proficiencies.join(", "))]));
+        }
+
+        ResponseOject::ephemeral(message)
+    })
+}
+
+/// sets a stat on a player's sheet, the caller's own unless another player is picked.
+/// only the GM edits character sheets
+fn set_stat(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let (user_id, name, value) = match (interaction.option_snowflake("player").or(interaction.user_id()), interaction.option_str("stat"), interaction.option_i64("value")) {
+        (Some(u), Some(n), Some(v)) => (u, n, v),
+        _ => return ResponseOject::ephemeral(locale.text("stats.stat_required"))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("stats.not_gm_set"))
+        }
+
+        let stat = match campaign.ruleset.get().stat(name) {
+            Some(s) => s,
+            None => return ResponseOject::ephemeral(locale.format("stats.unknown_stat", &[("stat", &name), ("ruleset", &campaign.ruleset.get().name())]))
+        };
+
+        if value < stat.min as i64 || value > stat.max as i64 {
+            return ResponseOject::ephemeral(locale.format("stats.out_of_range", &[("stat", &locale.term(stat.name)), ("min", &stat.min), ("max", &stat.max)]))
+        }
+
+        match campaign.player_mut(user_id) {
+            Some(player) => {
+                player.stats.insert(stat.key.to_string(), value as i32);
+                ResponseOject::ephemeral(locale.format("stats.set", &[("player", &player.name), ("stat", &stat.key.to_uppercase()), ("value", &value)]))
+            },
+            None => ResponseOject::ephemeral(locale.text("player.other_not_joined"))
+        }
+    })
+}
+
+fn toggle_proficiency(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let (user_id, name) = match (interaction.option_snowflake("player").or(interaction.user_id()), interaction.option_str("skill")) {
+        (Some(u), Some(n)) => (u, n),
+        _ => return ResponseOject::ephemeral(locale.text("check.skill_required"))
+    };
+
+    let save = interaction.option_bool("save").unwrap_or(false);
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("stats.not_gm_proficient"))
+        }
+
+        let proficiency = match campaign.ruleset.get().proficiency(name, save) {
+            Ok(p) => p,
+            Err(e) => return ResponseOject::ephemeral(e.describe(&locale))
+        };
+
+        let player = match campaign.player_mut(user_id) {
+            Some(p) => p,
+            None => return ResponseOject::ephemeral(locale.text("player.other_not_joined"))
+        };
+
+        if player.is_proficient(&proficiency) {
+            player.proficiencies.retain(|p| *p != proficiency);
+            ResponseOject::ephemeral(locale.format("stats.proficiency_removed", &[("player", &player.name), ("proficiency", &locale.term(&proficiency))]))
+        } else {
+            player.proficiencies.push(proficiency.clone());
+            ResponseOject::ephemeral(locale.format("stats.proficiency_added", &[("player", &player.name), ("proficiency", &locale.term(&proficiency))]))
+        }
+    })
+}
+
+/// suggests check names from the campaign's ruleset for the skill option, and stat
+/// names for the stat option
+pub fn check_autocomplete(state: &AppState, interaction: &Interaction) -> AutocompleteResponse {
+    let focused = match interaction.focused_option() {
+        Some(f) => f,
+        None => return AutocompleteResponse::new(Vec::new())
+    };
+    let partial = focused.value.as_ref()
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .to_lowercase();
+
+    let ruleset = campaign_ruleset(&state.campaigns, interaction).get();
+    let names: Vec<&str> = if focused.name == "stat" {
+        ruleset.stats().iter().map(|s| s.name).collect()
+    } else {
+        ruleset.check_names()
+    };
+
+    let choices: Vec<CommandOptionChoice> = names.into_iter()
+        .filter(|name| name.contains(&partial))
+        .take(MAX_AUTOCOMPLETE_CHOICES)
+        .map(|name| i18n::choice(serde_json::Value::String(name.to_string()), |locale| locale.term(name)))
+        .collect();
+
+    AutocompleteResponse::new(choices)
+}
@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+use super::campaign::{Campaign, with_campaign};
+use super::dice;
+use super::history::HistoryKind;
+use super::i18n::{self, Locale};
+use super::discord_data_structs::{Interaction, ResponseOject};
+use super::snowflake::Snowflake;
+use super::AppState;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Combatant {
+    pub name: String,
+
+    /// the player behind the combatant, `None` for enemies
+    pub player_id: Option<Snowflake>,
+    pub initiative: i32,
+}
+
+/// a fight in progress, combatants sorted by initiative
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Combat {
+    pub round: u32,
+    pub order: Vec<Combatant>,
+}
+
+impl Combat {
+    /// rolls initiative for every player with the campaign's ruleset and a plain d20 for
+    /// each enemy
+    pub fn start(campaign: &Campaign, enemies: &[String]) -> Combat {
+        let ruleset = campaign.ruleset.get();
+
+        let order: Vec<Combatant> = campaign.players.iter()
+            .map(|p| Combatant {
+                name: p.name.clone(),
+                player_id: Some(p.id),
+                initiative: ruleset.initiative(p).total
+            })
+            .collect();
+
+        let mut combat = Combat {
+            round: 1,
+            order
+        };
+        combat.spawn(enemies);
+        combat
+    }
+
+    /// adds enemies to a fight, each rolling a plain d20 for initiative
+    pub fn spawn(&mut self, enemies: &[String]) {
+        self.order.extend(enemies.iter().map(|e| Combatant {
+            name: e.trim().to_string(),
+            player_id: None,
+            initiative: dice::roll_d20(0).total
+        }));
+        self.order.sort_by_key(|c| std::cmp::Reverse(c.initiative));
+    }
+
+    pub fn describe(&self, locale: &Locale) -> String {
+        let order: Vec<String> = self.order.iter()
+            .map(|c| format!("{} ({})", c.name, c.initiative))
+            .collect();
+        locale.format("combat.started", &[("round", &self.round), ("order", &order.join(", "))])
+    }
+}
+
+pub fn combat_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    match interaction.subcommand() {
+        Some("spawn") => spawn(state, interaction),
+        Some("end") => end(state, interaction),
+        _ => ResponseOject::ephemeral(i18n::locale(state, interaction).format("error.unknown_command", &[("command", &"combat")]))
+    }
+}
+
+/// puts enemies into the fight, starting one if there is none yet
+fn spawn(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let enemies: Vec<String> = interaction.option_str("enemies")
+        .map(|e| e.split(',').map(|n| n.trim().to_string()).filter(|n| !n.is_empty()).collect())
+        .unwrap_or_default();
+    if enemies.is_empty() {
+        return ResponseOject::ephemeral(locale.text("combat.enemies_required"))
+    }
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("combat.not_gm_spawn"))
+        }
+
+        let message = match campaign.combat.as_mut() {
+            Some(combat) => {
+                combat.spawn(&enemies);
+                locale.format("combat.joined", &[("enemies", &enemies.join(", "))])
+            },
+            None => {
+                let combat = Combat::start(campaign, &enemies);
+                let message = combat.describe(&locale);
+                campaign.combat = Some(combat);
+                message
+            }
+        };
+
+        campaign.record(HistoryKind::Resolution, None, &message);
+        ResponseOject::new(message)
+    })
+}
+
+fn end(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("combat.not_gm_end"))
+        }
+
+        match campaign.combat.take() {
+            Some(combat) => {
+                let message = locale.format("combat.over", &[("rounds", &combat.round)]);
+                campaign.record(HistoryKind::Resolution, None, &message);
+                ResponseOject::new(message)
+            },
+            None => ResponseOject::ephemeral(locale.text("combat.none"))
+        }
+    })
+}
+
+/// attacks with the equipped weapon, either against a fixed defense or another player
+/// who takes the damage
+pub fn attack_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let user_id = match interaction.user_id() {
+        Some(u) => u,
+        None => return ResponseOject::ephemeral(locale.text("error.no_user"))
+    };
+    let target_id = interaction.option_snowflake("target");
+    let defense = interaction.option_i64("defense").map(|d| d as i32);
+
+    with_campaign(state, interaction, |campaign| {
+        let ruleset = campaign.ruleset.get();
+        let attacker = match campaign.player(user_id) {
+            Some(p) => p,
+            None => return ResponseOject::ephemeral(locale.text("player.not_joined"))
+        };
+
+        // a player's defense comes from their sheet, `defense` only stands in for others
+        let defense = match target_id {
+            Some(target) => match campaign.player(target) {
+                Some(t) => Some(ruleset.defense(t, &state.catalog)),
+                None => return ResponseOject::ephemeral(locale.text("player.other_not_joined"))
+            },
+            None => defense
+        };
+
+        let attack = ruleset.resolve_attack(attacker, &state.catalog, defense);
+        let mut message = attack.describe(&attacker.name, &locale);
+
+        if let (Some(target), Some(damage)) = (target_id, &attack.damage)
+            && let Some(t) = campaign.player_mut(target) {
+            t.health = t.health.saturating_sub(damage.total.clamp(0, u8::MAX as i32) as u8);
+            message.push_str(&locale.format("combat.health", &[("player", &t.name), ("health", &t.health), ("max", &t.max_health)]));
+        }
+        if let Some(recoil) = &attack.recoil
+            && let Some(a) = campaign.player_mut(user_id) {
+            a.health = a.health.saturating_sub(recoil.total.clamp(0, u8::MAX as i32) as u8);
+            message.push_str(&locale.format("combat.health", &[("player", &a.name), ("health", &a.health), ("max", &a.max_health)]));
+        }
+
+        campaign.record(HistoryKind::Roll, Some(user_id), &message);
+        ResponseOject::new(message)
+    })
+}
@@ -0,0 +1,398 @@
+use std::env;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use super::campaign::{Campaign, with_campaign};
+use super::discord_data_structs::{Interaction, ResponseOject};
+use super::history;
+use super::i18n;
+use super::item::ItemCatalog;
+use super::lore;
+use super::narrator::{Narrator, NarrationRequest, PartyMember, Turn};
+use super::AppState;
+
+/// a rolling summary of the story, covering every turn up to `turns`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StorySummary {
+    pub text: String,
+
+    /// number of turns, counted from the start of the campaign, folded into the summary
+    pub turns: usize,
+
+    /// unix timestamp in seconds of when the summary was written
+    pub timestamp: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct ContextConfig {
+    /// rough number of tokens the whole prompt may use
+    pub token_budget: usize,
+
+    /// turns always sent word for word instead of summarized
+    pub recent_turns: usize,
+
+    /// older turns are only folded into the summary once this many have piled up
+    pub summary_batch: usize,
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        ContextConfig {
+            token_budget: 4096,
+            recent_turns: 10,
+            summary_batch: 10
+        }
+    }
+}
+
+impl ContextConfig {
+    /// reads NARRATOR_TOKEN_BUDGET, NARRATOR_RECENT_TURNS and NARRATOR_SUMMARY_BATCH,
+    /// falling back to the defaults
+    pub fn from_env() -> ContextConfig {
+        let default = ContextConfig::default();
+        let read = |name: &str, fallback: usize| env::var(name).ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(fallback);
+
+        ContextConfig {
+            token_budget: read("NARRATOR_TOKEN_BUDGET", default.token_budget),
+            recent_turns: read("NARRATOR_RECENT_TURNS", default.recent_turns),
+            summary_batch: read("NARRATOR_SUMMARY_BATCH", default.summary_batch)
+        }
+    }
+}
+
+/// about four characters per token, close enough for staying under a budget
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count() / 4 + 1
+}
+
+fn turns(campaign: &Campaign, before: usize) -> Vec<Turn> {
+    campaign.turns().into_iter()
+        .take(before)
+        .map(|h| Turn {
+            kind: h.kind,
+            author: h.player_id
+                .and_then(|id| campaign.player(id))
+                .map(|p| p.name.clone()),
+            text: h.text.clone()
+        })
+        .collect()
+}
+
+fn character_sheets(campaign: &Campaign, catalog: &ItemCatalog) -> Vec<String> {
+    let ruleset = campaign.ruleset.get();
+
+    campaign.players.iter()
+        .map(|p| {
+            let stats: Vec<String> = ruleset.stats().iter()
+                .map(|s| format!("{} {}", s.key, p.stat(s)))
+                .collect();
+            let equipped: Vec<String> = p.inventory.equipped_items(catalog).map(|i| i.name.clone()).collect();
+
+            let mut sheet = format!("- {}, level {} {}, {}/{} HP, {}", p.name, p.level, p.class, p.health, p.max_health, stats.join(", "));
+            if !equipped.is_empty() {
+                sheet.push_str(&format!(", wielding {}", equipped.join(", ")));
+            }
+            if !p.features.is_empty() {
+                sheet.push_str(&format!(", features: {}", p.features.join(", ")));
+            }
+            sheet
+        })
+        .collect()
+}
+
+/// lore lines for the prompt, the entries named in `text` first, within `budget` tokens
+fn lore_lines(campaign: &Campaign, text: &str, budget: usize) -> Vec<String> {
+    let mut used = 0;
+    let mut lines: Vec<String> = Vec::new();
+
+    for entry in lore::relevant_lore(campaign, text) {
+        let line = entry.prompt_line();
+        used += estimate_tokens(&line);
+        if used > budget {
+            break
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+fn system_prompt(campaign: &Campaign, sheets: &[String], lore: &[String]) -> String {
+    let mut prompt = format!("You are the game master of a {} tabletop roleplaying campaign played over Discord. \
+        Narrate the outcome of the players' actions in the second person, in a few vivid paragraphs. \
+        Never decide what the players do next. Use the provided tools for anything the rules decide, \
+        such as checks, damage, items and starting combat, and leave the numbers to the game.",
+        campaign.ruleset.get().name());
+
+    if !campaign.world_facts.is_empty() {
+        prompt.push_str("\n\nFacts about the world:\n");
+        prompt.push_str(&campaign.world_facts.iter().map(|f| format!("- {}", f)).collect::<Vec<String>>().join("\n"));
+    }
+
+    if !lore.is_empty() {
+        prompt.push_str("\n\nPlaces, people and lore of the world:\n");
+        prompt.push_str(&lore.join("\n"));
+    }
+
+    if let Some(summary) = campaign.summaries.last() {
+        prompt.push_str("\n\nThe story so far:\n");
+        prompt.push_str(&summary.text);
+    }
+
+    prompt.push_str("\n\nThe party:\n");
+    prompt.push_str(&sheets.join("\n"));
+    prompt
+}
+
+/// assembles the narrator prompt for an action: world facts, lore, the rolling summary,
+/// character sheets and the turns after the summary, dropping the oldest turns until
+/// the prompt fits the token budget. lore named in the action or recent turns goes first
+/// and gets at most a quarter of the budget. `before` is the number of turns logged before the
+/// action, so the action itself isn't sent twice
+pub fn build_request(campaign: &Campaign, catalog: &ItemCatalog, config: &ContextConfig, before: usize, player: &str, action: &str) -> NarrationRequest {
+    let summarized = campaign.summaries.last().map(|s| s.turns).unwrap_or(0);
+    let all_turns = turns(campaign, before);
+    let mut recent_turns: Vec<Turn> = all_turns.into_iter().skip(summarized).collect();
+    let keep_from = recent_turns.len().saturating_sub(config.recent_turns.max(1));
+    recent_turns.drain(..keep_from);
+
+    let mentioned = recent_turns.iter()
+        .map(|t| t.text.as_str())
+        .chain([action])
+        .collect::<Vec<&str>>()
+        .join("\n");
+    let lore = lore_lines(campaign, &mentioned, config.token_budget / 4);
+    let sheets = character_sheets(campaign, catalog);
+    let system_prompt = system_prompt(campaign, &sheets, &lore);
+
+    let fixed = estimate_tokens(&system_prompt) + estimate_tokens(player) + estimate_tokens(action);
+    let mut total = fixed + recent_turns.iter().map(|t| estimate_tokens(&t.text)).sum::<usize>();
+    while total > config.token_budget && !recent_turns.is_empty() {
+        let dropped = recent_turns.remove(0);
+        total -= estimate_tokens(&dropped.text);
+    }
+
+    if total > config.token_budget {
+        log::warn!("narrator prompt for campaign {} is about {} tokens, over the budget of {}", campaign.id, total, config.token_budget);
+    }
+
+    let party: Vec<PartyMember> = campaign.players.iter()
+        .map(|p| PartyMember {
+            name: p.name.clone(),
+            class: p.class.clone(),
+            level: p.level,
+            health: p.health,
+            max_health: p.max_health
+        })
+        .collect();
+
+    NarrationRequest {
+        system_prompt,
+        party,
+        recent_turns,
+        player: player.to_string(),
+        action: action.to_string(),
+        outcomes: None
+    }
+}
+
+/// turns that have aged out of the recent window but aren't summarized yet, once there
+/// are at least `summary_batch` of them
+fn turns_to_fold(campaign: &Campaign, config: &ContextConfig, before: usize) -> Option<(String, Vec<Turn>, usize)> {
+    let summarized = campaign.summaries.last().map(|s| s.turns).unwrap_or(0);
+    let fold_until = before.saturating_sub(config.recent_turns);
+
+    if fold_until < summarized + config.summary_batch.max(1) {
+        return None
+    }
+
+    let previous = campaign.summaries.last().map(|s| s.text.clone()).unwrap_or_default();
+    let pending: Vec<Turn> = turns(campaign, fold_until).into_iter().skip(summarized).collect();
+    Some((previous, pending, fold_until))
+}
+
+/// folds turns that have aged out of the recent window into a new rolling summary.
+/// the campaign lock isn't held while the narrator writes the summary
+pub async fn fold_history(state: &Arc<AppState>, narrator: &Arc<dyn Narrator>, campaign_id: u32, before: usize) {
+    let pending = match state.campaigns.lock() {
+        Ok(lock) => lock.get(campaign_id)
+            .and_then(|c| turns_to_fold(c, &state.context, before)),
+        Err(e) => {
+            log::error!("unable to obtain lock for app state\n{}", e);
+            None
+        }
+    };
+
+    let (previous, pending, fold_until) = match pending {
+        Some(p) => p,
+        None => return
+    };
+
+    log::info!("summarizing {} turns for campaign {}", pending.len(), campaign_id);
+    let text = match narrator.summarize(&previous, &pending).await {
+        Ok(t) => t,
+        Err(e) => {
+            log::error!("could not summarize history for campaign {}\n{}", campaign_id, e);
+            return
+        }
+    };
+
+    match state.campaigns.lock() {
+        Ok(mut lock) => {
+            if let Some(campaign) = lock.get_mut(campaign_id) {
+                let summarized = campaign.summaries.last().map(|s| s.turns).unwrap_or(0);
+                if fold_until > summarized {
+                    campaign.summaries.push(StorySummary {
+                        text,
+                        turns: fold_until,
+                        timestamp: history::now()
+                    });
+                }
+            }
+        },
+        Err(e) => log::error!("unable to obtain lock for app state\n{}", e)
+    }
+}
+
+pub fn facts_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    match interaction.subcommand() {
+        Some("add") => add_fact(state, interaction),
+        Some("list") => list_facts(state, interaction),
+        Some("remove") => remove_fact(state, interaction),
+        _ => ResponseOject::ephemeral(i18n::locale(state, interaction).format("error.unknown_command", &[("command", &"facts")]))
+    }
+}
+
+fn add_fact(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let fact = match interaction.option_str("fact") {
+        Some(f) => f.trim().to_string(),
+        None => return ResponseOject::ephemeral(locale.text("facts.required"))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("facts.not_gm_add"))
+        }
+
+        campaign.world_facts.push(fact.clone());
+        ResponseOject::ephemeral(locale.format("facts.added", &[("number", &campaign.world_facts.len()), ("fact", &fact)]))
+    })
+}
+
+fn list_facts(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    with_campaign(state, interaction, |campaign| {
+        if campaign.world_facts.is_empty() {
+            return ResponseOject::ephemeral(locale.text("facts.empty"))
+        }
+
+        let facts: Vec<String> = campaign.world_facts.iter()
+            .enumerate()
+            .map(|(i, f)| format!("{}. {}", i + 1, f))
+            .collect();
+        ResponseOject::ephemeral(facts.join("\n"))
+    })
+}
+
+fn remove_fact(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let number = match interaction.option_i64("number") {
+        Some(n) if n > 0 => n as usize,
+        _ => return ResponseOject::ephemeral(locale.text("facts.number_required"))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("facts.not_gm_remove"))
+        }
+
+        if number > campaign.world_facts.len() {
+            return ResponseOject::ephemeral(locale.format("facts.unknown", &[("number", &number)]))
+        }
+
+        let removed = campaign.world_facts.remove(number - 1);
+        ResponseOject::ephemeral(locale.format("facts.removed", &[("fact", &removed)]))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::HistoryKind;
+    use crate::lore::{LoreEntry, LoreKind};
+    use crate::player::Player;
+    use crate::ruleset::RulesetId;
+    use crate::snowflake::Snowflake;
+
+    fn campaign() -> Campaign {
+        let mut campaign = Campaign::new(Snowflake::new(10), RulesetId::D20);
+        campaign.players.push(Player::new(Snowflake::new(1), "Ayla", "Fighter", RulesetId::D20.get()));
+        campaign.record(HistoryKind::Action, Some(Snowflake::new(1)), "open the door");
+        campaign.record(HistoryKind::Narration, None, "the door creaks open");
+        campaign
+    }
+
+    fn lore(name: &str, description: &str) -> LoreEntry {
+        LoreEntry {
+            name: name.to_string(),
+            kind: LoreKind::Location,
+            description: description.to_string(),
+            tags: Vec::new(),
+            secret: None,
+            share_secret: false
+        }
+    }
+
+    #[test]
+    fn summarized_turns_move_into_the_system_prompt() {
+        let mut campaign = campaign();
+        campaign.world_facts.push(String::from("the king is dead"));
+        campaign.summaries.push(StorySummary {
+            text: String::from("Ayla opened the door"),
+            turns: 2,
+            timestamp: 0
+        });
+
+        let before = campaign.turns().len();
+        let request = build_request(&campaign, &ItemCatalog::default(), &ContextConfig::default(), before, "Ayla", "light a torch");
+
+        assert!(request.recent_turns.is_empty());
+        assert!(request.system_prompt.contains("the king is dead"));
+        assert!(request.system_prompt.contains("Ayla opened the door"));
+        assert!(request.system_prompt.contains("str 10"));
+    }
+
+    #[test]
+    fn oldest_turns_are_dropped_to_fit_the_budget() {
+        let mut campaign = campaign();
+        for i in 0..6 {
+            campaign.record(HistoryKind::Narration, None, &format!("{} {}", i, "words ".repeat(100)));
+        }
+
+        let config = ContextConfig { token_budget: 500, ..ContextConfig::default() };
+        let before = campaign.turns().len();
+        let request = build_request(&campaign, &ItemCatalog::default(), &config, before, "Ayla", "light a torch");
+
+        assert!(request.recent_turns.len() < before);
+        assert!(request.recent_turns.last().unwrap().text.starts_with('5'));
+    }
+
+    #[test]
+    fn lore_named_in_the_action_comes_first_within_its_share() {
+        let mut campaign = campaign();
+        campaign.lore.push(lore("Harrow", &"a fishing town ".repeat(10)));
+        campaign.lore.push(lore("Greywater", "a river"));
+
+        let lines = lore_lines(&campaign, "row to greywater", 100);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("- Greywater"));
+
+        let lines = lore_lines(&campaign, "row to greywater", 10);
+        assert_eq!(lines, vec![String::from("- Greywater (location): a river")]);
+
+        let before = campaign.turns().len();
+        let request = build_request(&campaign, &ItemCatalog::default(), &ContextConfig::default(), before, "Ayla", "ride to Harrow");
+        assert!(request.system_prompt.contains("Places, people and lore of the world:\n- Harrow (location)"));
+    }
+}
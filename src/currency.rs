@@ -0,0 +1,253 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use super::i18n::Locale;
+
+pub const COPPER_PER_SILVER: u32 = 10;
+pub const COPPER_PER_GOLD: u32 = 100;
+pub const COPPER_PER_PLATINUM: u32 = 1000;
+
+/// why coins couldn't be read, paid or put away
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CoinError {
+    Unreadable(String),
+    UnknownCoin(String),
+    TooLarge(String),
+    PurseFull,
+    CantAfford { price: u32, purse: Purse },
+}
+
+impl CoinError {
+    pub fn describe(&self, locale: &Locale) -> String {
+        match self {
+            CoinError::Unreadable(amount) => locale.format("coins.unreadable", &[("amount", amount)]),
+            CoinError::UnknownCoin(coin) => locale.format("coins.unknown", &[("coin", coin)]),
+            CoinError::TooLarge(amount) => locale.format("coins.too_large", &[("amount", amount)]),
+            CoinError::PurseFull => locale.text("coins.purse_full"),
+            CoinError::CantAfford { price, purse } => locale.format("coins.cant_afford", &[("price", &Purse::from_copper(*price)), ("purse", purse)])
+        }
+    }
+}
+
+/// coins carried by a player, kept per denomination
+#[derive(Clone, Debug, Deserialize, Serialize, Default, PartialEq, Eq)]
+pub struct Purse {
+    pub platinum: u32,
+    pub gold: u32,
+    pub silver: u32,
+    pub copper: u32,
+}
+
+impl Purse {
+    pub fn new() -> Purse {
+        Purse::default()
+    }
+
+    /// purse holding `copper` in the fewest gold, silver and copper coins
+    pub fn from_copper(copper: u32) -> Purse {
+        Purse {
+            platinum: 0,
+            gold: copper / COPPER_PER_GOLD,
+            silver: (copper % COPPER_PER_GOLD) / COPPER_PER_SILVER,
+            copper: copper % COPPER_PER_SILVER
+        }
+    }
+
+    /// parses amounts such as "3gp 5sp", "12 cp" or a bare number of copper
+    pub fn parse(input: &str) -> Result<Purse, CoinError> {
+        let mut purse = Purse::new();
+        let input = input.trim().to_lowercase();
+
+        if let Ok(copper) = input.parse::<u32>() {
+            purse.copper = copper;
+            return Ok(purse)
+        }
+
+        let mut tokens: Vec<String> = Vec::new();
+        for word in input.split_whitespace() {
+            let split = word.find(|c: char| !c.is_ascii_digit()).unwrap_or(word.len());
+            if split > 0 && split < word.len() {
+                tokens.push(word[..split].to_string());
+                tokens.push(word[split..].to_string());
+            } else {
+                tokens.push(word.to_string());
+            }
+        }
+
+        if tokens.is_empty() || !tokens.len().is_multiple_of(2) {
+            return Err(CoinError::Unreadable(input))
+        }
+
+        for pair in tokens.chunks(2) {
+            let amount: u32 = match pair[0].parse() {
+                Ok(a) => a,
+                Err(_) => return Err(CoinError::Unreadable(input))
+            };
+
+            let coins = match pair[1].as_str() {
+                "pp" => &mut purse.platinum,
+                "gp" => &mut purse.gold,
+                "sp" => &mut purse.silver,
+                "cp" => &mut purse.copper,
+                other => return Err(CoinError::UnknownCoin(other.to_string()))
+            };
+            *coins = match coins.checked_add(amount) {
+                Some(c) => c,
+                None => return Err(CoinError::TooLarge(input))
+            };
+        }
+
+        Ok(purse)
+    }
+
+    /// parses an amount and returns its worth in copper, refusing amounts too large to hold
+    pub fn parse_copper(input: &str) -> Result<u32, CoinError> {
+        let purse = Purse::parse(input)?;
+        match u32::try_from(purse.total_copper()) {
+            Ok(copper) => Ok(copper),
+            Err(_) => Err(CoinError::TooLarge(input.trim().to_string()))
+        }
+    }
+
+    /// worth of every coin in the purse. kept wide so a full purse can't overflow
+    pub fn total_copper(&self) -> u64 {
+        self.platinum as u64 * COPPER_PER_PLATINUM as u64
+            + self.gold as u64 * COPPER_PER_GOLD as u64
+            + self.silver as u64 * COPPER_PER_SILVER as u64
+            + self.copper as u64
+    }
+
+    /// puts coins into the purse. nothing changes if any denomination would overflow
+    pub fn add(&mut self, other: &Purse) -> Result<(), CoinError> {
+        match (
+            self.platinum.checked_add(other.platinum),
+            self.gold.checked_add(other.gold),
+            self.silver.checked_add(other.silver),
+            self.copper.checked_add(other.copper)
+        ) {
+            (Some(platinum), Some(gold), Some(silver), Some(copper)) => {
+                *self = Purse { platinum, gold, silver, copper };
+                Ok(())
+            },
+            _ => Err(CoinError::PurseFull)
+        }
+    }
+
+    pub fn add_copper(&mut self, copper: u32) -> Result<(), CoinError> {
+        self.add(&Purse::from_copper(copper))
+    }
+
+    /// pays `copper` out of the purse, making change from larger coins as needed
+    pub fn spend(&mut self, copper: u32) -> Result<(), CoinError> {
+        let total = self.total_copper();
+        if total < copper as u64 {
+            return Err(CoinError::CantAfford { price: copper, purse: self.clone() })
+        }
+
+        let mut remaining = copper;
+        for (coins, worth) in [
+            (&mut self.copper, 1),
+            (&mut self.silver, COPPER_PER_SILVER),
+            (&mut self.gold, COPPER_PER_GOLD),
+            (&mut self.platinum, COPPER_PER_PLATINUM)
+        ] {
+            let used = (*coins).min(remaining / worth);
+            *coins -= used;
+            remaining -= used * worth;
+        }
+
+        // every coin left is worth more than what is still owed, so break the smallest one
+        if remaining > 0 {
+            let mut change = 0;
+            for (coins, worth) in [
+                (&mut self.silver, COPPER_PER_SILVER),
+                (&mut self.gold, COPPER_PER_GOLD),
+                (&mut self.platinum, COPPER_PER_PLATINUM)
+            ] {
+                if *coins > 0 {
+                    *coins -= 1;
+                    change = worth - remaining;
+                    break
+                }
+            }
+            // the coins change is paid in were all used up above, so this can't overflow
+            self.add_copper(change)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total_copper() == 0
+    }
+}
+
+impl fmt::Display for Purse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts: Vec<String> = Vec::new();
+        if self.platinum > 0 { parts.push(format!("{} pp", self.platinum)) }
+        if self.gold > 0 { parts.push(format!("{} gp", self.gold)) }
+        if self.silver > 0 { parts.push(format!("{} sp", self.silver)) }
+        if self.copper > 0 { parts.push(format!("{} cp", self.copper)) }
+
+        if parts.is_empty() {
+            write!(f, "0 cp")
+        } else {
+            write!(f, "{}", parts.join(" "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_refuses_amounts_that_overflow() {
+        assert_eq!(Purse::parse("4294967295cp 1cp"), Err(CoinError::TooLarge(String::from("4294967295cp 1cp"))));
+        assert!(Purse::parse("99999999999").is_err());
+        assert_eq!(Purse::parse_copper("5000000pp"), Err(CoinError::TooLarge(String::from("5000000pp"))));
+        assert_eq!(Purse::parse_copper("4294967pp").unwrap(), 4_294_967_000);
+    }
+
+    #[test]
+    fn spend_makes_change_from_the_smallest_coin_that_covers_it() {
+        let mut purse = Purse { platinum: 0, gold: 1, silver: 0, copper: 0 };
+        purse.spend(35).unwrap();
+        assert_eq!(purse, Purse { platinum: 0, gold: 0, silver: 6, copper: 5 });
+
+        let mut purse = Purse { platinum: 1, gold: 1, silver: 0, copper: 3 };
+        purse.spend(150).unwrap();
+        assert_eq!(purse, Purse { platinum: 0, gold: 9, silver: 5, copper: 3 });
+
+        let mut purse = Purse { platinum: 0, gold: 1, silver: 5, copper: 0 };
+        purse.spend(120).unwrap();
+        assert_eq!(purse.total_copper(), 30);
+        assert_eq!(purse.gold, 0);
+    }
+
+    #[test]
+    fn spend_refuses_what_the_purse_can_not_cover() {
+        let mut purse = Purse { platinum: 0, gold: 2, silver: 0, copper: 9 };
+        assert_eq!(purse.spend(210), Err(CoinError::CantAfford { price: 210, purse: purse.clone() }));
+        assert_eq!(purse, Purse { platinum: 0, gold: 2, silver: 0, copper: 9 });
+
+        purse.spend(209).unwrap();
+        assert!(purse.is_empty());
+    }
+
+    #[test]
+    fn spend_from_a_huge_purse_does_not_overflow() {
+        let mut purse = Purse { platinum: u32::MAX, gold: u32::MAX, silver: u32::MAX, copper: u32::MAX };
+        let before = purse.total_copper();
+        purse.spend(u32::MAX).unwrap();
+        assert_eq!(purse.total_copper(), before - u32::MAX as u64);
+    }
+
+    #[test]
+    fn add_leaves_a_full_purse_untouched() {
+        let mut purse = Purse { platinum: 1, gold: u32::MAX, silver: 0, copper: 0 };
+        assert!(purse.add(&Purse { platinum: 1, gold: 1, silver: 0, copper: 0 }).is_err());
+        assert_eq!(purse, Purse { platinum: 1, gold: u32::MAX, silver: 0, copper: 0 });
+        assert_eq!(purse.total_copper(), 1000 + u32::MAX as u64 * 100);
+    }
+}
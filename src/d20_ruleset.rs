@@ -0,0 +1,255 @@
+use super::advancement::ClassDefinition;
+use super::dice::{self, DiceRoll};
+use super::item::{ItemCatalog, EquipSlot};
+use super::player::Player;
+use super::ruleset::{
+    AttackKind,
+    AttackOutcome,
+    CheckError,
+    CheckOutcome,
+    Outcome,
+    Ruleset,
+    RulesetId,
+    StatDefinition
+};
+
+const fn ability(key: &'static str, name: &'static str) -> StatDefinition {
+    StatDefinition { key, name, min: 1, max: 30, default: 10 }
+}
+
+pub const ABILITIES: [StatDefinition; 6] = [
+    ability("str", "strength"),
+    ability("dex", "dexterity"),
+    ability("con", "constitution"),
+    ability("int", "intelligence"),
+    ability("wis", "wisdom"),
+    ability("cha", "charisma"),
+];
+
+/// every skill and the ability it is rolled with
+pub const SKILLS: [(&str, &str); 18] = [
+    ("acrobatics", "dex"),
+    ("animal handling", "wis"),
+    ("arcana", "int"),
+    ("athletics", "str"),
+    ("deception", "cha"),
+    ("history", "int"),
+    ("insight", "wis"),
+    ("intimidation", "cha"),
+    ("investigation", "int"),
+    ("medicine", "wis"),
+    ("nature", "int"),
+    ("perception", "wis"),
+    ("performance", "cha"),
+    ("persuasion", "cha"),
+    ("religion", "int"),
+    ("sleight of hand", "dex"),
+    ("stealth", "dex"),
+    ("survival", "wis"),
+];
+
+pub const BASE_ARMOR_CLASS: i32 = 10;
+
+/// total xp for levels 2 to 20
+pub const XP_THRESHOLDS: [u32; 19] = [
+    300, 900, 2700, 6500, 14000, 23000, 34000, 48000, 64000, 85000,
+    100000, 120000, 140000, 165000, 195000, 225000, 265000, 305000, 355000,
+];
+
+/// hit die used for players whose class isn't in advancement.json
+pub const DEFAULT_HIT_DIE: u8 = 8;
+
+/// damage dealt without a weapon in the main hand
+pub const UNARMED_DAMAGE: &str = "1";
+
+pub fn ability_modifier(score: i32) -> i32 {
+    (score - 10).div_euclid(2)
+}
+
+/// what is being rolled: a skill, a raw ability check or a saving throw
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Check {
+    Skill { skill: &'static str, ability: &'static str },
+    Ability(&'static str),
+    Save(&'static str),
+}
+
+impl Check {
+    pub fn parse(name: &str, save: bool) -> Result<Check, CheckError> {
+        if let Some(ability) = D20Ruleset.stat(name) {
+            return Ok(if save { Check::Save(ability.key) } else { Check::Ability(ability.key) })
+        }
+
+        let lower = name.trim().to_lowercase();
+        match SKILLS.iter().find(|(skill, _)| *skill == lower) {
+            Some((skill, _)) if save => Err(CheckError::SaveWithSkill(skill.to_string())),
+            Some((skill, ability)) => Ok(Check::Skill { skill, ability }),
+            None => Err(CheckError::UnknownCheck(name.to_string()))
+        }
+    }
+
+    pub fn ability(&self) -> &'static str {
+        match self {
+            Check::Skill { ability, .. } => ability,
+            Check::Ability(ability) => ability,
+            Check::Save(ability) => ability
+        }
+    }
+
+    /// proficiency name as stored on the player, e.g. "stealth" or "dex save"
+    pub fn proficiency(&self) -> Option<String> {
+        match self {
+            Check::Skill { skill, .. } => Some(skill.to_string()),
+            Check::Ability(_) => None,
+            Check::Save(ability) => Some(format!("{} save", ability))
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Check::Skill { skill, ability } => format!("{} ({})", skill, ability.to_uppercase()),
+            Check::Ability(ability) => format!("{} check", ability.to_uppercase()),
+            Check::Save(ability) => format!("{} save", ability.to_uppercase())
+        }
+    }
+}
+
+/// d20 SRD style rules: six abilities, d20 + modifier against a DC or armor class
+pub struct D20Ruleset;
+
+impl D20Ruleset {
+    fn modifier(&self, player: &Player, ability: &str) -> i32 {
+        match self.stat(ability) {
+            Some(def) => ability_modifier(player.stat(def)),
+            None => 0
+        }
+    }
+
+    fn check_modifier(&self, player: &Player, check: &Check) -> i32 {
+        let mut modifier = self.modifier(player, check.ability());
+        if let Some(proficiency) = check.proficiency()
+            && player.is_proficient(&proficiency) {
+            modifier += player.proficiency_bonus();
+        }
+        modifier
+    }
+}
+
+impl Ruleset for D20Ruleset {
+    fn id(&self) -> RulesetId {
+        RulesetId::D20
+    }
+
+    fn name(&self) -> &'static str {
+        "d20 SRD"
+    }
+
+    fn stats(&self) -> &'static [StatDefinition] {
+        &ABILITIES
+    }
+
+    fn check_names(&self) -> Vec<&'static str> {
+        ABILITIES.iter()
+            .map(|a| a.name)
+            .chain(SKILLS.iter().map(|(skill, _)| *skill))
+            .collect()
+    }
+
+    fn starting_health(&self) -> u8 {
+        10
+    }
+
+    fn resolve_check(&self, player: &Player, name: &str, save: bool, difficulty: Option<i64>) -> Result<CheckOutcome, CheckError> {
+        let check = Check::parse(name, save)?;
+        let roll = dice::roll_d20(self.check_modifier(player, &check));
+
+        let outcome = match difficulty {
+            Some(dc) if roll.total as i64 >= dc => Outcome::Success,
+            Some(_) => Outcome::Failure,
+            None => Outcome::Unresolved
+        };
+
+        Ok(CheckOutcome {
+            label: check.describe(),
+            roll,
+            outcome
+        })
+    }
+
+    fn defense(&self, player: &Player, catalog: &ItemCatalog) -> i32 {
+        BASE_ARMOR_CLASS + self.modifier(player, "dex") + player.inventory.ac_bonus(catalog)
+    }
+
+    /// d20 + STR + proficiency + weapon bonus against armor class. a natural 20 always
+    /// hits and rolls the damage dice twice, a natural 1 always misses
+    fn resolve_attack(&self, attacker: &Player, catalog: &ItemCatalog, defense: Option<i32>) -> AttackOutcome {
+        let strength = self.modifier(attacker, "str");
+        let modifier = strength + attacker.proficiency_bonus() + attacker.attack_bonus(catalog);
+        let roll = dice::roll_d20(modifier);
+        let natural = roll.rolls.first().copied().unwrap_or(0);
+
+        let outcome = match defense {
+            _ if natural == 20 => Outcome::Success,
+            _ if natural == 1 => Outcome::Failure,
+            Some(ac) if roll.total >= ac => Outcome::Success,
+            Some(_) => Outcome::Failure,
+            None => Outcome::Unresolved
+        };
+
+        let weapon = attacker.inventory.equipped.get(&EquipSlot::MainHand)
+            .and_then(|name| catalog.find(name));
+        let damage_dice = weapon.and_then(|w| w.damage.clone()).unwrap_or(UNARMED_DAMAGE.to_string());
+
+        let damage: Option<DiceRoll> = match outcome {
+            Outcome::Success | Outcome::Unresolved => {
+                let expression = if natural == 20 { format!("{}+{}", damage_dice, damage_dice) } else { damage_dice };
+                match dice::roll(&format!("{}{:+}", expression, strength)) {
+                    Ok(mut d) => {
+                        d.total = d.total.max(0);
+                        Some(d)
+                    },
+                    Err(e) => {
+                        log::error!("could not roll damage {} for {}\n{:?}", expression, attacker.name, e);
+                        None
+                    }
+                }
+            },
+            _ => None
+        };
+
+        AttackOutcome {
+            kind: AttackKind::Strike {
+                weapon: weapon.map(|w| w.name.clone()),
+                critical: natural == 20,
+                armor_class: defense
+            },
+            roll,
+            outcome,
+            damage,
+            recoil: None
+        }
+    }
+
+    fn xp_thresholds(&self) -> &'static [u32] {
+        &XP_THRESHOLDS
+    }
+
+    /// the rounded up average of the class hit die
+    fn health_per_level(&self, class: Option<&ClassDefinition>) -> u8 {
+        match class.map(|c| c.hit_die).unwrap_or(DEFAULT_HIT_DIE) {
+            0 => 0,
+            hit_die => hit_die / 2 + 1
+        }
+    }
+
+    fn initiative(&self, player: &Player) -> DiceRoll {
+        dice::roll_d20(self.modifier(player, "dex"))
+    }
+
+    fn proficiency(&self, name: &str, save: bool) -> Result<String, CheckError> {
+        match Check::parse(name, save)?.proficiency() {
+            Some(p) => Ok(p),
+            None => Err(CheckError::NoProficiency)
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use std::fmt;
+use rand::Rng;
+use super::i18n::Locale;
+
+/// one part of a dice expression, such as `2d6` or `+3`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DiceTerm {
+    Dice { count: u32, sides: u32, negative: bool },
+    Constant(i32),
+}
+
+impl DiceTerm {
+    /// lowest and highest value the term can roll, `None` when that doesn't fit an i32
+    fn range(&self) -> Option<(i32, i32)> {
+        match self {
+            DiceTerm::Dice { count, sides, negative } => {
+                let low = i32::try_from(*count).ok()?;
+                let high = i32::try_from(count.checked_mul(*sides)?).ok()?;
+                Some(if *negative { (-high, -low) } else { (low, high) })
+            },
+            DiceTerm::Constant(c) => Some((*c, *c))
+        }
+    }
+}
+
+/// result of rolling a dice expression
+#[derive(Clone, Debug)]
+pub struct DiceRoll {
+    pub expression: String,
+
+    /// every die rolled, in order of the terms
+    pub rolls: Vec<u32>,
+    pub total: i32,
+}
+
+impl fmt::Display for DiceRoll {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rolls: Vec<String> = self.rolls.iter().map(|r| r.to_string()).collect();
+        write!(f, "{} [{}] = **{}**", self.expression, rolls.join(", "), self.total)
+    }
+}
+
+pub const MAX_DICE: u32 = 100;
+pub const MAX_SIDES: u32 = 1000;
+
+/// why a dice expression can't be rolled, holding the expression as it was written
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DiceError {
+    Empty,
+    Unreadable(String),
+    OutOfBounds,
+    TooLarge(String),
+}
+
+impl DiceError {
+    pub fn describe(&self, locale: &Locale) -> String {
+        match self {
+            DiceError::Empty => locale.text("dice.empty"),
+            DiceError::Unreadable(expression) => locale.format("dice.unreadable", &[("expression", expression)]),
+            DiceError::OutOfBounds => locale.format("dice.out_of_bounds", &[("dice", &MAX_DICE), ("sides", &MAX_SIDES)]),
+            DiceError::TooLarge(expression) => locale.format("dice.too_large", &[("expression", expression)])
+        }
+    }
+}
+
+/// parses expressions like "d20", "2d6+3" or "1d8 - 1 + 1d4"
+pub fn parse(expression: &str) -> Result<Vec<DiceTerm>, DiceError> {
+    let cleaned: String = expression.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_lowercase();
+    if cleaned.is_empty() {
+        return Err(DiceError::Empty)
+    }
+
+    let mut terms: Vec<DiceTerm> = Vec::new();
+    let mut negative = false;
+    let mut current = String::new();
+
+    for c in cleaned.chars().chain(std::iter::once('+')) {
+        if c == '+' || c == '-' {
+            if current.is_empty() {
+                if terms.is_empty() && c == '-' {
+                    negative = !negative;
+                    continue
+                }
+                return Err(DiceError::Unreadable(expression.to_string()))
+            }
+
+            terms.push(parse_term(&current, negative, expression)?);
+            negative = c == '-';
+            current.clear();
+        } else {
+            current.push(c);
+        }
+    }
+
+    // every total the expression can reach has to fit, so rolling it never overflows
+    let (mut low, mut high) = (0i32, 0i32);
+    for term in &terms {
+        let sum = term.range().and_then(|(min, max)| Some((low.checked_add(min)?, high.checked_add(max)?)));
+        match sum {
+            Some(s) => (low, high) = s,
+            None => return Err(DiceError::TooLarge(expression.to_string()))
+        }
+    }
+
+    Ok(terms)
+}
+
+fn parse_term(term: &str, negative: bool, expression: &str) -> Result<DiceTerm, DiceError> {
+    let error = || DiceError::Unreadable(expression.to_string());
+
+    match term.split_once('d') {
+        Some((count, sides)) => {
+            let count: u32 = if count.is_empty() { 1 } else { count.parse().map_err(|_| error())? };
+            let sides: u32 = sides.parse().map_err(|_| error())?;
+
+            if count == 0 || count > MAX_DICE || sides == 0 || sides > MAX_SIDES {
+                return Err(DiceError::OutOfBounds)
+            }
+
+            Ok(DiceTerm::Dice { count, sides, negative })
+        },
+        None => {
+            let value: i32 = term.parse().map_err(|_| error())?;
+            Ok(DiceTerm::Constant(if negative { -value } else { value }))
+        }
+    }
+}
+
+pub fn roll_die(sides: u32) -> u32 {
+    rand::rng().random_range(1..=sides)
+}
+
+pub fn roll_terms(expression: &str, terms: &[DiceTerm]) -> DiceRoll {
+    let mut rolls: Vec<u32> = Vec::new();
+    let mut total: i32 = 0;
+
+    for term in terms {
+        match term {
+            DiceTerm::Dice { count, sides, negative } => {
+                for _ in 0..*count {
+                    let r = roll_die(*sides);
+                    rolls.push(r);
+                    total = total.saturating_add(if *negative { -(r as i32) } else { r as i32 });
+                }
+            },
+            DiceTerm::Constant(c) => total = total.saturating_add(*c)
+        }
+    }
+
+    DiceRoll {
+        expression: expression.trim().to_string(),
+        rolls,
+        total
+    }
+}
+
+pub fn roll(expression: &str) -> Result<DiceRoll, DiceError> {
+    let terms = parse(expression)?;
+    Ok(roll_terms(expression, &terms))
+}
+
+/// a single d20 plus a modifier, the roll behind every check and save
+pub fn roll_d20(modifier: i32) -> DiceRoll {
+    let die = roll_die(20);
+    DiceRoll {
+        expression: format!("1d20{:+}", modifier),
+        rolls: vec![die],
+        total: modifier.saturating_add(die as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_dice_and_constants() {
+        assert_eq!(parse("d20").unwrap(), vec![DiceTerm::Dice { count: 1, sides: 20, negative: false }]);
+        assert_eq!(parse(" 2D6 + 3 ").unwrap(), vec![
+            DiceTerm::Dice { count: 2, sides: 6, negative: false },
+            DiceTerm::Constant(3),
+        ]);
+        assert_eq!(parse("1d8-1-1d4").unwrap(), vec![
+            DiceTerm::Dice { count: 1, sides: 8, negative: false },
+            DiceTerm::Constant(-1),
+            DiceTerm::Dice { count: 1, sides: 4, negative: true },
+        ]);
+        assert_eq!(parse("-d4").unwrap(), vec![DiceTerm::Dice { count: 1, sides: 4, negative: true }]);
+    }
+
+    #[test]
+    fn refuses_malformed_expressions() {
+        for expression in ["", "   ", "2d6+", "2d6--1", "+3", "d", "2d", "xd6", "2d6x", "1.5d6"] {
+            assert!(parse(expression).is_err(), "{:?} was accepted", expression);
+        }
+    }
+
+    #[test]
+    fn refuses_dice_out_of_bounds() {
+        for expression in ["0d6", "1d0", "101d6", "1d1001"] {
+            assert_eq!(parse(expression), Err(DiceError::OutOfBounds), "{:?} was accepted", expression);
+        }
+        assert!(parse("100d1000").is_ok());
+    }
+
+    #[test]
+    fn refuses_totals_that_overflow() {
+        assert!(parse("2147483648").is_err());
+        assert!(parse("2147483647").is_ok());
+        assert_eq!(parse("2147483647+1"), Err(DiceError::TooLarge(String::from("2147483647+1"))));
+        assert!(parse("-2147483647-2").is_err());
+        assert!(parse("2147483647-1d6").is_ok());
+        assert!(parse("2147483000+1d1000").is_err());
+
+        let many = vec!["100d1000"; 21475].join("+");
+        assert!(parse(&many).is_err());
+        assert!(parse(&many[..many.len() - "+100d1000".len()]).is_ok());
+    }
+
+    #[test]
+    fn rolls_stay_within_the_expression() {
+        for _ in 0..100 {
+            let roll = roll("3d6-2").unwrap();
+            assert_eq!(roll.rolls.len(), 3);
+            assert!((1..=16).contains(&roll.total), "{}", roll);
+            assert_eq!(roll.total, roll.rolls.iter().sum::<u32>() as i32 - 2);
+        }
+
+        let roll = roll("2147483647-1d1").unwrap();
+        assert_eq!(roll.total, 2147483646);
+        assert_eq!(roll_d20(i32::MAX).total, i32::MAX);
+    }
+}
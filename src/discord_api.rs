@@ -0,0 +1,249 @@
+use std::env;
+use std::error::Error;
+use std::str::FromStr;
+use reqwest::{
+    Client,
+    header,
+    multipart
+};
+use serde::Deserialize;
+use super::discord_data_structs::{Attachment, Channel, MessageObject, StartThread};
+use super::stream::split_message;
+use super::snowflake::Snowflake;
+
+/// the part of a message object discord sends back that the bot needs
+#[derive(Deserialize, Debug)]
+struct CreatedMessage {
+    id: Snowflake,
+}
+
+pub const DISCORD_API_URL: &str = "https://discord.com/api/v10";
+
+/// REST client for the calls the bot makes outside of interaction responses
+#[derive(Clone)]
+pub struct DiscordClient {
+    client: Client,
+    headers: header::HeaderMap,
+    base_url: String,
+}
+
+impl DiscordClient {
+    pub fn new(token: &str, base_url: &str) -> DiscordClient {
+        let mut headers: header::HeaderMap = header::HeaderMap::new();
+        match header::HeaderValue::from_str(&format!("Bot {}", token)) {
+            Ok(v) => {
+                headers.insert(header::AUTHORIZATION, v);
+            },
+            Err(e) => log::error!("could not create auth header value\n{}", e)
+        }
+
+        DiscordClient {
+            client: Client::new(),
+            headers,
+            base_url: base_url.to_string()
+        }
+    }
+
+    pub fn from_env() -> DiscordClient {
+        let discord_token: String = env::var("DISCORD_TOKEN")
+            .expect("DISCORD_TOKEN env variable must be set");
+
+        DiscordClient::new(&discord_token, DISCORD_API_URL)
+    }
+
+    /// posts a new message into a channel
+    pub async fn create_message(&self, channel_id: Snowflake, message: &MessageObject) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let endpoint = reqwest::Url::from_str(&format!("{}/channels/{}/messages", self.base_url, channel_id))?;
+
+        let response = self.client.post(endpoint)
+            .headers(self.headers.clone())
+            .json(message)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("discord returned {} creating message\n{}", status, body).into())
+        }
+
+        Ok(())
+    }
+
+    /// replaces the content of a message the bot posted, leaving fields that aren't set as
+    /// they are
+    pub async fn edit_message(&self, channel_id: Snowflake, message_id: Snowflake, message: &MessageObject) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let endpoint = reqwest::Url::from_str(&format!("{}/channels/{}/messages/{}", self.base_url, channel_id, message_id))?;
+
+        let response = self.client.patch(endpoint)
+            .headers(self.headers.clone())
+            .json(message)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("discord returned {} editing message {}\n{}", status, message_id, body).into())
+        }
+
+        Ok(())
+    }
+
+    /// starts a thread in a text channel, not attached to any message
+    pub async fn create_thread(&self, channel_id: Snowflake, thread: &StartThread) -> Result<Channel, Box<dyn Error + Send + Sync>> {
+        let endpoint = reqwest::Url::from_str(&format!("{}/channels/{}/threads", self.base_url, channel_id))?;
+
+        let response = self.client.post(endpoint)
+            .headers(self.headers.clone())
+            .json(thread)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("discord returned {} creating thread {}\n{}", status, thread.name, body).into())
+        }
+
+        Ok(response.json::<Channel>().await?)
+    }
+
+    /// adds a user to a thread, which is the only way into a private one
+    pub async fn add_thread_member(&self, thread_id: Snowflake, user_id: Snowflake) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let endpoint = reqwest::Url::from_str(&format!("{}/channels/{}/thread-members/{}", self.base_url, thread_id, user_id))?;
+
+        let response = self.client.put(endpoint)
+            .headers(self.headers.clone())
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("discord returned {} adding {} to thread {}\n{}", status, user_id, thread_id, body).into())
+        }
+
+        Ok(())
+    }
+
+    /// fetches a file users uploaded, such as an attachment option of a command. the cdn
+    /// doesn't need the bot token, so it isn't sent along
+    pub async fn download_attachment(&self, url: &str) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let endpoint = reqwest::Url::from_str(url)?;
+
+        let response = self.client.get(endpoint)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(format!("discord returned {} downloading an attachment", response.status()).into())
+        }
+
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    /// replaces the content of the original response to an interaction, such as a deferred one
+    pub async fn edit_original_response(&self, application_id: Snowflake, interaction_token: &str, message: &MessageObject) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.edit_webhook_message(application_id, interaction_token, "@original", message).await
+    }
+
+    /// replaces the original response with a message carrying a file, such as an export
+    pub async fn edit_original_response_with_file(&self, application_id: Snowflake, interaction_token: &str, mut message: MessageObject, filename: &str, content: Vec<u8>) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let endpoint = reqwest::Url::from_str(&format!("{}/webhooks/{}/{}/messages/@original", self.base_url, application_id, interaction_token))?;
+
+        message.attachments = Some(vec![Attachment {
+            id: 0,
+            filename: filename.to_string(),
+            description: None
+        }]);
+
+        let form = multipart::Form::new()
+            .part("payload_json", multipart::Part::text(serde_json::to_string(&message)?).mime_str("application/json")?)
+            .part("files[0]", multipart::Part::bytes(content).file_name(filename.to_string()));
+
+        let response = self.client.patch(endpoint)
+            .multipart(form)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("discord returned {} uploading {}\n{}", status, filename, body).into())
+        }
+
+        Ok(())
+    }
+
+    /// replaces the content of a message sent through an interaction's webhook, either
+    /// "@original" or the id of a follow-up
+    pub async fn edit_webhook_message(&self, application_id: Snowflake, interaction_token: &str, message_id: &str, message: &MessageObject) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let endpoint = reqwest::Url::from_str(&format!("{}/webhooks/{}/{}/messages/{}", self.base_url, application_id, interaction_token, message_id))?;
+
+        let response = self.client.patch(endpoint)
+            .json(message)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("discord returned {} editing message {}\n{}", status, message_id, body).into())
+        }
+
+        Ok(())
+    }
+
+    /// sends a follow-up message to an interaction, returning the new message's id
+    pub async fn create_followup_message(&self, application_id: Snowflake, interaction_token: &str, message: &MessageObject) -> Result<Snowflake, Box<dyn Error + Send + Sync>> {
+        let endpoint = reqwest::Url::from_str(&format!("{}/webhooks/{}/{}", self.base_url, application_id, interaction_token))?;
+
+        let response = self.client.post(endpoint)
+            .query(&[("wait", "true")])
+            .json(message)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("discord returned {} creating follow-up message\n{}", status, body).into())
+        }
+
+        let created: CreatedMessage = response.json().await?;
+        Ok(created.id)
+    }
+
+    /// posts content of any length without waiting for it, split into as many messages
+    /// as it takes and sent in order
+    pub fn spawn_long_message(&self, channel_id: Snowflake, content: &str) {
+        let client = self.clone();
+        let pages = split_message(content);
+
+        tokio::spawn(async move {
+            for page in pages {
+                let message = MessageObject {
+                    content: page,
+                    ..Default::default()
+                };
+
+                if let Err(e) = client.create_message(channel_id, &message).await {
+                    log::error!("could not post message to channel {}\n{}", channel_id, e);
+                    return
+                }
+            }
+        });
+    }
+
+    /// posts a message without waiting for it, logging any failure
+    pub fn spawn_message(&self, channel_id: Snowflake, message: MessageObject) {
+        let client = self.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = client.create_message(channel_id, &message).await {
+                log::error!("could not post message to channel {}\n{}", channel_id, e);
+            }
+        });
+    }
+}
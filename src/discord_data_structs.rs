@@ -1,35 +1,136 @@
-use std::hash::Hash;
 use std::collections::HashMap;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::{de, ser};
+use serde::de::DeserializeOwned;
+use serde::ser::SerializeMap;
+use super::permissions::Permissions;
+use super::snowflake::Snowflake;
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
-#[non_exhaustive]
-pub enum AuthorizingIntegrationOwner {
-    GuildInstall(Option<u64>),
-    UserInstall(u64),
+/// an enum discord sends as a bare number. numbers it adds later land in `Unknown` instead of
+/// failing the whole payload
+macro_rules! discord_enum {
+    ($(#[$meta:meta])* $name:ident { $($(#[$variant_meta:meta])* $variant:ident = $value:literal,)* }) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+        #[serde(from = "u8", into = "u8")]
+        pub enum $name {
+            $($(#[$variant_meta])* $variant,)*
+            Unknown(u8),
+        }
+
+        impl From<u8> for $name {
+            fn from(value: u8) -> $name {
+                match value {
+                    $($value => $name::$variant,)*
+                    other => $name::Unknown(other)
+                }
+            }
+        }
+
+        impl From<$name> for u8 {
+            fn from(value: $name) -> u8 {
+                match value {
+                    $($name::$variant => $value,)*
+                    $name::Unknown(other) => other
+                }
+            }
+        }
+    };
+}
+
+discord_enum! {
+    InteractionType {
+        Ping = 1,
+        ApplicationCommand = 2,
+        MessageComponent = 3,
+        ApplicationCommandAutocomplete = 4,
+        ModalSubmit = 5,
+    }
+}
+
+discord_enum! {
+    ChannelType {
+        GuildText = 0,
+        Dm = 1,
+        GuildVoice = 2,
+        GroupDm = 3,
+        GuildCategory = 4,
+        GuildAnnouncement = 5,
+        AnnouncementThread = 10,
+        PublicThread = 11,
+        PrivateThread = 12,
+        GuildStageVoice = 13,
+        GuildDirectory = 14,
+        GuildForum = 15,
+        GuildMedia = 16,
+    }
+}
+
+discord_enum! {
+    /// type of a command option, both when registering commands and in invocations
+    OptionType {
+        SubCommand = 1,
+        SubCommandGroup = 2,
+        String = 3,
+        Integer = 4,
+        Boolean = 5,
+        User = 6,
+        Channel = 7,
+        Role = 8,
+        Mentionable = 9,
+        Number = 10,
+        Attachment = 11,
+    }
+}
+
+discord_enum! {
+    CommandType {
+        /// slash command
+        ChatInput = 1,
+        /// context menu command on a user
+        User = 2,
+        /// context menu command on a message
+        Message = 3,
+        PrimaryEntryPoint = 4,
+    }
+}
+
+discord_enum! {
+    #[derive(Default)]
+    ComponentType {
+        #[default]
+        ActionRow = 1,
+        Button = 2,
+        StringSelect = 3,
+        TextInput = 4,
+        UserSelect = 5,
+        RoleSelect = 6,
+        MentionableSelect = 7,
+        ChannelSelect = 8,
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Interaction {
-    pub id: String,
-
-    pub application_id: String,
+    pub id: Snowflake,
 
-    pub r#type: u8,
+    pub application_id: Snowflake,
 
-    pub data: Option<AppCommand>,
+    /// the interaction's `type` with the `data` that comes with it
+    #[serde(flatten)]
+    pub data: InteractionData,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub guild: Option<Guild>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub guild_id: Option<String>,
+    pub guild_id: Option<Snowflake>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub channel: Option<Channel>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub channel_id: Option<String>,
+    pub channel_id: Option<Snowflake>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub member: Option<Member>,
@@ -41,10 +142,13 @@ pub struct Interaction {
 
     pub version: u32,
 
+    /// message the component was attached to, only sent for component interactions
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub message: Option<String>,
+    pub message: Option<Message>,
 
-    pub app_permissions: String,
+    /// permissions of the bot in the channel as a decimal string, missing for pings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_permissions: Option<String>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub locale: Option<String>,
@@ -54,53 +158,714 @@ pub struct Interaction {
 
     pub entitlements: Option<Vec<Entitlement>>,
 
-    pub authorizing_integration_owners: Option<HashMap<String, String>>, 
+    /// installation that authorized the interaction, keyed by integration type: "0" for
+    /// servers, "1" for users
+    pub authorizing_integration_owners: Option<HashMap<String, Snowflake>>,
 
+    /// where the command was invoked: 0 in a server, 1 in the bot's DM, 2 in other private channels
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub context: Option<u32>,
+    pub context: Option<u8>,
+
+    /// largest upload allowed in the channel, in bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attachment_size_limit: Option<u64>,
+}
+impl Interaction {
+    /// id of the user that triggered the interaction, whether it came from a guild or a DM
+    pub fn user_id(&self) -> Option<Snowflake> {
+        if let Some(member) = &self.member
+            && let Some(user) = &member.user {
+            return Some(user.id)
+        }
+
+        self.user.as_ref().map(|u| u.id)
+    }
+
+    /// display name of the user that triggered the interaction
+    pub fn user_name(&self) -> String {
+        if let Some(member) = &self.member {
+            if let Some(nick) = &member.nick {
+                return nick.clone()
+            }
+            if let Some(user) = &member.user {
+                return user.username.clone()
+            }
+        }
+
+        match &self.user {
+            Some(u) => u.username.clone(),
+            None => String::from("unknown")
+        }
+    }
+
+    /// role ids of the member, empty outside of servers
+    pub fn member_roles(&self) -> &[Snowflake] {
+        self.member.as_ref()
+            .and_then(|m| m.roles.as_deref())
+            .unwrap_or(&[])
+    }
+
+    /// the member's permissions in the channel, overwrites included. nothing outside of servers
+    pub fn member_permissions(&self) -> Permissions {
+        self.member.as_ref()
+            .and_then(|m| m.permissions.as_deref())
+            .map(Permissions::parse)
+            .unwrap_or_default()
+    }
+
+    /// what the bot itself may do in the channel
+    pub fn app_permissions(&self) -> Permissions {
+        self.app_permissions.as_deref()
+            .map(Permissions::parse)
+            .unwrap_or_default()
+    }
+
+    pub fn kind(&self) -> InteractionType {
+        self.data.kind()
+    }
+
+    /// the invoked command, for slash commands and their autocomplete
+    pub fn command(&self) -> Option<&CommandData> {
+        match &self.data {
+            InteractionData::ApplicationCommand(command) | InteractionData::Autocomplete(command) => Some(command),
+            _ => None
+        }
+    }
+
+    pub fn command_name(&self) -> Option<&str> {
+        self.command().map(|c| c.name.as_str())
+    }
+
+    /// custom id of the clicked component or submitted modal
+    pub fn custom_id(&self) -> Option<&str> {
+        match &self.data {
+            InteractionData::MessageComponent(component) => Some(&component.custom_id),
+            InteractionData::ModalSubmit(modal) => Some(&modal.custom_id),
+            _ => None
+        }
+    }
+
+    /// options of the invoked command, flattened through a single level of subcommand
+    pub fn options(&self) -> &[CommandDataOption] {
+        let options: &[CommandDataOption] = match self.command() {
+            Some(command) => command.options.as_deref().unwrap_or(&[]),
+            None => &[]
+        };
+
+        match options.first() {
+            Some(first) if first.r#type == OptionType::SubCommand => match &first.options {
+                Some(sub) => sub,
+                None => &[]
+            },
+            _ => options
+        }
+    }
+
+    /// name of the invoked subcommand, if the command has any
+    pub fn subcommand(&self) -> Option<&str> {
+        let first = self.command()?.options.as_ref()?.first()?;
+        if first.r#type == OptionType::SubCommand {
+            Some(&first.name)
+        } else {
+            None
+        }
+    }
+
+    pub fn option(&self, name: &str) -> Option<&CommandDataOption> {
+        self.options().iter().find(|o| o.name == name)
+    }
+
+    pub fn option_str(&self, name: &str) -> Option<&str> {
+        self.option(name)?.value.as_ref()?.as_str()
+    }
+
+    pub fn option_i64(&self, name: &str) -> Option<i64> {
+        self.option(name)?.value.as_ref()?.as_i64()
+    }
+
+    pub fn option_bool(&self, name: &str) -> Option<bool> {
+        self.option(name)?.value.as_ref()?.as_bool()
+    }
+
+    /// id picked in a user, channel, role, mentionable or attachment option
+    pub fn option_snowflake(&self, name: &str) -> Option<Snowflake> {
+        self.option_str(name)?.parse().ok()
+    }
+
+    /// the file uploaded through an attachment option
+    pub fn option_attachment(&self, name: &str) -> Option<&ResolvedAttachment> {
+        let id = self.option_snowflake(name)?;
+        self.command()?.resolved.as_ref()?.attachments.as_ref()?.get(&id)
+    }
+
+    /// the channel picked in a channel option
+    pub fn option_channel(&self, name: &str) -> Option<&ResolvedChannel> {
+        let id = self.option_snowflake(name)?;
+        self.command()?.resolved.as_ref()?.channels.as_ref()?.get(&id)
+    }
+
+    /// value of a text input in a submitted modal
+    pub fn text_input(&self, custom_id: &str) -> Option<&str> {
+        let modal = match &self.data {
+            InteractionData::ModalSubmit(modal) => modal,
+            _ => return None
+        };
+
+        modal.components.iter()
+            .flat_map(|row| row.components.iter().flatten())
+            .find(|c| c.custom_id.as_deref() == Some(custom_id))?
+            .value.as_deref()
+    }
+
+    /// the option currently being typed in an autocomplete interaction
+    pub fn focused_option(&self) -> Option<&CommandDataOption> {
+        self.options().iter().find(|o| o.focused == Some(true))
+    }
+}
 
-    pub attachment_size_limit: u32,
+/// what an interaction is about. discord sends the type next to the data, and the data's
+/// shape depends on the type
+#[derive(Debug)]
+pub enum InteractionData {
+    Ping,
+    ApplicationCommand(CommandData),
+    MessageComponent(ComponentData),
+    Autocomplete(CommandData),
+    ModalSubmit(ModalSubmitData),
+
+    /// a type added after the bot was written, its data kept as sent
+    Unknown(u8, Option<serde_json::Value>),
+}
+
+impl InteractionData {
+    pub fn kind(&self) -> InteractionType {
+        match self {
+            InteractionData::Ping => InteractionType::Ping,
+            InteractionData::ApplicationCommand(_) => InteractionType::ApplicationCommand,
+            InteractionData::MessageComponent(_) => InteractionType::MessageComponent,
+            InteractionData::Autocomplete(_) => InteractionType::ApplicationCommandAutocomplete,
+            InteractionData::ModalSubmit(_) => InteractionType::ModalSubmit,
+            InteractionData::Unknown(t, _) => InteractionType::Unknown(*t)
+        }
+    }
 }
+
+/// `type` and `data` of an interaction as they are on the wire
+#[derive(Serialize, Deserialize)]
+struct TaggedData {
+    r#type: InteractionType,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data: Option<serde_json::Value>,
+}
+
+/// reads the data of an interaction type that requires it
+fn read_data<T: DeserializeOwned, E: de::Error>(data: Option<serde_json::Value>) -> Result<T, E> {
+    let data = data.ok_or_else(|| E::missing_field("data"))?;
+    serde_json::from_value(data).map_err(E::custom)
+}
+
+impl<'de> Deserialize<'de> for InteractionData {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<InteractionData, D::Error> {
+        let TaggedData { r#type, data } = TaggedData::deserialize(deserializer)?;
+
+        Ok(match r#type {
+            InteractionType::Ping => InteractionData::Ping,
+            InteractionType::ApplicationCommand => InteractionData::ApplicationCommand(read_data(data)?),
+            InteractionType::MessageComponent => InteractionData::MessageComponent(read_data(data)?),
+            InteractionType::ApplicationCommandAutocomplete => InteractionData::Autocomplete(read_data(data)?),
+            InteractionType::ModalSubmit => InteractionData::ModalSubmit(read_data(data)?),
+            InteractionType::Unknown(t) => InteractionData::Unknown(t, data)
+        })
+    }
+}
+
+impl Serialize for InteractionData {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let data = match self {
+            InteractionData::Ping => None,
+            InteractionData::ApplicationCommand(command) | InteractionData::Autocomplete(command) => Some(serde_json::to_value(command)),
+            InteractionData::MessageComponent(component) => Some(serde_json::to_value(component)),
+            InteractionData::ModalSubmit(modal) => Some(serde_json::to_value(modal)),
+            InteractionData::Unknown(_, data) => data.clone().map(Ok)
+        };
+
+        TaggedData {
+            r#type: self.kind(),
+            data: data.transpose().map_err(ser::Error::custom)?
+        }.serialize(serializer)
+    }
+}
+
+/// data of a slash command, or of the command being typed for autocomplete
 #[derive(Serialize, Deserialize, Debug)]
-pub struct AppCommand {
+pub struct CommandData {
+    pub id: Snowflake,
     pub name: String,
-    pub r#type: u8,
-    pub id: String,
+
+    pub r#type: CommandType,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guild_id: Option<Snowflake>,
+
+    /// user or message a context menu command was used on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_id: Option<Snowflake>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<Vec<CommandDataOption>>,
+
+    /// full objects for the ids in attachment, user and channel options
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolved: Option<ResolvedData>,
 }
-#[derive(Deserialize, Serialize, Debug)]
-pub struct ResponseOject {
-    pub r#type: u8,
-    pub data: Option<MessageObject>
+
+/// data of a clicked button or a picked select menu option
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ComponentData {
+    pub custom_id: String,
+    pub component_type: ComponentType,
+
+    /// picked options, only sent for select menus
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub values: Option<Vec<String>>,
+}
+
+/// the filled in text inputs of a submitted modal
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ModalSubmitData {
+    pub custom_id: String,
+    pub components: Vec<Component>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ResolvedData {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub users: Option<HashMap<Snowflake, User>>,
+
+    /// members of the users above, without their `user`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub members: Option<HashMap<Snowflake, Member>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channels: Option<HashMap<Snowflake, ResolvedChannel>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attachments: Option<HashMap<Snowflake, ResolvedAttachment>>,
+}
+
+/// the part of a channel resolved data carries
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResolvedChannel {
+    pub id: Snowflake,
+    pub r#type: ChannelType,
+    pub name: Option<String>,
+    pub parent_id: Option<Snowflake>,
+    pub permissions: Option<String>,
+}
+
+/// a file a user uploaded through an attachment option, downloadable from `url`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResolvedAttachment {
+    pub id: Snowflake,
+    pub filename: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+
+    /// in bytes
+    pub size: u64,
+    pub url: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CommandDataOption {
+    pub name: String,
+    pub r#type: OptionType,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<serde_json::Value>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<Vec<CommandDataOption>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub focused: Option<bool>,
+}
+discord_enum! {
+    /// how an interaction is answered
+    InteractionCallbackType {
+        Pong = 1,
+        ChannelMessageWithSource = 4,
+        DeferredChannelMessageWithSource = 5,
+        DeferredUpdateMessage = 6,
+        UpdateMessage = 7,
+        ApplicationCommandAutocompleteResult = 8,
+        Modal = 9,
+    }
+}
+
+/// the answer to an interaction. every variant carries exactly the data its callback type
+/// takes, written as `{"type": n, "data": {...}}`
+#[derive(Debug, Clone)]
+pub enum ResponseOject {
+    /// acknowledges a ping
+    Pong,
+
+    /// posts a message into the channel
+    Message(MessageObject),
+
+    /// shows "thinking..." until the original response is edited
+    Deferred { ephemeral: bool },
+
+    /// replaces the message a clicked component is attached to
+    Update(MessageObject),
+
+    Autocomplete(AutocompleteResponse),
+
+    /// opens a popup form, submitted back as a modal submit interaction
+    Modal(ModalData),
 }
 
 impl ResponseOject {
     pub fn new(message: String) -> ResponseOject {
 
         let message = MessageObject{
-            content: message
+            content: message,
+            ..Default::default()
         };
 
-        ResponseOject {
-            r#type: 4,
-            data: Some(message)
-        } 
+        ResponseOject::Message(message)
+    }
+
+    pub fn with_components(message: String, components: Vec<Component>) -> ResponseOject {
+
+        let message = MessageObject{
+            content: message,
+            components: Some(components),
+            ..Default::default()
+        };
+
+        ResponseOject::Message(message)
+    }
+
+    /// replaces the message a clicked component is attached to
+    pub fn update(message: String, components: Vec<Component>) -> ResponseOject {
+
+        let message = MessageObject{
+            content: message,
+            components: Some(components),
+            ..Default::default()
+        };
+
+        ResponseOject::Update(message)
+    }
+
+    /// acknowledges the interaction, showing "thinking..." until the original response is edited
+    pub fn deferred() -> ResponseOject {
+        ResponseOject::Deferred { ephemeral: false }
+    }
+
+    /// opens a popup form, submitted back as a modal submit interaction
+    pub fn modal(custom_id: &str, title: &str, components: Vec<Component>) -> ResponseOject {
+        ResponseOject::Modal(ModalData {
+            custom_id: custom_id.to_string(),
+            title: title.to_string(),
+            components
+        })
+    }
+
+    /// like `deferred`, the eventual response only visible to the user
+    pub fn deferred_ephemeral() -> ResponseOject {
+        ResponseOject::Deferred { ephemeral: true }
+    }
+
+    pub fn with_embeds(message: String, embeds: Vec<Embed>) -> ResponseOject {
+
+        let message = MessageObject{
+            content: message,
+            embeds: Some(embeds),
+            ..Default::default()
+        };
+
+        ResponseOject::Message(message)
+    }
+
+    /// response only visible to the user that triggered the interaction
+    pub fn ephemeral(message: String) -> ResponseOject {
+
+        let message = MessageObject{
+            content: message,
+            flags: Some(EPHEMERAL_FLAG),
+            ..Default::default()
+        };
+
+        ResponseOject::Message(message)
+    }
+
+    pub fn callback_type(&self) -> InteractionCallbackType {
+        match self {
+            ResponseOject::Pong => InteractionCallbackType::Pong,
+            ResponseOject::Message(_) => InteractionCallbackType::ChannelMessageWithSource,
+            ResponseOject::Deferred { .. } => InteractionCallbackType::DeferredChannelMessageWithSource,
+            ResponseOject::Update(_) => InteractionCallbackType::UpdateMessage,
+            ResponseOject::Autocomplete(_) => InteractionCallbackType::ApplicationCommandAutocompleteResult,
+            ResponseOject::Modal(_) => InteractionCallbackType::Modal
+        }
+    }
+
+    /// the message the response posts or updates, `None` for the other callbacks
+    pub fn message_mut(&mut self) -> Option<&mut MessageObject> {
+        match self {
+            ResponseOject::Message(message) | ResponseOject::Update(message) => Some(message),
+            _ => None
+        }
     }
 }
-#[derive(Deserialize, Serialize, Debug)]
-pub struct Pong {
-    pub r#type: u8,
+
+impl Serialize for ResponseOject {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("type", &self.callback_type())?;
+        match self {
+            ResponseOject::Pong | ResponseOject::Deferred { ephemeral: false } => {},
+            ResponseOject::Deferred { ephemeral: true } => map.serialize_entry("data", &serde_json::json!({ "flags": EPHEMERAL_FLAG }))?,
+            ResponseOject::Message(message) | ResponseOject::Update(message) => map.serialize_entry("data", message)?,
+            ResponseOject::Autocomplete(autocomplete) => map.serialize_entry("data", autocomplete)?,
+            ResponseOject::Modal(modal) => map.serialize_entry("data", modal)?
+        }
+        map.end()
+    }
 }
-#[derive(Deserialize, Serialize, Debug)]
+
+pub const EPHEMERAL_FLAG: u32 = 1 << 6;
+
+pub const MAX_AUTOCOMPLETE_CHOICES: usize = 25;
+
+pub const MAX_MESSAGE_LENGTH: usize = 2000;
+
+/// suggestions for the option being typed, at most `MAX_AUTOCOMPLETE_CHOICES`
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct AutocompleteResponse {
+    pub choices: Vec<CommandOptionChoice>
+}
+
+impl AutocompleteResponse {
+    pub fn new(choices: Vec<CommandOptionChoice>) -> AutocompleteResponse {
+        AutocompleteResponse { choices }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct CommandOptionChoice {
+    pub name: String,
+    pub value: serde_json::Value,
+
+    /// translated names keyed by discord locale
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name_localizations: Option<HashMap<String, String>>
+}
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct MessageObject{
-    pub content: String
+    pub content: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flags: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub components: Option<Vec<Component>>,
+
+    /// files uploaded alongside the message, matched to the multipart parts by id
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attachments: Option<Vec<Attachment>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embeds: Option<Vec<Embed>>
+}
+
+/// a popup form of text inputs, each in an action row of its own
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ModalData {
+    pub custom_id: String,
+    pub title: String,
+    pub components: Vec<Component>
+}
+
+/// attachment metadata for a message. `id` is the index of the `files[n]` multipart part
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Attachment {
+    pub id: u32,
+    pub filename: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>
+}
+
+/// discord allows up to 25 fields per embed
+pub const MAX_EMBED_FIELDS: usize = 25;
+
+/// rich card shown below a message's content
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct Embed {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// rgb colour of the stripe on the left
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<Vec<EmbedField>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub footer: Option<EmbedFooter>
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct EmbedField {
+    pub name: String,
+    pub value: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inline: Option<bool>
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct EmbedFooter {
+    pub text: String
+}
+
+pub const BUTTON_STYLE_PRIMARY: u8 = 1;
+pub const BUTTON_STYLE_SECONDARY: u8 = 2;
+pub const BUTTON_STYLE_SUCCESS: u8 = 3;
+pub const BUTTON_STYLE_DANGER: u8 = 4;
+
+pub const TEXT_INPUT_SHORT: u8 = 1;
+pub const TEXT_INPUT_PARAGRAPH: u8 = 2;
+
+/// message component: an action row, or a button or text input inside one
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct Component {
+    pub r#type: ComponentType,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub style: Option<u8>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub custom_id: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disabled: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub components: Option<Vec<Component>>,
+
+    /// text of a text input, prefilled when sent and typed in when a modal is submitted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required: Option<bool>
+}
+
+impl Component {
+    pub fn action_row(components: Vec<Component>) -> Component {
+        Component {
+            r#type: ComponentType::ActionRow,
+            components: Some(components),
+            ..Default::default()
+        }
+    }
+
+    pub fn button(style: u8, label: &str, custom_id: &str) -> Component {
+        Component {
+            r#type: ComponentType::Button,
+            style: Some(style),
+            label: Some(label.to_string()),
+            custom_id: Some(custom_id.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// text input for a modal, which has to sit in its own action row
+    pub fn text_input(style: u8, label: &str, custom_id: &str, value: &str, required: bool) -> Component {
+        Component {
+            r#type: ComponentType::TextInput,
+            style: Some(style),
+            label: Some(label.to_string()),
+            custom_id: Some(custom_id.to_string()),
+            value: if value.is_empty() { None } else { Some(value.to_string()) },
+            required: Some(required),
+            ..Default::default()
+        }
+    }
 }
 
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct Command {
     pub name: String,
-    pub r#type: u8,
-    pub description: String
+    pub r#type: CommandType,
+    pub description: String,
+
+    /// translated names and descriptions keyed by discord locale
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name_localizations: Option<HashMap<String, String>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description_localizations: Option<HashMap<String, String>>,
+
+    /// permission bits a member needs to see the command, as a decimal string
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_member_permissions: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<Vec<CommandOption>>
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct CommandOption {
+    pub name: String,
+    pub r#type: OptionType,
+    pub description: String,
+
+    /// translated names and descriptions keyed by discord locale
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name_localizations: Option<HashMap<String, String>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description_localizations: Option<HashMap<String, String>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub autocomplete: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub choices: Option<Vec<CommandOptionChoice>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_value: Option<i64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_value: Option<i64>,
+
+    /// bounds on the length of a string option
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_length: Option<u16>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<u16>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<Vec<CommandOption>>
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -108,21 +873,26 @@ pub struct Commands {
     pub commands: Vec<Command>
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct User {
-	pub id: String,
-	
-    pub username: String, 
-	
+    pub id: Snowflake,
+
+    pub username: String,
+
+    /// "0" for users that moved to unique usernames
     pub discriminator: String,
-	
-    pub global_name: String,
-   
-    pub avatar: String,
-  
-	pub bot: Option<bool>,
- 
-	pub system: Option<bool>,
+
+    /// display name, null unless the user picked one
+    pub global_name: Option<String>,
+
+    /// avatar hash, null for the default avatar
+    pub avatar: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bot: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<bool>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub mfa_enabled: Option<bool>,
@@ -148,122 +918,206 @@ pub struct User {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub premium_type: Option<u32>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub public_flags: Option<u32>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub avatar_decoration_data: Option<AvatarDecorationData>,
 
+    /// cosmetics such as nameplates, kept as sent since the bot doesn't show them
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub collectibles: Option<String>,
+    pub collectibles: Option<serde_json::Value>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub primary_guild: Option<PrimaryGuild>
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct AvatarDecorationData {
     pub asset: String,
-    pub sku_id: String
+    pub sku_id: Option<Snowflake>
 }
 
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct PrimaryGuild {
-    pub identity_guild_id: Option<String>,
+    pub identity_guild_id: Option<Snowflake>,
     pub identity_enabled: Option<bool>,
-    pub tag: String,
-    pub badge: String
+    pub tag: Option<String>,
+    pub badge: Option<String>
 }
-#[derive(Deserialize, Serialize, Debug)]
-pub struct AuthorizingIntegrationOwners(pub Vec<AuthorizingIntegrationOwner>);
 
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct Entitlement {
-    pub id: String,
-    pub sku_id: Option<String>,
-    pub application_id: String,
-    pub user_id: Option<String>,
+    pub id: Snowflake,
+    pub sku_id: Option<Snowflake>,
+    pub application_id: Snowflake,
+    pub user_id: Option<Snowflake>,
     pub r#type: u32,
     pub deleted: bool,
     pub starts_at: Option<String>,
     pub ends_at: Option<String>,
-    pub guild_id: Option<String>,
-    pub consumed: bool
+    pub guild_id: Option<Snowflake>,
+    pub consumed: Option<bool>
 }
 #[derive(Deserialize, Serialize, Debug)]
 pub struct Channel {
-    pub id: String,
-    pub r#type: u8,
-    pub guild_id: Option<String>,
+    pub id: Snowflake,
+    pub r#type: ChannelType,
+    pub guild_id: Option<Snowflake>,
     pub position: Option<i32>,
 
-    #[serde(skip)]
-    pub permission_overwrites: Option<String>,
+    pub permission_overwrites: Option<Vec<Overwrite>>,
     pub name: Option<String>,
     pub topic: Option<String>,
     pub nsfw: Option<bool>,
-    pub last_message_id: Option<String>,
+    pub last_message_id: Option<Snowflake>,
     pub bitrate: Option<i32>,
     pub user_limit: Option<i32>,
     pub rate_limit_per_user: Option<i32>,
-    pub recipients: Option<User>,
+    pub recipients: Option<Vec<User>>,
     pub icon: Option<String>,
-    pub owner_id: Option<String>,
-    pub application_id: Option<String>,
-    pub parent_id: Option<String>,
+    pub owner_id: Option<Snowflake>,
+    pub application_id: Option<Snowflake>,
+    pub parent_id: Option<Snowflake>,
     pub last_pin_timestamp: Option<String>,
     pub rtc_region: Option<String>,
     pub video_quality_mode: Option<i32>,
     pub message_count: Option<i32>,
     pub member_count: Option<i32>,
-    #[serde(skip)]
-    pub thread_metadata: Option<String>,
-    #[serde(skip)]
-    pub member: Option<String>,
+
+    /// only sent for threads
+    pub thread_metadata: Option<ThreadMetadata>,
+
+    /// the bot's own membership, only sent for threads it has joined
+    pub member: Option<ThreadMember>,
     pub default_auto_archive_duration: Option<i32>,
     pub permissions: Option<String>,
     pub flags: Option<i32>,
     pub total_message_sent: Option<i32>,
-    #[serde(skip)]
-    pub available_tags: Option<String>,
-    pub applied_tags: Option<Vec<String>>,
-    #[serde(skip)]
-    pub default_reaction_emoji: Option<String>,
+
+    /// tags posts can be given, forum and media channels only
+    pub available_tags: Option<Vec<ForumTag>>,
+    pub applied_tags: Option<Vec<Snowflake>>,
+    pub default_reaction_emoji: Option<DefaultReaction>,
     pub default_thread_rate_limit_per_user: Option<i32>,
     pub default_sort_order: Option<i32>,
     pub default_forum_layout: Option<i32>
 }
 
+discord_enum! {
+    OverwriteType {
+        Role = 0,
+        Member = 1,
+    }
+}
+
+/// permissions granted or taken away in one channel, for a role or a member
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Overwrite {
+    pub id: Snowflake,
+    pub r#type: OverwriteType,
+    pub allow: String,
+    pub deny: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ForumTag {
+    pub id: Snowflake,
+    pub name: String,
+
+    /// whether only members who can manage threads may apply it
+    #[serde(default)]
+    pub moderated: bool,
+    pub emoji_id: Option<Snowflake>,
+    pub emoji_name: Option<String>,
+}
+
+/// the reaction shown on new forum posts, a custom emoji by id or a unicode emoji by name
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct DefaultReaction {
+    pub emoji_id: Option<Snowflake>,
+    pub emoji_name: Option<String>,
+}
+
+impl ChannelType {
+    pub fn is_thread(self) -> bool {
+        matches!(self, ChannelType::AnnouncementThread | ChannelType::PublicThread | ChannelType::PrivateThread)
+    }
+}
+
+impl Channel {
+    pub fn is_thread(&self) -> bool {
+        self.r#type.is_thread()
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ThreadMetadata {
+    pub archived: bool,
+
+    /// minutes without activity before the thread is archived: 60, 1440, 4320 or 10080
+    pub auto_archive_duration: u32,
+    pub archive_timestamp: String,
+    pub locked: bool,
+
+    /// whether members who aren't moderators can add others, private threads only
+    pub invitable: Option<bool>,
+    pub create_timestamp: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ThreadMember {
+    /// the thread, left out in some gateway events
+    pub id: Option<Snowflake>,
+    pub user_id: Option<Snowflake>,
+    pub join_timestamp: String,
+    pub flags: u32,
+}
+
+/// body of a request starting a thread that isn't attached to a message
+#[derive(Deserialize, Serialize, Debug)]
+pub struct StartThread {
+    pub name: String,
+    pub r#type: ChannelType,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_archive_duration: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub invitable: Option<bool>,
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct Guild {
-    pub id: Option<String>,
+    pub id: Option<Snowflake>,
     pub name: Option<String>,
     pub icon: Option<String>,
     pub icon_hash: Option<String>,
     pub splash: Option<String>,
     pub discovery_splash: Option<String>,
     pub owner: Option<bool>,
-    pub owner_id: Option<String>,
+    pub owner_id: Option<Snowflake>,
     pub permissions: Option<String>,
     pub region: Option<String>,
-    pub afk_channel_id: Option<String>,
+    pub afk_channel_id: Option<Snowflake>,
     pub afk_timeout: Option<u32>,
     pub widget_enabled: Option<bool>,
-    pub widget_channel_id: Option<String>,
+    pub widget_channel_id: Option<Snowflake>,
     pub verification_level: Option<u8>,
     pub default_message_notifications: Option<u8>,
     pub explicit_content_filter: Option<u8>,
-    #[serde(skip)]
-    pub roles: Option<String>,
-    #[serde(skip)]
-    pub emojis: Option<String>,
-    #[serde(skip)]
-    pub features: Option<String>,
+    pub roles: Option<Vec<Role>>,
+    pub emojis: Option<Vec<Emoji>>,
+
+    /// kept as strings, discord adds new features all the time
+    pub features: Option<Vec<String>>,
     pub mfa_level: Option<u8>,
-    pub application_id: Option<String>,
-    pub system_channel_id: Option<String>,
+    pub application_id: Option<Snowflake>,
+    pub system_channel_id: Option<Snowflake>,
     pub system_channel_flags: Option<u32>,
-    pub rules_channel_id: Option<String>,
+    pub rules_channel_id: Option<Snowflake>,
     pub max_presences: Option<u32>,
     pub max_members: Option<u32>,
     pub vanity_url_code: Option<String>,
@@ -272,28 +1126,139 @@ pub struct Guild {
     pub premium_tier: Option<u8>,
     pub premium_subscription_count: Option<u32>,
     pub preferred_locale: Option<String>,
-    pub public_updates_channel_id: Option<String>,
+    pub public_updates_channel_id: Option<Snowflake>,
     pub max_video_channel_users: Option<u32>,
     pub max_stage_video_channel_users: Option<u32>,
     pub approximate_member_count: Option<u32>,
     pub approximate_presence_count: Option<u32>,
-    pub welcome_screen: Option<String>,
+    pub welcome_screen: Option<WelcomeScreen>,
     pub nsfw_level: Option<u8>,
-    #[serde(skip)]
-    pub stickers: Option<String>,
+    pub stickers: Option<Vec<Sticker>>,
     pub premium_progress_bar_enabled: Option<bool>,
-    pub safety_alerts_channel_id: Option<String>,
-    #[serde(skip)]
-    pub incidents_data: Option<String>,
+    pub safety_alerts_channel_id: Option<Snowflake>,
+    pub incidents_data: Option<IncidentsData>,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Role {
+    pub id: Snowflake,
+    pub name: String,
+
+    #[serde(default)]
+    pub color: u32,
+
+    /// whether members are listed apart under the role
+    #[serde(default)]
+    pub hoist: bool,
+    pub icon: Option<String>,
+    pub unicode_emoji: Option<String>,
+
+    #[serde(default)]
+    pub position: i32,
+    pub permissions: String,
+
+    /// whether an integration such as a bot manages the role
+    #[serde(default)]
+    pub managed: bool,
+
+    #[serde(default)]
+    pub mentionable: bool,
+    pub tags: Option<RoleTags>,
+
+    #[serde(default)]
+    pub flags: u32,
+}
+
+/// what a role belongs to. discord sends the flags as keys set to null, so a key that is
+/// there means true
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct RoleTags {
+    pub bot_id: Option<Snowflake>,
+    pub integration_id: Option<Snowflake>,
+    pub subscription_listing_id: Option<Snowflake>,
+
+    #[serde(default, deserialize_with = "present", serialize_with = "as_present", skip_serializing_if = "is_false")]
+    pub premium_subscriber: bool,
+
+    #[serde(default, deserialize_with = "present", serialize_with = "as_present", skip_serializing_if = "is_false")]
+    pub available_for_purchase: bool,
+
+    #[serde(default, deserialize_with = "present", serialize_with = "as_present", skip_serializing_if = "is_false")]
+    pub guild_connections: bool,
+}
+
+fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    de::IgnoredAny::deserialize(deserializer).map(|_| true)
+}
+
+fn as_present<S: Serializer>(_: &bool, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_none()
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// what new members see before they start chatting, community servers only
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct WelcomeScreen {
+    pub description: Option<String>,
+
+    #[serde(default)]
+    pub welcome_channels: Vec<WelcomeScreenChannel>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct WelcomeScreenChannel {
+    pub channel_id: Snowflake,
+    pub description: String,
+    pub emoji_id: Option<Snowflake>,
+    pub emoji_name: Option<String>,
+}
+
+discord_enum! {
+    StickerFormatType {
+        Png = 1,
+        Apng = 2,
+        Lottie = 3,
+        Gif = 4,
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Sticker {
+    pub id: Snowflake,
+    pub name: String,
+    pub description: Option<String>,
+
+    /// autocomplete keywords, comma separated
+    #[serde(default)]
+    pub tags: String,
+
+    /// 1 for stickers of a standard pack, 2 for a server's own
+    pub r#type: u8,
+    pub format_type: StickerFormatType,
+    pub available: Option<bool>,
+    pub guild_id: Option<Snowflake>,
+    pub user: Option<User>,
+}
+
+/// ISO8601 timestamps of the server's raid protections, null while they are off
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct IncidentsData {
+    pub invites_disabled_until: Option<String>,
+    pub dms_disabled_until: Option<String>,
+    pub dm_spam_detected_at: Option<String>,
+    pub raid_detected_at: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Member {
     pub user: Option<User>,
     pub nick: Option<String>,
     pub avatar: Option<String>,
     pub banner: Option<String>,
-    pub roles: Option<Vec<String>>,
+    pub roles: Option<Vec<Snowflake>>,
     pub joined_at: Option<String>,
     pub premium_since: Option<String>,
     pub deaf: Option<bool>,
@@ -302,4 +1267,347 @@ pub struct Member {
     pub permissions: Option<String>,
     pub communication_disabled_until: Option<String>,
     pub avatar_decoration_data: Option<AvatarDecorationData>,
-}
\ No newline at end of file
+    pub flags: Option<u32>,
+}
+
+/// a message as discord sends it, with the parts the bot reads
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Message {
+    pub id: Snowflake,
+    pub channel_id: Snowflake,
+
+    /// missing for messages sent by webhooks on behalf of no user
+    pub author: Option<User>,
+    pub content: String,
+    pub timestamp: String,
+    pub edited_timestamp: Option<String>,
+
+    #[serde(default)]
+    pub mentions: Vec<User>,
+
+    #[serde(default)]
+    pub embeds: Vec<Embed>,
+
+    #[serde(default)]
+    pub components: Vec<Component>,
+
+    #[serde(default)]
+    pub r#type: u8,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flags: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guild_id: Option<Snowflake>,
+
+    /// the author's membership, without `user`. only sent in gateway events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub member: Option<Member>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhook_id: Option<Snowflake>,
+}
+
+discord_enum! {
+    /// what a gateway payload is for, in either direction
+    GatewayOpcode {
+        Dispatch = 0,
+        Heartbeat = 1,
+        Identify = 2,
+        PresenceUpdate = 3,
+        VoiceStateUpdate = 4,
+        Resume = 6,
+        Reconnect = 7,
+        RequestGuildMembers = 8,
+        InvalidSession = 9,
+        Hello = 10,
+        HeartbeatAck = 11,
+    }
+}
+
+/// a message sent over the gateway. `s` and `t` are only set for dispatched events
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GatewayPayload {
+    pub op: GatewayOpcode,
+
+    #[serde(default)]
+    pub d: serde_json::Value,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub s: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub t: Option<String>,
+}
+
+impl GatewayPayload {
+    pub fn new(op: GatewayOpcode, d: serde_json::Value) -> GatewayPayload {
+        GatewayPayload {
+            op,
+            d,
+            s: None,
+            t: None
+        }
+    }
+}
+
+/// first payload of every connection
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Hello {
+    /// milliseconds between heartbeats
+    pub heartbeat_interval: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct IdentifyProperties {
+    pub os: String,
+    pub browser: String,
+    pub device: String,
+}
+
+/// starts a new gateway session
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Identify {
+    pub token: String,
+    pub intents: u64,
+    pub properties: IdentifyProperties,
+}
+
+/// picks a dropped session back up, replaying the events missed since `seq`
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Resume {
+    pub token: String,
+    pub session_id: String,
+    pub seq: u64,
+}
+
+/// the part of READY the bot needs
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Ready {
+    pub user: User,
+    pub session_id: String,
+
+    /// where to connect when resuming this session
+    pub resume_gateway_url: String,
+}
+
+/// an emoji of a reaction or a server. custom emojis have an id, unicode emojis only a name
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Emoji {
+    pub id: Option<Snowflake>,
+    pub name: Option<String>,
+
+    /// roles allowed to use the emoji, everyone when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<Snowflake>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub animated: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub managed: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub available: Option<bool>,
+}
+
+/// MESSAGE_REACTION_ADD
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MessageReaction {
+    pub user_id: Snowflake,
+    pub channel_id: Snowflake,
+    pub message_id: Snowflake,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guild_id: Option<Snowflake>,
+    pub emoji: Emoji,
+}
+
+/// GUILD_MEMBER_REMOVE
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GuildMemberRemove {
+    pub guild_id: Snowflake,
+    pub user: User,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture<T: DeserializeOwned>(name: &str) -> T {
+        let path = format!("{}/tests/fixtures/{}.json", env!("CARGO_MANIFEST_DIR"), name);
+        let content = std::fs::read_to_string(&path).unwrap();
+        serde_json::from_str(&content).unwrap_or_else(|e| panic!("{} does not parse\n{}", name, e))
+    }
+
+    #[test]
+    fn pings_parse_without_guild_fields() {
+        let ping: Interaction = fixture("ping");
+        assert!(matches!(ping.data, InteractionData::Ping));
+        assert!(ping.app_permissions().is_empty());
+        assert_eq!(ping.attachment_size_limit, None);
+    }
+
+    #[test]
+    fn commands_parse_with_null_profile_fields() {
+        let command: Interaction = fixture("slash_command");
+        assert_eq!(command.kind(), InteractionType::ApplicationCommand);
+        assert_eq!(command.user_id(), Some(Snowflake::new(1183455781045665824)));
+        assert_eq!(command.user_name(), "ayla_of_the_vale");
+        assert_eq!(command.subcommand(), Some("create"));
+        assert_eq!(command.option_str("title"), Some("The Lost Lantern"));
+        assert_eq!(command.option_i64("xp"), Some(150));
+        assert_eq!(command.member_roles(), [Snowflake::new(1183460234019713086)]);
+        assert!(command.member_permissions().contains(Permissions::MANAGE_GUILD));
+        assert!(command.app_permissions().contains(Permissions::CREATE_PUBLIC_THREADS));
+        assert_eq!(command.channel.as_ref().map(|c| c.r#type), Some(ChannelType::GuildText));
+        assert!(matches!(&command.data, InteractionData::ApplicationCommand(c) if c.r#type == CommandType::ChatInput));
+
+        let user = command.member.as_ref().and_then(|m| m.user.as_ref()).unwrap();
+        assert_eq!(user.global_name, None);
+        assert_eq!(user.avatar, None);
+    }
+
+    #[test]
+    fn components_carry_their_message() {
+        let click: Interaction = fixture("component");
+        assert_eq!(click.kind(), InteractionType::MessageComponent);
+        assert_eq!(click.custom_id(), Some("session:yes:3"));
+        assert_eq!(click.user_id(), Some(Snowflake::new(1183455781045665824)));
+        assert_eq!(click.channel.as_ref().map(|c| c.r#type), Some(ChannelType::Dm));
+        assert!(matches!(&click.data, InteractionData::MessageComponent(c) if c.component_type == ComponentType::Button));
+
+        let message = click.message.as_ref().unwrap();
+        assert_eq!(message.id, Snowflake::new(1301337999988877766));
+        assert_eq!(message.components[0].r#type, ComponentType::ActionRow);
+        assert_eq!(message.components[0].components.as_ref().map(|c| c.len()), Some(2));
+        assert_eq!(message.author.as_ref().map(|a| a.username.as_str()), Some("Game Master"));
+    }
+
+    #[test]
+    fn autocomplete_in_threads_and_modals_parse() {
+        let autocomplete: Interaction = fixture("autocomplete");
+        assert_eq!(autocomplete.kind(), InteractionType::ApplicationCommandAutocomplete);
+        assert_eq!(autocomplete.focused_option().and_then(|o| o.value.as_ref()).and_then(|v| v.as_str()), Some("ha"));
+        assert!(autocomplete.channel.as_ref().is_some_and(|c| c.is_thread()));
+        assert_eq!(autocomplete.user_name(), "Ayla");
+
+        let modal: Interaction = fixture("modal_submit");
+        assert_eq!(modal.kind(), InteractionType::ModalSubmit);
+        assert_eq!(modal.text_input("action"), Some("I pry the lantern from the statue"));
+        assert_eq!(modal.text_input("narration"), Some(""));
+        assert!(modal.member_permissions().contains(Permissions::MANAGE_THREADS));
+    }
+
+    #[test]
+    fn resolved_attachments_and_channels_parse() {
+        let import: Interaction = fixture("attachment_command");
+        let attachment = import.option_attachment("file").unwrap();
+        assert_eq!(attachment.filename, "campaign-3.json");
+        assert_eq!(attachment.size, 48213);
+
+        let channels = import.command().and_then(|c| c.resolved.as_ref()).and_then(|r| r.channels.as_ref()).unwrap();
+        assert_eq!(channels[&Snowflake::new(1183456098718179411)].name.as_deref(), Some("market"));
+    }
+
+    #[test]
+    fn forum_channels_parse_their_overwrites_and_tags() {
+        let forum: Channel = fixture("forum_channel");
+        assert_eq!(forum.r#type, ChannelType::GuildForum);
+
+        let overwrites = forum.permission_overwrites.as_ref().unwrap();
+        assert_eq!(overwrites.len(), 3);
+        assert_eq!(overwrites[0].r#type, OverwriteType::Role);
+        assert_eq!(overwrites[0].deny, "2048");
+        assert_eq!(overwrites[2].r#type, OverwriteType::Member);
+
+        let tags = forum.available_tags.as_ref().unwrap();
+        assert_eq!(tags[0].emoji_name.as_deref(), Some("📌"));
+        assert!(tags[1].moderated);
+        assert_eq!(tags[1].emoji_id, Some(Snowflake::new(1183462800531128340)));
+        assert_eq!(forum.default_reaction_emoji.as_ref().and_then(|r| r.emoji_name.as_deref()), Some("⚔️"));
+
+        let again: Channel = serde_json::from_value(serde_json::to_value(&forum).unwrap()).unwrap();
+        assert_eq!(again.available_tags.map(|t| t.len()), Some(2));
+    }
+
+    #[test]
+    fn guilds_parse_roles_emojis_and_stickers() {
+        let guild: Guild = fixture("guild");
+        assert_eq!(guild.features.as_ref().map(|f| f.len()), Some(4));
+
+        let roles = guild.roles.as_ref().unwrap();
+        assert_eq!(roles[1].unicode_emoji.as_deref(), Some("🎲"));
+        assert!(roles[1].hoist && roles[1].mentionable);
+        assert!(roles[0].tags.is_none());
+        assert_eq!(roles[2].tags.as_ref().and_then(|t| t.bot_id), Some(Snowflake::new(1183461010976460830)));
+        assert!(!roles[2].tags.as_ref().unwrap().premium_subscriber);
+        assert!(roles[3].tags.as_ref().unwrap().premium_subscriber);
+
+        let emojis = guild.emojis.as_ref().unwrap();
+        assert_eq!(emojis[1].roles, [Snowflake::new(1183460234019713086)]);
+        assert_eq!(emojis[1].animated, Some(true));
+
+        let welcome = guild.welcome_screen.as_ref().unwrap();
+        assert_eq!(welcome.welcome_channels.len(), 2);
+        assert_eq!(welcome.welcome_channels[1].emoji_id, Some(Snowflake::new(1183462800531128340)));
+
+        let stickers = guild.stickers.as_ref().unwrap();
+        assert_eq!(stickers[0].format_type, StickerFormatType::Png);
+        assert_eq!(stickers[1].format_type, StickerFormatType::Unknown(9));
+        assert_eq!(guild.incidents_data.as_ref().and_then(|i| i.invites_disabled_until.as_deref()), Some("2026-10-20T18:00:00.000000+00:00"));
+
+        // the flags of role tags go back out as null keys, the way discord sends them
+        let json = serde_json::to_value(&guild).unwrap();
+        assert_eq!(json["roles"][3]["tags"], serde_json::json!({ "premium_subscriber": null, "bot_id": null, "integration_id": null, "subscription_listing_id": null }));
+        assert_eq!(json["roles"][2]["tags"]["bot_id"], "1183461010976460830");
+        assert!(serde_json::from_value::<Guild>(json).unwrap().roles.unwrap()[3].tags.as_ref().unwrap().premium_subscriber);
+    }
+
+    #[test]
+    fn partial_guilds_of_interactions_parse() {
+        let command: Interaction = fixture("slash_command");
+        let guild = command.guild.as_ref().unwrap();
+        assert_eq!(guild.features.as_deref(), Some(&[String::from("NEWS"), String::from("COMMUNITY")][..]));
+        assert!(guild.roles.is_none() && guild.welcome_screen.is_none());
+    }
+
+    #[test]
+    fn unknown_enum_values_survive_a_round_trip() {
+        let channel: ChannelType = serde_json::from_str("99").unwrap();
+        assert_eq!(channel, ChannelType::Unknown(99));
+        assert_eq!(serde_json::to_string(&channel).unwrap(), "99");
+        assert_eq!(serde_json::to_string(&OptionType::Attachment).unwrap(), "11");
+    }
+
+    #[test]
+    fn data_must_match_the_interaction_type() {
+        let mut payload: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(
+            format!("{}/tests/fixtures/component.json", env!("CARGO_MANIFEST_DIR"))).unwrap()).unwrap();
+
+        // a button click claiming to be a slash command has no command name or id
+        payload["type"] = serde_json::json!(2);
+        assert!(serde_json::from_value::<Interaction>(payload.clone()).is_err());
+
+        payload["type"] = serde_json::json!(42);
+        let unknown: Interaction = serde_json::from_value(payload.clone()).unwrap();
+        assert_eq!(unknown.kind(), InteractionType::Unknown(42));
+        assert_eq!(serde_json::to_value(&unknown).unwrap()["data"], payload["data"]);
+        assert_eq!(unknown.custom_id(), None);
+    }
+
+    #[test]
+    fn responses_carry_the_data_of_their_callback_type() {
+        let json = |response: ResponseOject| serde_json::to_value(response).unwrap();
+
+        assert_eq!(json(ResponseOject::Pong), serde_json::json!({ "type": 1 }));
+        assert_eq!(json(ResponseOject::deferred()), serde_json::json!({ "type": 5 }));
+        assert_eq!(json(ResponseOject::deferred_ephemeral()), serde_json::json!({ "type": 5, "data": { "flags": EPHEMERAL_FLAG } }));
+        assert_eq!(json(ResponseOject::ephemeral(String::from("hi"))), serde_json::json!({ "type": 4, "data": { "content": "hi", "flags": EPHEMERAL_FLAG } }));
+        assert_eq!(json(ResponseOject::update(String::from("done"), Vec::new()))["type"], 7);
+        assert_eq!(json(ResponseOject::Autocomplete(AutocompleteResponse::new(Vec::new()))), serde_json::json!({ "type": 8, "data": { "choices": [] } }));
+
+        let modal = json(ResponseOject::modal("approval:edited:1", "Action #1", Vec::new()));
+        assert_eq!(modal, serde_json::json!({ "type": 9, "data": { "custom_id": "approval:edited:1", "title": "Action #1", "components": [] } }));
+    }
+}
@@ -0,0 +1,672 @@
+use std::env;
+use std::error::Error;
+use std::ops::BitOr;
+use std::sync::Arc;
+use std::time::Duration;
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::time::{self, Instant};
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use super::action;
+use super::discord_data_structs::{
+    GatewayOpcode,
+    GatewayPayload,
+    GuildMemberRemove,
+    Hello,
+    Identify,
+    IdentifyProperties,
+    Message,
+    MessageObject,
+    MessageReaction,
+    Ready,
+    Resume
+};
+use super::i18n;
+use super::session::{self, Rsvp};
+use super::snowflake::Snowflake;
+use super::AppState;
+
+pub const GATEWAY_VERSION: u8 = 10;
+
+/// how long a new connection may take to say hello
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// wait before the first reconnect, doubled after every connection that never got ready
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// close code for connections the bot drops itself. anything but 1000 and 1001 keeps the
+/// session resumable
+const RECONNECT_CLOSE_CODE: u16 = 4000;
+
+/// gateway events the bot subscribes to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Intents(u64);
+
+impl Intents {
+    pub const GUILDS: Intents = Intents(1 << 0);
+
+    /// privileged, has to be switched on in the developer portal
+    pub const GUILD_MEMBERS: Intents = Intents(1 << 1);
+    pub const GUILD_MESSAGES: Intents = Intents(1 << 9);
+    pub const GUILD_MESSAGE_REACTIONS: Intents = Intents(1 << 10);
+    pub const DIRECT_MESSAGES: Intents = Intents(1 << 12);
+    pub const DIRECT_MESSAGE_REACTIONS: Intents = Intents(1 << 13);
+
+    /// privileged, without it messages arrive without their content
+    pub const MESSAGE_CONTENT: Intents = Intents(1 << 15);
+
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+}
+
+impl BitOr for Intents {
+    type Output = Intents;
+
+    fn bitor(self, other: Intents) -> Intents {
+        Intents(self.0 | other.0)
+    }
+}
+
+/// what the bot needs for in-character chat, RSVP reactions and members leaving
+pub const DEFAULT_INTENTS: Intents = Intents(
+    Intents::GUILDS.0
+        | Intents::GUILD_MEMBERS.0
+        | Intents::GUILD_MESSAGES.0
+        | Intents::GUILD_MESSAGE_REACTIONS.0
+        | Intents::DIRECT_MESSAGES.0
+        | Intents::DIRECT_MESSAGE_REACTIONS.0
+        | Intents::MESSAGE_CONTENT.0
+);
+
+#[derive(Clone, Debug)]
+pub struct GatewayConfig {
+    /// such as wss://gateway.discord.gg, the version and encoding are added when missing
+    pub url: String,
+    pub token: String,
+    pub intents: Intents,
+}
+
+impl GatewayConfig {
+    /// reads GATEWAY_URL and GATEWAY_INTENTS, a number. without a url the bot only sees
+    /// interactions
+    pub fn from_env() -> Option<GatewayConfig> {
+        let url = env::var("GATEWAY_URL").ok().filter(|u| !u.trim().is_empty())?;
+
+        Some(GatewayConfig {
+            url: url.trim().to_string(),
+            token: env::var("DISCORD_TOKEN").expect("DISCORD_TOKEN env variable must be set"),
+            intents: env::var("GATEWAY_INTENTS").ok()
+                .and_then(|i| i.trim().parse().ok())
+                .map(Intents)
+                .unwrap_or(DEFAULT_INTENTS)
+        })
+    }
+}
+
+/// adds the version and encoding, which discord leaves off the urls it hands out
+fn versioned_url(url: &str) -> String {
+    match url.contains('?') {
+        true => url.to_string(),
+        false => format!("{}/?v={}&encoding=json", url.trim_end_matches('/'), GATEWAY_VERSION)
+    }
+}
+
+/// the dispatched events the bot acts on
+#[derive(Debug, Clone)]
+pub enum GatewayEvent {
+    Ready(Ready),
+    MessageCreate(Box<Message>),
+    MessageReactionAdd(MessageReaction),
+    MessageReactionRemove(MessageReaction),
+    GuildMemberRemove(GuildMemberRemove),
+}
+
+impl GatewayEvent {
+    /// `None` for the events the bot ignores
+    fn parse(name: &str, data: serde_json::Value) -> Result<Option<GatewayEvent>, serde_json::Error> {
+        Ok(Some(match name {
+            "READY" => GatewayEvent::Ready(serde_json::from_value(data)?),
+            "MESSAGE_CREATE" => GatewayEvent::MessageCreate(serde_json::from_value(data)?),
+            "MESSAGE_REACTION_ADD" => GatewayEvent::MessageReactionAdd(serde_json::from_value(data)?),
+            "MESSAGE_REACTION_REMOVE" => GatewayEvent::MessageReactionRemove(serde_json::from_value(data)?),
+            "GUILD_MEMBER_REMOVE" => GatewayEvent::GuildMemberRemove(serde_json::from_value(data)?),
+            _ => return Ok(None)
+        }))
+    }
+}
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// a session discord can pick back up after the connection drops
+#[derive(Clone, Debug)]
+struct ResumableSession {
+    id: String,
+    url: String,
+}
+
+/// how a connection ended
+#[derive(Debug)]
+enum Disconnect {
+    /// connect again, resuming the session if there is one
+    Resume,
+
+    /// connect again with a new session
+    Identify,
+
+    /// nobody listens to the events anymore
+    Closed,
+
+    /// discord refused the bot for good, such as for a wrong token or intents it may not use
+    Fatal(String),
+}
+
+/// what the close code discord ended the connection with asks for
+fn disconnect_for(frame: Option<&CloseFrame>) -> Disconnect {
+    let code = frame.map(|f| u16::from(f.code));
+    match code {
+        Some(4004 | 4010..=4014) => Disconnect::Fatal(format!("gateway closed the connection with {}: {}",
+            code.unwrap_or_default(), frame.map(|f| f.reason.as_str()).unwrap_or_default())),
+        Some(4007 | 4009) => Disconnect::Identify,
+        _ => Disconnect::Resume
+    }
+}
+
+/// the next payload of a connection, skipping websocket pings and anything unreadable
+async fn next_payload(socket: &mut Socket) -> Result<GatewayPayload, Disconnect> {
+    loop {
+        let text = match socket.next().await {
+            Some(Ok(WsMessage::Text(text))) => text.to_string(),
+            Some(Ok(WsMessage::Binary(bytes))) => String::from_utf8_lossy(&bytes).to_string(),
+            Some(Ok(WsMessage::Close(frame))) => return Err(disconnect_for(frame.as_ref())),
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => {
+                log::warn!("gateway connection failed\n{}", e);
+                return Err(Disconnect::Resume)
+            },
+            None => return Err(Disconnect::Resume)
+        };
+
+        match serde_json::from_str(&text) {
+            Ok(payload) => return Ok(payload),
+            Err(e) => log::warn!("could not read gateway payload {}\n{}", text, e)
+        }
+    }
+}
+
+/// keeps a gateway session up: identifies, heartbeats, resumes dropped connections and
+/// passes the events it receives on
+pub struct GatewayClient {
+    config: GatewayConfig,
+    session: Option<ResumableSession>,
+
+    /// sequence number of the last dispatched event
+    sequence: Option<u64>,
+
+    /// whether the current connection got as far as READY or RESUMED
+    ready: bool,
+}
+
+impl GatewayClient {
+    pub fn new(config: GatewayConfig) -> GatewayClient {
+        GatewayClient {
+            config,
+            session: None,
+            sequence: None,
+            ready: false
+        }
+    }
+
+    /// stays connected until nobody receives the events anymore or discord refuses the bot
+    pub async fn run(mut self, events: mpsc::UnboundedSender<GatewayEvent>) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut delay = INITIAL_RECONNECT_DELAY;
+
+        loop {
+            let url = versioned_url(self.session.as_ref().map(|s| s.url.as_str()).unwrap_or(&self.config.url));
+            let disconnect = match connect_async(url.as_str()).await {
+                Ok((socket, _)) => self.connect(socket, &events).await,
+                Err(e) => {
+                    log::warn!("could not connect to the gateway at {}\n{}", url, e);
+                    Disconnect::Resume
+                }
+            };
+
+            match disconnect {
+                Disconnect::Resume => log::info!("gateway disconnected, resuming"),
+                Disconnect::Identify => {
+                    log::info!("gateway session is gone, identifying again");
+                    self.session = None;
+                    self.sequence = None;
+                },
+                Disconnect::Closed => return Ok(()),
+                Disconnect::Fatal(reason) => return Err(reason.into())
+            }
+
+            if self.ready {
+                self.ready = false;
+                delay = INITIAL_RECONNECT_DELAY;
+            }
+            time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+    }
+
+    /// runs one connection from hello until it drops
+    async fn connect(&mut self, mut socket: Socket, events: &mpsc::UnboundedSender<GatewayEvent>) -> Disconnect {
+        let hello: Hello = match time::timeout(HELLO_TIMEOUT, next_payload(&mut socket)).await {
+            Ok(Ok(payload)) if payload.op == GatewayOpcode::Hello => match serde_json::from_value(payload.d) {
+                Ok(h) => h,
+                Err(e) => {
+                    log::warn!("could not read gateway hello\n{}", e);
+                    return Disconnect::Resume
+                }
+            },
+            Ok(Err(disconnect)) => return disconnect,
+            _ => {
+                log::warn!("gateway did not say hello");
+                return Disconnect::Resume
+            }
+        };
+
+        if let Err(e) = send(&mut socket, &self.handshake()).await {
+            log::warn!("could not send gateway handshake\n{}", e);
+            return Disconnect::Resume
+        }
+
+        // the first heartbeat goes out after a random part of the interval, so bots that
+        // reconnect at once don't all beat in step
+        let interval = Duration::from_millis(hello.heartbeat_interval.max(1));
+        let mut next_beat = Instant::now() + interval.mul_f64(rand::random::<f64>());
+        let mut acked = true;
+
+        loop {
+            let payload = tokio::select! {
+                _ = time::sleep_until(next_beat) => {
+                    if !acked {
+                        log::warn!("gateway did not acknowledge the last heartbeat");
+                        return close(socket).await
+                    }
+                    acked = false;
+                    next_beat = Instant::now() + interval;
+                    if let Err(e) = send(&mut socket, &self.heartbeat()).await {
+                        log::warn!("could not send heartbeat\n{}", e);
+                        return Disconnect::Resume
+                    }
+                    continue
+                },
+                payload = next_payload(&mut socket) => match payload {
+                    Ok(p) => p,
+                    Err(disconnect) => return disconnect
+                }
+            };
+
+            match payload.op {
+                GatewayOpcode::Dispatch => {
+                    if payload.s.is_some() {
+                        self.sequence = payload.s;
+                    }
+                    if let Some(disconnect) = self.dispatch(payload, events) {
+                        return disconnect
+                    }
+                },
+                GatewayOpcode::Heartbeat => {
+                    if let Err(e) = send(&mut socket, &self.heartbeat()).await {
+                        log::warn!("could not send heartbeat\n{}", e);
+                        return Disconnect::Resume
+                    }
+                },
+                GatewayOpcode::HeartbeatAck => acked = true,
+                GatewayOpcode::Reconnect => {
+                    log::info!("gateway asked for a reconnect");
+                    return close(socket).await
+                },
+                GatewayOpcode::InvalidSession => {
+                    let resumable = payload.d.as_bool().unwrap_or(false);
+                    close(socket).await;
+                    return match resumable {
+                        true => Disconnect::Resume,
+                        false => Disconnect::Identify
+                    }
+                },
+                _ => log::debug!("ignoring gateway opcode {:?}", payload.op)
+            }
+        }
+    }
+
+    /// resumes the last session if there is one, otherwise starts a new one
+    fn handshake(&self) -> GatewayPayload {
+        match (&self.session, self.sequence) {
+            (Some(session), Some(seq)) => GatewayPayload::new(GatewayOpcode::Resume, serde_json::json!(Resume {
+                token: self.config.token.clone(),
+                session_id: session.id.clone(),
+                seq
+            })),
+            _ => GatewayPayload::new(GatewayOpcode::Identify, serde_json::json!(Identify {
+                token: self.config.token.clone(),
+                intents: self.config.intents.bits(),
+                properties: IdentifyProperties {
+                    os: env::consts::OS.to_string(),
+                    browser: String::from("discord_gm_bot"),
+                    device: String::from("discord_gm_bot")
+                }
+            }))
+        }
+    }
+
+    fn heartbeat(&self) -> GatewayPayload {
+        GatewayPayload::new(GatewayOpcode::Heartbeat, serde_json::json!(self.sequence))
+    }
+
+    /// keeps what resuming needs and passes the event on
+    fn dispatch(&mut self, payload: GatewayPayload, events: &mpsc::UnboundedSender<GatewayEvent>) -> Option<Disconnect> {
+        let name = payload.t.unwrap_or_default();
+        if name == "RESUMED" {
+            log::info!("gateway session resumed");
+            self.ready = true;
+            return None
+        }
+
+        let event = match GatewayEvent::parse(&name, payload.d) {
+            Ok(Some(e)) => e,
+            Ok(None) => return None,
+            Err(e) => {
+                log::warn!("could not read gateway event {}\n{}", name, e);
+                return None
+            }
+        };
+
+        if let GatewayEvent::Ready(ready) = &event {
+            log::info!("connected to the gateway as {}", ready.user.username);
+            self.session = Some(ResumableSession {
+                id: ready.session_id.clone(),
+                url: ready.resume_gateway_url.clone()
+            });
+            self.ready = true;
+        }
+
+        match events.send(event) {
+            Ok(()) => None,
+            Err(_) => Some(Disconnect::Closed)
+        }
+    }
+}
+
+async fn send(socket: &mut Socket, payload: &GatewayPayload) -> Result<(), Box<dyn Error + Send + Sync>> {
+    socket.send(WsMessage::text(serde_json::to_string(payload)?)).await?;
+    Ok(())
+}
+
+/// drops the connection without ending the session, so the next one can resume it
+async fn close(mut socket: Socket) -> Disconnect {
+    let frame = CloseFrame {
+        code: CloseCode::from(RECONNECT_CLOSE_CODE),
+        reason: "reconnecting".into()
+    };
+    if let Err(e) = socket.close(Some(frame)).await {
+        log::debug!("could not close gateway connection\n{}", e);
+    }
+    Disconnect::Resume
+}
+
+/// connects to the gateway and feeds its events into the campaigns
+pub fn spawn_gateway(state: Arc<AppState>, config: GatewayConfig) {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        if let Err(e) = GatewayClient::new(config).run(sender).await {
+            log::error!("gateway stopped\n{}", e);
+        }
+    });
+
+    tokio::spawn(async move {
+        // the bot's own user, to tell its messages and reactions apart
+        let mut bot_id: Option<Snowflake> = None;
+
+        while let Some(event) = receiver.recv().await {
+            match event {
+                GatewayEvent::Ready(ready) => bot_id = Some(ready.user.id),
+                GatewayEvent::MessageCreate(message) => message_created(&state, bot_id, &message),
+                GatewayEvent::MessageReactionAdd(reaction) => reaction_changed(&state, bot_id, &reaction, true),
+                GatewayEvent::MessageReactionRemove(reaction) => reaction_changed(&state, bot_id, &reaction, false),
+                GatewayEvent::GuildMemberRemove(removal) => member_removed(&state, &removal)
+            }
+        }
+    });
+}
+
+/// remembers the bot's session announcements and records in-character chat of players
+fn message_created(state: &AppState, bot_id: Option<Snowflake>, message: &Message) {
+    let author = match &message.author {
+        Some(a) => a,
+        None => return
+    };
+
+    let mut lock = match state.campaigns.lock() {
+        Ok(l) => l,
+        Err(e) => {
+            log::error!("unable to obtain lock for app state\n{}", e);
+            return
+        }
+    };
+    let campaign = match lock.in_channel_mut(message.channel_id) {
+        Some(c) => c,
+        None => return
+    };
+
+    if Some(author.id) == bot_id {
+        session::track_announcement(campaign, message);
+    } else if author.bot != Some(true) && message.webhook_id.is_none() {
+        action::record_chat(campaign, author.id, &message.content);
+    }
+}
+
+/// counts ✅, 🤔 and ❌ on a session announcement as answers and refreshes it. taking
+/// the reaction back withdraws the answer
+fn reaction_changed(state: &AppState, bot_id: Option<Snowflake>, reaction: &MessageReaction, added: bool) {
+    let rsvp = match reaction.emoji.name.as_deref().and_then(Rsvp::from_emoji) {
+        Some(r) if Some(reaction.user_id) != bot_id => r,
+        _ => return
+    };
+
+    let description = match state.campaigns.lock() {
+        Ok(mut lock) => lock.in_channel_mut(reaction.channel_id)
+            .and_then(|c| {
+                let locale = i18n::guild_locale(state, c.guild_id);
+                match added {
+                    true => session::rsvp_reaction(c, reaction.message_id, reaction.user_id, rsvp, &locale),
+                    false => session::rsvp_reaction_removed(c, reaction.message_id, reaction.user_id, rsvp, &locale)
+                }
+            }),
+        Err(e) => {
+            log::error!("unable to obtain lock for app state\n{}", e);
+            None
+        }
+    };
+
+    let content = match description {
+        Some(d) => d,
+        None => return
+    };
+
+    let discord = state.discord.clone();
+    let (channel_id, message_id) = (reaction.channel_id, reaction.message_id);
+    tokio::spawn(async move {
+        let message = MessageObject {
+            content,
+            ..Default::default()
+        };
+
+        if let Err(e) = discord.edit_message(channel_id, message_id, &message).await {
+            log::error!("could not refresh session announcement\n{}", e);
+        }
+    });
+}
+
+/// takes members who left a server out of its campaigns and tells the table
+fn member_removed(state: &AppState, removal: &GuildMemberRemove) {
+    let mut departures: Vec<(Snowflake, String)> = Vec::new();
+    let locale = i18n::guild_locale(state, Some(removal.guild_id));
+
+    match state.campaigns.lock() {
+        Ok(mut lock) => {
+            for campaign in lock.iter_mut().filter(|c| c.guild_id == Some(removal.guild_id)) {
+                if let Some(player) = campaign.remove_player(removal.user.id) {
+                    departures.push((campaign.announce_channel(), locale.format("campaign.member_left", &[("player", &player.name)])));
+                }
+            }
+        },
+        Err(e) => log::error!("unable to obtain lock for app state\n{}", e)
+    }
+
+    for (channel_id, content) in departures {
+        state.discord.spawn_message(channel_id, MessageObject {
+            content,
+            ..Default::default()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_async;
+    use crate::campaign::Campaign;
+    use crate::player::Player;
+    use crate::ruleset::RulesetId;
+
+    const PLAYER: Snowflake = Snowflake::new(1183455781045665824);
+
+    type ServerSocket = WebSocketStream<TcpStream>;
+
+    async fn accept(listener: &TcpListener) -> ServerSocket {
+        let (stream, _) = listener.accept().await.unwrap();
+        accept_async(stream).await.unwrap()
+    }
+
+    async fn send_payload(socket: &mut ServerSocket, payload: serde_json::Value) {
+        socket.send(WsMessage::text(payload.to_string())).await.unwrap();
+    }
+
+    async fn receive(socket: &mut ServerSocket) -> GatewayPayload {
+        loop {
+            match socket.next().await.unwrap().unwrap() {
+                WsMessage::Text(text) => return serde_json::from_str(&text).unwrap(),
+                _ => continue
+            }
+        }
+    }
+
+    fn config(address: &str) -> GatewayConfig {
+        GatewayConfig {
+            url: format!("ws://{}", address),
+            token: String::from("token"),
+            intents: Intents::GUILD_MESSAGES | Intents::MESSAGE_CONTENT
+        }
+    }
+
+    fn ready(address: &str) -> serde_json::Value {
+        serde_json::json!({ "op": 0, "s": 1, "t": "READY", "d": {
+            "v": 10,
+            "user": { "id": "1301337999988877700", "username": "Game Master", "discriminator": "0", "global_name": null, "avatar": null, "bot": true },
+            "session_id": "session-1",
+            "resume_gateway_url": format!("ws://{}", address),
+            "guilds": []
+        } })
+    }
+
+    #[tokio::test]
+    async fn identifies_dispatches_and_resumes_after_a_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (sender, mut events) = mpsc::unbounded_channel();
+        let client = tokio::spawn(GatewayClient::new(config(&address)).run(sender));
+
+        let mut socket = accept(&listener).await;
+        send_payload(&mut socket, serde_json::json!({ "op": 10, "d": { "heartbeat_interval": 50 } })).await;
+
+        let identify = receive(&mut socket).await;
+        assert_eq!(identify.op, GatewayOpcode::Identify);
+        assert_eq!(identify.d["token"], "token");
+        assert_eq!(identify.d["intents"], (1 << 9) | (1 << 15));
+
+        send_payload(&mut socket, ready(&address)).await;
+        send_payload(&mut socket, serde_json::json!({ "op": 0, "s": 2, "t": "TYPING_START", "d": {} })).await;
+        send_payload(&mut socket, serde_json::json!({ "op": 0, "s": 3, "t": "MESSAGE_CREATE", "d": {
+            "id": "1301337999988877766",
+            "channel_id": "1183456098718179410",
+            "guild_id": "1183456098227453962",
+            "author": { "id": "1183455781045665824", "username": "ayla_of_the_vale", "discriminator": "0", "global_name": null, "avatar": null },
+            "content": "I raise my lantern",
+            "timestamp": "2030-07-01T17:30:00.000000+00:00",
+            "edited_timestamp": null
+        } })).await;
+
+        assert!(matches!(events.recv().await, Some(GatewayEvent::Ready(r)) if r.session_id == "session-1"));
+        assert!(matches!(events.recv().await, Some(GatewayEvent::MessageCreate(m)) if m.content == "I raise my lantern"));
+
+        // heartbeats carry the last sequence number, once the client got to it
+        loop {
+            let heartbeat = receive(&mut socket).await;
+            assert_eq!(heartbeat.op, GatewayOpcode::Heartbeat);
+            send_payload(&mut socket, serde_json::json!({ "op": 11 })).await;
+            if heartbeat.d == 3 {
+                break
+            }
+        }
+        send_payload(&mut socket, serde_json::json!({ "op": 7, "d": null })).await;
+
+        let mut socket = accept(&listener).await;
+        send_payload(&mut socket, serde_json::json!({ "op": 10, "d": { "heartbeat_interval": 60000 } })).await;
+
+        let resume = receive(&mut socket).await;
+        assert_eq!(resume.op, GatewayOpcode::Resume);
+        assert_eq!(resume.d["session_id"], "session-1");
+        assert_eq!(resume.d["seq"], 3);
+
+        client.abort();
+    }
+
+    #[tokio::test]
+    async fn identifies_again_for_lost_sessions_and_stops_when_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (sender, _events) = mpsc::unbounded_channel();
+        let client = tokio::spawn(GatewayClient::new(config(&address)).run(sender));
+
+        let mut socket = accept(&listener).await;
+        send_payload(&mut socket, serde_json::json!({ "op": 10, "d": { "heartbeat_interval": 60000 } })).await;
+        assert_eq!(receive(&mut socket).await.op, GatewayOpcode::Identify);
+        send_payload(&mut socket, ready(&address)).await;
+        send_payload(&mut socket, serde_json::json!({ "op": 9, "d": false })).await;
+
+        let mut socket = accept(&listener).await;
+        send_payload(&mut socket, serde_json::json!({ "op": 10, "d": { "heartbeat_interval": 60000 } })).await;
+        assert_eq!(receive(&mut socket).await.op, GatewayOpcode::Identify);
+        socket.close(Some(CloseFrame { code: CloseCode::from(4004), reason: "Authentication failed.".into() })).await.unwrap();
+
+        let stopped = time::timeout(Duration::from_secs(5), client).await.unwrap().unwrap();
+        assert!(stopped.unwrap_err().to_string().contains("4004"));
+    }
+
+    #[test]
+    fn only_in_character_chat_of_players_goes_into_the_story() {
+        let mut campaign = Campaign::new(Snowflake::new(10), RulesetId::D20);
+        campaign.players.push(Player::new(PLAYER, "Ayla", "Fighter", RulesetId::D20.get()));
+        assert!(!action::record_chat(&mut campaign, PLAYER, "I raise my lantern"));
+
+        campaign.active = true;
+        assert!(action::record_chat(&mut campaign, PLAYER, " I raise my lantern "));
+        assert!(!action::record_chat(&mut campaign, PLAYER, "((brb, pizza))"));
+        assert!(!action::record_chat(&mut campaign, PLAYER, "OOC: same time next week?"));
+        assert!(!action::record_chat(&mut campaign, Snowflake::new(11), "I steal the lantern"));
+
+        assert_eq!(campaign.history.len(), 1);
+        assert_eq!(campaign.history[0].text, "I raise my lantern");
+    }
+}
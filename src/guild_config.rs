@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use super::discord_data_structs::{Interaction, ResponseOject};
+use super::i18n::{self, Locale};
+use super::permissions::Permissions;
+use super::ruleset::RulesetId;
+use super::snowflake::Snowflake;
+use super::AppState;
+
+/// where guild settings are kept unless GUILD_CONFIG points elsewhere
+pub const DEFAULT_GUILD_CONFIG_PATH: &str = "guild_config.json";
+
+/// most channels a server can limit campaigns to
+pub const MAX_ALLOWED_CHANNELS: usize = 25;
+
+/// locales discord lets users and servers pick
+pub const DISCORD_LOCALES: &[&str] = &[
+    "id", "da", "de", "en-GB", "en-US", "es-ES", "es-419", "fr", "hr", "it", "lt", "hu", "nl", "no", "pl",
+    "pt-BR", "ro", "fi", "sv-SE", "vi", "tr", "cs", "el", "bg", "ru", "uk", "hi", "th", "zh-CN", "ja", "zh-TW", "ko"
+];
+
+/// settings a server applies to the campaigns created in it
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct GuildConfig {
+    /// ruleset /init uses when none is picked
+    pub ruleset: RulesetId,
+
+    /// whether new campaigns are narrated, when a narrator is configured at all
+    pub narrator: bool,
+
+    /// most players a new campaign takes, `None` for no limit
+    pub max_players: Option<u32>,
+
+    /// channels campaigns may be created in, every channel when empty
+    pub allowed_channels: Vec<Snowflake>,
+
+    /// members with this role may create campaigns and become GMs of them
+    pub gm_role: Option<Snowflake>,
+
+    /// language of the bot's messages for the whole server
+    pub locale: Option<String>,
+}
+
+impl Default for GuildConfig {
+    fn default() -> GuildConfig {
+        GuildConfig {
+            ruleset: RulesetId::default(),
+            narrator: true,
+            max_players: None,
+            allowed_channels: Vec::new(),
+            gm_role: None,
+            locale: None
+        }
+    }
+}
+
+impl GuildConfig {
+    pub fn allows_channel(&self, channel_id: Snowflake) -> bool {
+        self.allowed_channels.is_empty() || self.allowed_channels.contains(&channel_id)
+    }
+
+    pub fn describe(&self, locale: &Locale) -> String {
+        let channels = match self.allowed_channels.is_empty() {
+            true => locale.text("config.any"),
+            false => self.allowed_channels.iter().map(|c| format!("<#{}>", c)).collect::<Vec<String>>().join(", ")
+        };
+
+        locale.format("config.summary", &[
+            ("ruleset", &self.ruleset.get().name()),
+            ("narrator", &locale.text(if self.narrator { "config.on" } else { "config.off" })),
+            ("max_players", &self.max_players.map(|m| m.to_string()).unwrap_or(locale.text("config.no_limit"))),
+            ("channels", &channels),
+            ("gm_role", &self.gm_role.as_ref().map(|r| format!("<@&{}>", r)).unwrap_or(locale.text("config.none"))),
+            ("locale", &self.locale.clone().unwrap_or(locale.text("config.discord_locale")))
+        ])
+    }
+}
+
+/// settings of every server, written back to `path` whenever they change
+#[derive(Debug)]
+pub struct GuildConfigs {
+    path: PathBuf,
+    guilds: HashMap<Snowflake, GuildConfig>,
+}
+
+impl GuildConfigs {
+    /// reads the settings file, starting out empty when there is none yet
+    pub fn load(path: &Path) -> Result<GuildConfigs, Box<dyn Error + Send + Sync>> {
+        let guilds: HashMap<Snowflake, GuildConfig> = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into())
+        };
+
+        log::info!("loaded settings for {} guilds from {}", guilds.len(), path.display());
+        Ok(GuildConfigs { path: path.to_path_buf(), guilds })
+    }
+
+    /// settings of a server, the defaults outside of servers and for servers that changed nothing
+    pub fn get(&self, guild_id: Option<Snowflake>) -> GuildConfig {
+        guild_id.and_then(|g| self.guilds.get(&g))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// changes a server's settings and saves them. nothing changes when `change` fails or the
+    /// settings can't be written
+    pub fn update<F>(&mut self, guild_id: Snowflake, locale: &Locale, change: F) -> Result<String, String>
+    where F: FnOnce(&mut GuildConfig) -> Result<String, String> {
+        let mut config = self.get(Some(guild_id));
+        let message = change(&mut config)?;
+
+        let previous = self.guilds.insert(guild_id, config);
+        if let Err(e) = self.save() {
+            log::error!("could not save guild settings to {}\n{}", self.path.display(), e);
+            match previous {
+                Some(p) => self.guilds.insert(guild_id, p),
+                None => self.guilds.remove(&guild_id)
+            };
+            return Err(locale.text("config.save_failed"))
+        }
+
+        Ok(message)
+    }
+
+    /// writes next to the file first so a crash never leaves half of it behind
+    fn save(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let content = serde_json::to_string_pretty(&self.guilds)?;
+        let temporary = self.path.with_extension("json.tmp");
+        fs::write(&temporary, content)?;
+        fs::rename(&temporary, &self.path)?;
+        Ok(())
+    }
+}
+
+pub fn config_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let guild_id = match interaction.guild_id {
+        Some(g) => g,
+        None => return ResponseOject::ephemeral(locale.text("config.server_only"))
+    };
+    if !interaction.member_permissions().contains(Permissions::MANAGE_GUILD) {
+        return ResponseOject::ephemeral(locale.text("config.not_allowed"))
+    }
+
+    let result = match state.guild_configs.lock() {
+        Ok(mut lock) => match interaction.subcommand() {
+            Some("show") => Ok(lock.get(Some(guild_id)).describe(&locale)),
+            Some("set") => lock.update(guild_id, &locale, |config| set(config, interaction, &locale)),
+            Some("unset") => lock.update(guild_id, &locale, |config| unset(config, interaction, &locale)),
+            Some("allow") => lock.update(guild_id, &locale, |config| allow(config, interaction, &locale)),
+            Some("disallow") => lock.update(guild_id, &locale, |config| disallow(config, interaction, &locale)),
+            _ => Err(locale.format("error.unknown_command", &[("command", &"config")]))
+        },
+        Err(e) => {
+            log::error!("unable to obtain lock for app state\n{}", e);
+            Err(locale.text("error.unable"))
+        }
+    };
+
+    match result {
+        Ok(message) => ResponseOject::ephemeral(message),
+        Err(e) => ResponseOject::ephemeral(e)
+    }
+}
+
+/// applies every option given, leaving the others as they are
+fn set(config: &mut GuildConfig, interaction: &Interaction, locale: &Locale) -> Result<String, String> {
+    if let Some(key) = interaction.option_str("ruleset") {
+        config.ruleset = RulesetId::from_key(key).ok_or(locale.format("init.unknown_ruleset", &[("ruleset", &key)]))?;
+    }
+    if let Some(narrator) = interaction.option_bool("narrator") {
+        config.narrator = narrator;
+    }
+    if let Some(max) = interaction.option_i64("max_players") {
+        if max < 1 {
+            return Err(locale.text("config.min_players"))
+        }
+        config.max_players = Some(max as u32);
+    }
+    if let Some(role) = interaction.option_snowflake("gm_role") {
+        config.gm_role = Some(role);
+    }
+    if let Some(key) = interaction.option_str("locale") {
+        let key = DISCORD_LOCALES.iter()
+            .find(|l| l.eq_ignore_ascii_case(key.trim()))
+            .ok_or(locale.format("config.unknown_locale", &[("locale", &key)]))?;
+        config.locale = Some(key.to_string());
+    }
+
+    Ok(locale.format("config.saved_new", &[("settings", &config.describe(locale))]))
+}
+
+fn unset(config: &mut GuildConfig, interaction: &Interaction, locale: &Locale) -> Result<String, String> {
+    match interaction.option_str("setting") {
+        Some("max_players") => config.max_players = None,
+        Some("gm_role") => config.gm_role = None,
+        Some("locale") => config.locale = None,
+        Some("channels") => config.allowed_channels.clear(),
+        _ => return Err(locale.text("config.unknown_setting"))
+    }
+
+    Ok(locale.format("config.saved", &[("settings", &config.describe(locale))]))
+}
+
+fn allow(config: &mut GuildConfig, interaction: &Interaction, locale: &Locale) -> Result<String, String> {
+    let channel = interaction.option_snowflake("channel").ok_or(locale.text("config.channel_required"))?;
+    if config.allowed_channels.contains(&channel) {
+        return Err(locale.format("config.channel_already", &[("channel", &channel)]))
+    }
+    if config.allowed_channels.len() >= MAX_ALLOWED_CHANNELS {
+        return Err(locale.format("config.too_many_channels", &[("count", &MAX_ALLOWED_CHANNELS)]))
+    }
+
+    config.allowed_channels.push(channel);
+    Ok(locale.format("config.allowed", &[("channel", &channel)]))
+}
+
+fn disallow(config: &mut GuildConfig, interaction: &Interaction, locale: &Locale) -> Result<String, String> {
+    let channel = interaction.option_snowflake("channel").ok_or(locale.text("config.channel_required"))?;
+    if !config.allowed_channels.contains(&channel) {
+        return Err(locale.format("config.not_listed", &[("channel", &channel)]))
+    }
+
+    config.allowed_channels.retain(|c| *c != channel);
+    match config.allowed_channels.is_empty() {
+        true => Ok(locale.text("config.list_empty")),
+        false => Ok(locale.format("config.disallowed", &[("channel", &channel)]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUILD: Snowflake = Snowflake::new(1183456098227453962);
+    const TAVERN: Snowflake = Snowflake::new(1183456098718179410);
+    const MARKET: Snowflake = Snowflake::new(1183456098718179411);
+
+    #[test]
+    fn settings_survive_a_restart_and_failed_changes_are_dropped() {
+        let path = std::env::temp_dir().join(format!("guild-config-test-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut configs = GuildConfigs::load(&path).unwrap();
+        assert_eq!(configs.get(Some(GUILD)), GuildConfig::default());
+
+        configs.update(GUILD, &Locale::new(None, None), |config| {
+            config.ruleset = RulesetId::Pbta;
+            config.narrator = false;
+            config.allowed_channels.push(TAVERN);
+            Ok(String::new())
+        }).unwrap();
+        let refused = configs.update(GUILD, &Locale::new(None, None), |config| {
+            config.max_players = Some(3);
+            Err(String::from("no"))
+        });
+        assert!(refused.is_err());
+
+        let reloaded = GuildConfigs::load(&path).unwrap().get(Some(GUILD));
+        assert_eq!(reloaded.ruleset, RulesetId::Pbta);
+        assert!(!reloaded.narrator);
+        assert_eq!(reloaded.max_players, None);
+        assert!(reloaded.allows_channel(TAVERN));
+        assert!(!reloaded.allows_channel(MARKET));
+        assert!(GuildConfig::default().allows_channel(MARKET));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
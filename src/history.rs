@@ -0,0 +1,54 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use super::snowflake::Snowflake;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryKind {
+    Experience,
+    LevelUp,
+    Action,
+    Narration,
+
+    /// a game mechanic the narrator asked for, after the engine resolved it
+    Resolution,
+
+    /// a check or attack a player rolled with a command
+    Roll,
+
+    /// a quest was taken on, completed or failed
+    Quest,
+}
+
+/// a single change to the campaign, kept in order of when it happened
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HistoryEntry {
+    /// unix timestamp in seconds
+    pub timestamp: u64,
+    pub kind: HistoryKind,
+
+    /// player the change applies to, if any
+    pub player_id: Option<Snowflake>,
+    pub text: String,
+}
+
+impl HistoryEntry {
+    pub fn new(kind: HistoryKind, player_id: Option<Snowflake>, text: &str) -> HistoryEntry {
+        HistoryEntry {
+            timestamp: now(),
+            kind,
+            player_id,
+            text: text.to_string()
+        }
+    }
+}
+
+pub fn now() -> u64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs(),
+        Err(e) => {
+            log::error!("system clock is before the unix epoch\n{}", e);
+            0
+        }
+    }
+}
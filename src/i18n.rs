@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::LazyLock;
+use super::discord_data_structs::{CommandOptionChoice, Interaction};
+use super::snowflake::Snowflake;
+use super::AppState;
+
+/// language replies fall back to when neither the user's nor the server's is available
+pub const DEFAULT_LOCALE: &str = "en-US";
+
+/// message catalogs compiled into the bot, keyed by discord locale
+const BUNDLED: &[(&str, &str)] = &[
+    ("en-US", include_str!("../locales/en-US.json")),
+    ("de", include_str!("../locales/de.json")),
+];
+
+static CATALOG: LazyLock<Catalog> = LazyLock::new(Catalog::bundled);
+
+/// reply texts of every bundled language. a text is a template whose `{name}` placeholders
+/// are filled in when the reply is written
+#[derive(Debug)]
+pub struct Catalog {
+    languages: HashMap<&'static str, HashMap<String, String>>,
+}
+
+impl Catalog {
+    fn bundled() -> Catalog {
+        let languages = BUNDLED.iter()
+            .map(|(locale, content)| {
+                let messages: HashMap<String, String> = serde_json::from_str(content)
+                    .unwrap_or_else(|e| panic!("message catalog {} is not valid\n{}", locale, e));
+                (*locale, messages)
+            })
+            .collect();
+
+        Catalog { languages }
+    }
+
+    /// the bundled language for a discord locale. regional variants share their language,
+    /// so en-GB reads en-US and de-AT would read de
+    fn language(&self, locale: &str) -> Option<&HashMap<String, String>> {
+        if let Some(messages) = self.languages.get(locale) {
+            return Some(messages)
+        }
+
+        let primary = locale.split('-').next().unwrap_or(locale);
+        self.languages.iter()
+            .find(|(bundled, _)| bundled.split('-').next() == Some(primary))
+            .map(|(_, messages)| messages)
+    }
+
+    pub fn locales(&self) -> Vec<&'static str> {
+        let mut locales: Vec<&'static str> = self.languages.keys().copied().collect();
+        locales.sort_unstable();
+        locales
+    }
+}
+
+/// the languages to answer an interaction in, most preferred first
+#[derive(Clone, Debug, PartialEq)]
+pub struct Locale {
+    preferred: Vec<String>,
+}
+
+impl Locale {
+    /// the user's locale, then the server's, then the default
+    pub fn new(user: Option<&str>, guild: Option<&str>) -> Locale {
+        let preferred = [user, guild, Some(DEFAULT_LOCALE)].into_iter()
+            .flatten()
+            .map(|l| l.to_string())
+            .collect();
+
+        Locale { preferred }
+    }
+
+    /// the text for `key` in the first preferred language that has it, the key itself when
+    /// no catalog does
+    pub fn text(&self, key: &str) -> String {
+        self.preferred.iter()
+            .filter_map(|locale| CATALOG.language(locale))
+            .find_map(|messages| messages.get(key))
+            .cloned()
+            .unwrap_or_else(|| {
+                log::warn!("no message {} in any catalog", key);
+                key.to_string()
+            })
+    }
+
+    /// a game term such as a skill, stat or item name, which stays as it is when no catalog
+    /// translates it
+    pub fn term(&self, name: &str) -> String {
+        let key = format!("term.{}", name.to_lowercase());
+        self.preferred.iter()
+            .filter_map(|locale| CATALOG.language(locale))
+            .find_map(|messages| messages.get(&key))
+            .cloned()
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    /// the text for `key` with its placeholders filled in. values are inserted as they are,
+    /// so a name that looks like a placeholder stays untouched
+    pub fn format(&self, key: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+        let text = self.text(key);
+        let mut formatted = String::with_capacity(text.len());
+        let mut rest = text.as_str();
+
+        while let Some(open) = rest.find('{') {
+            formatted.push_str(&rest[..open]);
+            let after = &rest[open + 1..];
+
+            let placeholder = after.find('}').and_then(|close| {
+                args.iter()
+                    .find(|(name, _)| *name == &after[..close])
+                    .map(|(_, value)| (value, close))
+            });
+            match placeholder {
+                Some((value, close)) => {
+                    formatted.push_str(&value.to_string());
+                    rest = &after[close + 1..];
+                },
+                None => {
+                    formatted.push('{');
+                    rest = after;
+                }
+            }
+        }
+
+        formatted.push_str(rest);
+        formatted
+    }
+}
+
+/// an autocomplete choice named in every bundled language, `name` writing it for one of
+/// them. discord shows each user the name in their own
+pub fn choice(value: serde_json::Value, name: impl Fn(&Locale) -> String) -> CommandOptionChoice {
+    let name_localizations: HashMap<String, String> = CATALOG.locales().into_iter()
+        .map(|locale| (locale.to_string(), name(&Locale::new(Some(locale), None))))
+        .collect();
+
+    CommandOptionChoice {
+        name: name(&Locale::new(None, None)),
+        value,
+        name_localizations: Some(name_localizations)
+    }
+}
+
+/// the language to answer in. a locale set with /config takes the place of the one discord
+/// reports for the server
+pub fn locale(state: &AppState, interaction: &Interaction) -> Locale {
+    let configured = configured_locale(state, interaction.guild_id);
+    Locale::new(interaction.locale.as_deref(), configured.as_deref().or(interaction.guild_locale.as_deref()))
+}
+
+/// the language for messages nobody asked for, such as a round closing at its deadline
+pub fn guild_locale(state: &AppState, guild_id: Option<Snowflake>) -> Locale {
+    Locale::new(None, configured_locale(state, guild_id).as_deref())
+}
+
+fn configured_locale(state: &AppState, guild_id: Option<Snowflake>) -> Option<String> {
+    match state.guild_configs.lock() {
+        Ok(lock) => lock.get(guild_id).locale,
+        Err(e) => {
+            log::error!("unable to obtain lock for app state\n{}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discord_data_structs::{CommandOption, Commands};
+    use crate::guild_config::DISCORD_LOCALES;
+
+    /// every bundled language names and describes the command or option in a way discord
+    /// accepts
+    fn check_localizations(path: &str, names: &Option<HashMap<String, String>>, descriptions: &Option<HashMap<String, String>>) {
+        for locale in CATALOG.locales().into_iter().filter(|l| *l != DEFAULT_LOCALE) {
+            assert!(names.as_ref().is_some_and(|n| n.contains_key(locale)), "{} has no {} name", path, locale);
+            assert!(descriptions.as_ref().is_some_and(|d| d.contains_key(locale)), "{} has no {} description", path, locale);
+        }
+
+        for (locale, name) in names.iter().flatten() {
+            assert!(DISCORD_LOCALES.contains(&locale.as_str()), "{} uses unknown locale {}", path, locale);
+            let valid = (1..=32).contains(&name.chars().count())
+                && name.chars().all(|c| c == '-' || c == '_' || c.is_alphanumeric())
+                && name.to_lowercase() == *name;
+            assert!(valid, "{} has the invalid {} name {}", path, locale, name);
+        }
+        for (locale, description) in descriptions.iter().flatten() {
+            assert!(DISCORD_LOCALES.contains(&locale.as_str()), "{} uses unknown locale {}", path, locale);
+            assert!((1..=100).contains(&description.chars().count()), "{} has a {} description of the wrong length", path, locale);
+        }
+    }
+
+    fn check_option(path: &str, option: &CommandOption) {
+        let path = format!("{} {}", path, option.name);
+        check_localizations(&path, &option.name_localizations, &option.description_localizations);
+
+        for choice in option.choices.iter().flatten() {
+            for locale in choice.name_localizations.iter().flat_map(|l| l.keys()) {
+                assert!(DISCORD_LOCALES.contains(&locale.as_str()), "{} choice {} uses unknown locale {}", path, choice.name, locale);
+            }
+        }
+        for nested in option.options.iter().flatten() {
+            check_option(&path, nested);
+        }
+    }
+
+    #[test]
+    fn every_language_has_every_message() {
+        let english = CATALOG.language(DEFAULT_LOCALE).unwrap();
+        for locale in CATALOG.locales() {
+            let messages = CATALOG.language(locale).unwrap();
+            for key in english.keys() {
+                assert!(messages.contains_key(key), "{} is missing {}", locale, key);
+            }
+            for key in messages.keys() {
+                assert!(english.contains_key(key), "{} has unknown message {}", locale, key);
+            }
+        }
+    }
+
+    #[test]
+    fn translations_keep_every_placeholder() {
+        let placeholders = |text: &str| {
+            let mut names: Vec<String> = text.split('{').skip(1)
+                .filter_map(|part| part.split_once('}').map(|(name, _)| name.to_string()))
+                .collect();
+            names.sort();
+            names.dedup();
+            names
+        };
+
+        let english = CATALOG.language(DEFAULT_LOCALE).unwrap();
+        for locale in CATALOG.locales() {
+            let messages = CATALOG.language(locale).unwrap();
+            for (key, text) in english {
+                assert_eq!(placeholders(&messages[key]), placeholders(text), "{} changes the placeholders of {}", locale, key);
+            }
+        }
+    }
+
+    #[test]
+    fn catalogs_and_command_localizations_use_discord_locales() {
+        for locale in CATALOG.locales() {
+            assert!(DISCORD_LOCALES.contains(&locale), "{} is not a discord locale", locale);
+        }
+
+        let commands: Commands = serde_json::from_str(include_str!("../commands.json")).unwrap();
+        for command in &commands.commands {
+            check_localizations(&command.name, &command.name_localizations, &command.description_localizations);
+            for option in command.options.iter().flatten() {
+                check_option(&command.name, option);
+            }
+        }
+    }
+
+    #[test]
+    fn falls_back_from_user_to_guild_to_default() {
+        let german = Locale::new(Some("de"), Some("en-US"));
+        assert_eq!(german.format("start.begun", &[("players", &3)]), "die Kampagne beginnt mit 3 Spielenden!");
+        assert_eq!(german.format("join.joined", &[("name", &"{class}"), ("class", &"Magier")]), "{class}, Magier, ist der Kampagne beigetreten!");
+
+        let guild = Locale::new(Some("ja"), Some("de"));
+        assert_eq!(guild.text("join.full"), "die Kampagne ist voll");
+
+        let regional = Locale::new(Some("en-GB"), None);
+        assert_eq!(regional.text("join.full"), "the campaign is full");
+
+        let unknown = Locale::new(Some("ja"), Some("ko"));
+        assert_eq!(unknown.text("join.full"), "the campaign is full");
+        assert_eq!(unknown.text("no.such.key"), "no.such.key");
+    }
+
+    #[test]
+    fn choices_are_named_in_every_language() {
+        let choice = choice(serde_json::Value::from("stealth"), |locale| locale.term("stealth"));
+        let names = choice.name_localizations.unwrap();
+
+        assert_eq!(choice.name, "stealth");
+        assert_eq!(names["de"], "Heimlichkeit");
+        assert_eq!(names["en-US"], "stealth");
+        assert_eq!(Locale::new(Some("de"), None).term("Goblin King"), "Goblin King");
+    }
+}
@@ -0,0 +1,207 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use super::campaign::Campaign;
+use super::combat::Combat;
+use super::dice;
+use super::history::HistoryKind;
+use super::i18n::Locale;
+use super::item::ItemCatalog;
+use super::player::Player;
+
+/// most items a single intent may hand out, so a confused narrator can't flood inventories
+pub const MAX_INTENT_ITEMS: u32 = 10;
+
+fn default_quantity() -> u32 {
+    1
+}
+
+/// a game mechanic the narrator asks for instead of deciding the numbers itself. players
+/// are named the way the narrator sees them in the prompt
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(tag = "intent", rename_all = "snake_case")]
+pub enum Intent {
+    RollCheck {
+        player: String,
+        check: String,
+        #[serde(default)]
+        save: bool,
+        difficulty: Option<i64>,
+    },
+    ApplyDamage {
+        player: String,
+
+        /// dice expression such as 1d6+2, rolled by the engine
+        damage: String,
+        source: Option<String>,
+    },
+    GiveItem {
+        player: String,
+        item: String,
+        #[serde(default = "default_quantity")]
+        quantity: u32,
+    },
+    StartCombat {
+        enemies: Vec<String>,
+    },
+}
+
+impl Intent {
+    /// builds an intent from a tool call, the function name picking the variant
+    pub fn from_tool_call(name: &str, arguments: &str) -> Result<Intent, String> {
+        let mut value: Value = serde_json::from_str(arguments)
+            .map_err(|e| format!("arguments for {} are not json\n{}", name, e))?;
+
+        match value.as_object_mut() {
+            Some(object) => {
+                object.insert(String::from("intent"), Value::String(name.to_string()));
+            },
+            None => return Err(format!("arguments for {} are not an object", name))
+        }
+
+        serde_json::from_value(value).map_err(|e| format!("invalid {} call\n{}", name, e))
+    }
+}
+
+/// function definitions offered to the narrator, in the OpenAI tools format
+pub fn tool_definitions() -> Value {
+    let tool = |name: &str, description: &str, parameters: Value| json!({
+        "type": "function",
+        "function": {
+            "name": name,
+            "description": description,
+            "parameters": parameters
+        }
+    });
+
+    json!([
+        tool("roll_check", "roll a skill check, ability check or saving throw for a player", json!({
+            "type": "object",
+            "properties": {
+                "player": { "type": "string", "description": "name of the player character" },
+                "check": { "type": "string", "description": "skill or ability, such as stealth or dex" },
+                "save": { "type": "boolean", "description": "whether this is a saving throw" },
+                "difficulty": { "type": "integer", "description": "DC to beat, if the ruleset uses one" }
+            },
+            "required": ["player", "check"]
+        })),
+        tool("apply_damage", "deal damage to a player character, rolled by the game", json!({
+            "type": "object",
+            "properties": {
+                "player": { "type": "string", "description": "name of the player character" },
+                "damage": { "type": "string", "description": "dice expression such as 1d6+2" },
+                "source": { "type": "string", "description": "what deals the damage, such as a goblin's scimitar" }
+            },
+            "required": ["player", "damage"]
+        })),
+        tool("give_item", "give a player an item from the item catalog", json!({
+            "type": "object",
+            "properties": {
+                "player": { "type": "string", "description": "name of the player character" },
+                "item": { "type": "string", "description": "name of the item" },
+                "quantity": { "type": "integer", "minimum": 1 }
+            },
+            "required": ["player", "item"]
+        })),
+        tool("start_combat", "start combat and roll initiative", json!({
+            "type": "object",
+            "properties": {
+                "enemies": { "type": "array", "items": { "type": "string" }, "description": "names of the enemies" }
+            },
+            "required": ["enemies"]
+        }))
+    ])
+}
+
+fn player_named<'a>(campaign: &'a mut Campaign, name: &str) -> Result<&'a mut Player, String> {
+    let name = name.trim();
+    campaign.players.iter_mut()
+        .find(|p| p.name.eq_ignore_ascii_case(name))
+        .ok_or(format!("{} is not in the party", name))
+}
+
+/// validates an intent against the rules and applies it to the campaign. returns what
+/// happened, worded for the players
+pub fn apply_intent(campaign: &mut Campaign, catalog: &ItemCatalog, locale: &Locale, intent: &Intent) -> Result<String, String> {
+    let ruleset = campaign.ruleset.get();
+
+    match intent {
+        Intent::RollCheck { player, check, save, difficulty } => {
+            let player = player_named(campaign, player)?;
+            let result = ruleset.resolve_check(player, check, *save, *difficulty).map_err(|e| e.describe(locale))?;
+            Ok(locale.format("intent.rolled", &[("player", &player.name), ("check", &result.label), ("roll", &result.roll), ("outcome", &result.outcome.describe(locale))]))
+        },
+        Intent::ApplyDamage { player, damage, source } => {
+            let mut roll = dice::roll(damage).map_err(|e| e.describe(locale))?;
+            roll.total = roll.total.max(0);
+
+            let player = player_named(campaign, player)?;
+            player.health = player.health.saturating_sub(roll.total.clamp(0, u8::MAX as i32) as u8);
+
+            let source = source.as_ref().map(|s| locale.format("intent.damage_source", &[("source", s)])).unwrap_or_default();
+            Ok(locale.format("intent.damaged", &[("player", &player.name), ("roll", &roll), ("source", &source), ("health", &player.health), ("max", &player.max_health)]))
+        },
+        Intent::GiveItem { player, item, quantity } => {
+            if *quantity == 0 || *quantity > MAX_INTENT_ITEMS {
+                return Err(format!("can't give {} items at once", quantity))
+            }
+
+            let item = catalog.find(item).ok_or(format!("{} is not in the item catalog", item))?;
+            let player = player_named(campaign, player)?;
+            player.inventory.add(&item.name, *quantity, locale)?;
+            Ok(locale.format("intent.received", &[("player", &player.name), ("item", &locale.term(&item.name)), ("quantity", quantity)]))
+        },
+        Intent::StartCombat { enemies } => {
+            if campaign.combat.is_some() {
+                return Err(String::from("combat has already started"))
+            }
+
+            let combat = Combat::start(campaign, enemies);
+            let summary = combat.describe(locale);
+            campaign.combat = Some(combat);
+            Ok(summary)
+        }
+    }
+}
+
+/// applies every intent in order. rejected intents are logged and left out, so the story
+/// only ever shows what the rules allowed
+pub fn apply_intents(campaign: &mut Campaign, catalog: &ItemCatalog, locale: &Locale, intents: &[Intent]) -> Vec<String> {
+    let mut results: Vec<String> = Vec::new();
+
+    for intent in intents {
+        match apply_intent(campaign, catalog, locale, intent) {
+            Ok(result) => {
+                campaign.record(HistoryKind::Resolution, None, &result);
+                results.push(result);
+            },
+            Err(e) => log::warn!("rejected narrator intent {:?} in channel {}\n{}", intent, campaign.channel_id, e)
+        }
+    }
+
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ruleset::RulesetId;
+    use crate::snowflake::Snowflake;
+
+    #[test]
+    fn intents_are_checked_against_the_rules() {
+        let mut campaign = Campaign::new(Snowflake::new(10), RulesetId::D20);
+        campaign.players.push(Player::new(Snowflake::new(1), "Ayla", "Fighter", RulesetId::D20.get()));
+        let intents = vec![
+            Intent::ApplyDamage { player: String::from("ayla"), damage: String::from("3"), source: None },
+            Intent::ApplyDamage { player: String::from("Bram"), damage: String::from("3"), source: None },
+            Intent::GiveItem { player: String::from("Ayla"), item: String::from("vorpal sword"), quantity: 1 },
+            Intent::StartCombat { enemies: vec![String::from("goblin")] }
+        ];
+
+        let results = apply_intents(&mut campaign, &ItemCatalog::default(), &Locale::new(None, None), &intents);
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(campaign.players[0].health, 7);
+        assert_eq!(campaign.combat.as_ref().unwrap().order.len(), 2);
+    }
+}
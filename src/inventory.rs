@@ -0,0 +1,355 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use super::item::{EquipSlot, Item, ItemCatalog, ItemKind};
+use super::campaign::with_campaign;
+use super::discord_data_structs::{
+    AutocompleteResponse,
+    CommandOptionChoice,
+    Interaction,
+    ResponseOject,
+    MAX_AUTOCOMPLETE_CHOICES
+};
+use super::i18n::{self, Locale};
+use super::AppState;
+
+/// most of one item a command moves at once, matching the max_value in commands.json
+pub const MAX_QUANTITY: u32 = 1000;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InventoryEntry {
+    pub item: String,
+    pub quantity: u32,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+pub struct Inventory {
+    pub entries: Vec<InventoryEntry>,
+    pub equipped: HashMap<EquipSlot, String>,
+}
+
+impl Inventory {
+    pub fn new() -> Inventory {
+        Inventory::default()
+    }
+
+    pub fn quantity(&self, item: &str) -> u32 {
+        match self.entries.iter().find(|e| e.item.eq_ignore_ascii_case(item)) {
+            Some(e) => e.quantity,
+            None => 0
+        }
+    }
+
+    /// adds `quantity` of an item, refusing to go past what a count can hold
+    pub fn add(&mut self, item: &str, quantity: u32, locale: &Locale) -> Result<(), String> {
+        match self.entries.iter_mut().find(|e| e.item.eq_ignore_ascii_case(item)) {
+            Some(e) => match e.quantity.checked_add(quantity) {
+                Some(q) => e.quantity = q,
+                None => return Err(locale.format("inventory.no_room", &[("quantity", &quantity), ("item", &locale.term(&e.item))]))
+            },
+            None => self.entries.push(InventoryEntry {
+                item: item.to_string(),
+                quantity
+            })
+        }
+
+        Ok(())
+    }
+
+    /// removes `quantity` of an item, unequipping it if none are left
+    pub fn remove(&mut self, item: &str, quantity: u32, locale: &Locale) -> Result<(), String> {
+        let index = match self.entries.iter().position(|e| e.item.eq_ignore_ascii_case(item)) {
+            Some(i) => i,
+            None => return Err(locale.format("inventory.none_held", &[("item", &locale.term(item))]))
+        };
+
+        if self.entries[index].quantity < quantity {
+            return Err(locale.format("inventory.only_have", &[("quantity", &self.entries[index].quantity), ("item", &locale.term(&self.entries[index].item))]))
+        }
+
+        self.entries[index].quantity -= quantity;
+        if self.entries[index].quantity == 0 {
+            let removed = self.entries.remove(index);
+            self.equipped.retain(|_, equipped| !equipped.eq_ignore_ascii_case(&removed.item));
+        }
+
+        Ok(())
+    }
+
+    /// equips an item into its slot, returning the item it replaced
+    pub fn equip(&mut self, item: &Item, locale: &Locale) -> Result<Option<String>, String> {
+        let slot = match item.slot {
+            Some(s) => s,
+            None => return Err(locale.format("inventory.not_equippable", &[("item", &locale.term(&item.name))]))
+        };
+
+        if self.quantity(&item.name) == 0 {
+            return Err(locale.format("inventory.none_held", &[("item", &locale.term(&item.name))]))
+        }
+
+        Ok(self.equipped.insert(slot, item.name.clone()))
+    }
+
+    pub fn unequip(&mut self, slot: EquipSlot) -> Option<String> {
+        self.equipped.remove(&slot)
+    }
+
+    pub fn total_weight(&self, catalog: &ItemCatalog) -> f32 {
+        self.entries.iter()
+            .filter_map(|e| catalog.find(&e.item).map(|i| i.weight * e.quantity as f32))
+            .sum()
+    }
+
+    pub fn equipped_items<'a>(&'a self, catalog: &'a ItemCatalog) -> impl Iterator<Item = &'a Item> {
+        self.equipped.values().filter_map(|name| catalog.find(name))
+    }
+
+    pub fn ac_bonus(&self, catalog: &ItemCatalog) -> i32 {
+        self.equipped_items(catalog).map(|i| i.ac_bonus).sum()
+    }
+
+    /// attack bonus of the weapon held in the main hand
+    pub fn attack_bonus(&self, catalog: &ItemCatalog) -> i32 {
+        self.equipped.get(&EquipSlot::MainHand)
+            .and_then(|name| catalog.find(name))
+            .filter(|i| i.kind == ItemKind::Weapon)
+            .map(|i| i.attack_bonus)
+            .unwrap_or(0)
+    }
+}
+
+pub fn inventory_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let user_id = match interaction.option_snowflake("player").or(interaction.user_id()) {
+        Some(u) => u,
+        None => return ResponseOject::ephemeral(locale.text("error.no_user"))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        let player = match campaign.player(user_id) {
+            Some(p) => p,
+            None => return ResponseOject::ephemeral(locale.text("player.other_not_joined"))
+        };
+
+        let mut lines: Vec<String> = Vec::new();
+        lines.push(locale.format("inventory.header", &[
+            ("player", &player.name),
+            ("health", &player.health),
+            ("max", &player.max_health),
+            ("defense", &campaign.ruleset.get().defense(player, &state.catalog)),
+            ("attack", &format!("{:+}", player.attack_bonus(&state.catalog)))
+        ]));
+
+        if player.inventory.entries.is_empty() {
+            lines.push(locale.text("inventory.empty"));
+        }
+
+        for entry in &player.inventory.entries {
+            let equipped = player.inventory.equipped.iter()
+                .find(|(_, name)| name.eq_ignore_ascii_case(&entry.item))
+                .map(|(slot, _)| format!(" [{}]", locale.term(slot.name())))
+                .unwrap_or_default();
+            lines.push(format!("- {} x{}{}", locale.term(&entry.item), entry.quantity, equipped));
+        }
+
+        lines.push(locale.format("inventory.weight", &[("weight", &player.inventory.total_weight(&state.catalog))]));
+
+        ResponseOject::ephemeral(lines.join("\n"))
+    })
+}
+
+/// puts catalog items into a player's inventory. only the GM hands out items, players trade
+/// what they hold with /trade
+pub fn give_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let (target, item_name) = match (interaction.option_snowflake("player"), interaction.option_str("item")) {
+        (Some(t), Some(i)) => (t, i),
+        _ => return ResponseOject::ephemeral(locale.text("inventory.give_required"))
+    };
+    let quantity = interaction.option_i64("quantity").unwrap_or(1).clamp(1, MAX_QUANTITY as i64) as u32;
+
+    let item = match state.catalog.find(item_name) {
+        Some(i) => i,
+        None => return ResponseOject::ephemeral(locale.format("item.unknown", &[("item", &item_name)]))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("inventory.not_gm_give"))
+        }
+
+        match campaign.player_mut(target) {
+            Some(player) => {
+                if let Err(e) = player.inventory.add(&item.name, quantity, &locale) {
+                    return ResponseOject::ephemeral(e)
+                }
+                ResponseOject::new(locale.format("inventory.received", &[("player", &player.name), ("item", &locale.term(&item.name)), ("quantity", &quantity)]))
+            },
+            None => ResponseOject::ephemeral(locale.text("player.other_not_joined"))
+        }
+    })
+}
+
+pub fn use_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let (user_id, item_name) = match (interaction.user_id(), interaction.option_str("item")) {
+        (Some(u), Some(i)) => (u, i),
+        _ => return ResponseOject::ephemeral(locale.text("inventory.item_required"))
+    };
+
+    let item = match state.catalog.find(item_name) {
+        Some(i) => i,
+        None => return ResponseOject::ephemeral(locale.format("item.unknown", &[("item", &item_name)]))
+    };
+
+    if item.kind != ItemKind::Consumable {
+        return ResponseOject::ephemeral(locale.format("inventory.not_usable", &[("item", &locale.term(&item.name))]))
+    }
+
+    with_campaign(state, interaction, |campaign| {
+        let player = match campaign.player_mut(user_id) {
+            Some(p) => p,
+            None => return ResponseOject::ephemeral(locale.text("player.not_joined"))
+        };
+
+        if let Err(e) = player.inventory.remove(&item.name, 1, &locale) {
+            return ResponseOject::ephemeral(e)
+        }
+
+        match item.heal {
+            Some(amount) => {
+                let healed = player.heal(amount);
+                ResponseOject::new(locale.format("inventory.used_healed", &[
+                    ("player", &player.name),
+                    ("item", &locale.term(&item.name)),
+                    ("healed", &healed),
+                    ("health", &player.health),
+                    ("max", &player.max_health)
+                ]))
+            },
+            None => ResponseOject::new(locale.format("inventory.used", &[("player", &player.name), ("item", &locale.term(&item.name))]))
+        }
+    })
+}
+
+pub fn equip_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let (user_id, item_name) = match (interaction.user_id(), interaction.option_str("item")) {
+        (Some(u), Some(i)) => (u, i),
+        _ => return ResponseOject::ephemeral(locale.text("inventory.item_required"))
+    };
+
+    let item = match state.catalog.find(item_name) {
+        Some(i) => i,
+        None => return ResponseOject::ephemeral(locale.format("item.unknown", &[("item", &item_name)]))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        let ruleset = campaign.ruleset.get();
+        let player = match campaign.player_mut(user_id) {
+            Some(p) => p,
+            None => return ResponseOject::ephemeral(locale.text("player.not_joined"))
+        };
+
+        match player.inventory.equip(item, &locale) {
+            Ok(replaced) => {
+                let mut message = locale.format("inventory.equipped", &[("player", &player.name), ("item", &locale.term(&item.name))]);
+                if let Some(r) = replaced {
+                    message.push_str(&locale.format("inventory.replacing", &[("item", &locale.term(&r))]));
+                }
+                message.push_str(&locale.format("inventory.equipped_stats", &[
+                    ("defense", &ruleset.defense(player, &state.catalog)),
+                    ("attack", &format!("{:+}", player.attack_bonus(&state.catalog)))
+                ]));
+                ResponseOject::new(message)
+            },
+            Err(e) => ResponseOject::ephemeral(e)
+        }
+    })
+}
+
+/// suggests catalog item names matching what the user has typed so far
+pub fn item_autocomplete(state: &AppState, interaction: &Interaction) -> AutocompleteResponse {
+    let partial = interaction.focused_option()
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+
+    let choices: Vec<CommandOptionChoice> = state.catalog.search(partial)
+        .into_iter()
+        .take(MAX_AUTOCOMPLETE_CHOICES)
+        .map(|i| i18n::choice(serde_json::Value::String(i.name.clone()), |locale| locale.term(&i.name)))
+        .collect();
+
+    AutocompleteResponse::new(choices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn catalog() -> ItemCatalog {
+        serde_json::from_str(include_str!("../items.json")).unwrap()
+    }
+
+    #[test]
+    fn equipping_into_a_taken_slot_replaces_the_item() {
+        let catalog = catalog();
+        let locale = Locale::new(None, None);
+        let mut inventory = Inventory::new();
+        inventory.add("Dagger", 1, &locale).unwrap();
+        inventory.add("Longsword +1", 1, &locale).unwrap();
+        inventory.add("Shield", 1, &locale).unwrap();
+
+        assert_eq!(inventory.equip(catalog.find("dagger").unwrap(), &locale), Ok(None));
+        assert_eq!(inventory.equip(catalog.find("shield").unwrap(), &locale), Ok(None));
+        assert_eq!(inventory.equip(catalog.find("longsword +1").unwrap(), &locale), Ok(Some(String::from("Dagger"))));
+
+        assert_eq!(inventory.equipped.len(), 2);
+        assert_eq!(inventory.equipped[&EquipSlot::MainHand], "Longsword +1");
+        assert_eq!(inventory.equipped[&EquipSlot::OffHand], "Shield");
+        assert_eq!(inventory.attack_bonus(&catalog), 1);
+        assert_eq!(inventory.quantity("Dagger"), 1);
+    }
+
+    #[test]
+    fn only_held_equipment_can_be_equipped() {
+        let catalog = catalog();
+        let locale = Locale::new(None, None);
+        let mut inventory = Inventory::new();
+        inventory.add("Rope", 1, &locale).unwrap();
+
+        assert!(inventory.equip(catalog.find("rope").unwrap(), &locale).is_err());
+        assert!(inventory.equip(catalog.find("chain mail").unwrap(), &locale).is_err());
+        assert!(inventory.equipped.is_empty());
+    }
+
+    #[test]
+    fn giving_away_the_last_one_unequips_it() {
+        let catalog = catalog();
+        let locale = Locale::new(None, None);
+        let mut inventory = Inventory::new();
+        inventory.add("Chain Shirt", 2, &locale).unwrap();
+        inventory.equip(catalog.find("chain shirt").unwrap(), &locale).unwrap();
+
+        inventory.remove("chain shirt", 1, &locale).unwrap();
+        assert_eq!(inventory.equipped.get(&EquipSlot::Body).map(|s| s.as_str()), Some("Chain Shirt"));
+
+        assert!(inventory.remove("chain shirt", 2, &locale).is_err());
+        inventory.remove("chain shirt", 1, &locale).unwrap();
+        assert!(inventory.equipped.is_empty());
+        assert_eq!(inventory.quantity("Chain Shirt"), 0);
+        assert_eq!(inventory.ac_bonus(&catalog), 0);
+    }
+
+    #[test]
+    fn adding_past_the_largest_count_changes_nothing() {
+        let locale = Locale::new(None, None);
+        let mut inventory = Inventory::new();
+        inventory.add("Torch", u32::MAX - 1, &locale).unwrap();
+
+        assert!(inventory.add("torch", 2, &locale).is_err());
+        assert_eq!(inventory.quantity("Torch"), u32::MAX - 1);
+        inventory.add("torch", 1, &locale).unwrap();
+        assert_eq!(inventory.quantity("Torch"), u32::MAX);
+    }
+}
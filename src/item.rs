@@ -0,0 +1,90 @@
+use std::fs;
+use std::error::Error;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemKind {
+    Weapon,
+    Armor,
+    Shield,
+    Consumable,
+    Gear,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum EquipSlot {
+    MainHand,
+    OffHand,
+    Body,
+}
+
+impl EquipSlot {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EquipSlot::MainHand => "main hand",
+            EquipSlot::OffHand => "off hand",
+            EquipSlot::Body => "body",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Item {
+    pub name: String,
+    pub kind: ItemKind,
+    pub description: String,
+
+    /// weight in pounds
+    pub weight: f32,
+
+    /// value in copper pieces
+    pub value: u32,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slot: Option<EquipSlot>,
+
+    #[serde(default)]
+    pub ac_bonus: i32,
+
+    #[serde(default)]
+    pub attack_bonus: i32,
+
+    /// damage dice for weapons, e.g. "1d8"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub damage: Option<String>,
+
+    /// hit points restored when a consumable is used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heal: Option<u8>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+pub struct ItemCatalog {
+    pub items: Vec<Item>
+}
+
+impl ItemCatalog {
+    pub fn load(path: &Path) -> Result<ItemCatalog, Box<dyn Error + Send + Sync>> {
+        let catalog_string: String = fs::read_to_string(path)?;
+        let catalog: ItemCatalog = serde_json::from_str(&catalog_string)?;
+
+        log::info!("loaded {} items from {}", catalog.items.len(), path.display());
+        Ok(catalog)
+    }
+
+    /// case insensitive lookup by item name
+    pub fn find(&self, name: &str) -> Option<&Item> {
+        self.items.iter().find(|i| i.name.eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// items whose name contains the partial input, used for autocomplete
+    pub fn search(&self, partial: &str) -> Vec<&Item> {
+        let partial = partial.trim().to_lowercase();
+        self.items.iter()
+            .filter(|i| i.name.to_lowercase().contains(&partial))
+            .collect()
+    }
+}
@@ -0,0 +1,484 @@
+use std::error::Error;
+use std::sync::Arc;
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response}
+};
+use chrono::{DateTime, NaiveDate};
+use serde::Deserialize;
+use super::campaign::{Campaign, with_campaign};
+use super::discord_data_structs::{Interaction, MessageObject, ResponseOject};
+use super::history::{HistoryEntry, HistoryKind};
+use super::i18n::{self, Locale};
+use super::AppState;
+
+/// how long past its scheduled end a session still counts, games tend to run over
+pub const SESSION_OVERRUN_SECONDS: u64 = 3600;
+
+pub const DEFAULT_ADMIN_URL: &str = "http://127.0.0.1:3000";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JournalFormat {
+    Markdown,
+    Html,
+}
+
+impl JournalFormat {
+    pub fn from_key(key: &str) -> Option<JournalFormat> {
+        match key.trim().to_lowercase().as_str() {
+            "markdown" | "md" => Some(JournalFormat::Markdown),
+            "html" => Some(JournalFormat::Html),
+            _ => None
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            JournalFormat::Markdown => "md",
+            JournalFormat::Html => "html"
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            JournalFormat::Markdown => "text/markdown; charset=utf-8",
+            JournalFormat::Html => "text/html; charset=utf-8"
+        }
+    }
+}
+
+/// which part of the history goes into the journal. every bound is optional and they
+/// narrow each other down
+#[derive(Clone, Debug, Default)]
+pub struct JournalFilter {
+    pub session: Option<u32>,
+    pub from: Option<NaiveDate>,
+
+    /// last day included
+    pub to: Option<NaiveDate>,
+}
+
+impl JournalFilter {
+    /// reads dates written as YYYY-MM-DD
+    pub fn parse(session: Option<u32>, from: Option<&str>, to: Option<&str>, locale: &Locale) -> Result<JournalFilter, String> {
+        let date = |input: &str| NaiveDate::parse_from_str(input.trim(), "%Y-%m-%d")
+            .map_err(|_| locale.format("journal.bad_date", &[("date", &input)]));
+
+        let filter = JournalFilter {
+            session,
+            from: from.map(date).transpose()?,
+            to: to.map(date).transpose()?
+        };
+
+        if let (Some(from), Some(to)) = (filter.from, filter.to) && from > to {
+            return Err(locale.text("journal.reversed_range"))
+        }
+        Ok(filter)
+    }
+
+    /// the unix timestamps in seconds the journal covers, both ends included. dates are read as UTC
+    fn window(&self, campaign: &Campaign, locale: &Locale) -> Result<(u64, u64), String> {
+        let mut start = 0;
+        let mut end = u64::MAX;
+
+        if let Some(id) = self.session {
+            let session = match campaign.sessions.iter().find(|s| s.id == id) {
+                Some(s) => s,
+                None => return Err(locale.format("journal.unknown_session", &[("id", &id)]))
+            };
+            start = session.start;
+            end = session.start + session.duration_minutes * 60 + SESSION_OVERRUN_SECONDS;
+        }
+        if let Some(from) = self.from {
+            start = start.max(from.and_hms_opt(0, 0, 0).map(|t| t.and_utc().timestamp().max(0) as u64).unwrap_or(0));
+        }
+        if let Some(to) = self.to {
+            end = end.min(to.and_hms_opt(23, 59, 59).map(|t| t.and_utc().timestamp().max(0) as u64).unwrap_or(u64::MAX));
+        }
+
+        Ok((start, end))
+    }
+
+    fn describe(&self, campaign: &Campaign, locale: &Locale) -> String {
+        let mut parts: Vec<String> = Vec::new();
+        if let Some(session) = self.session.and_then(|id| campaign.sessions.iter().find(|s| s.id == id)) {
+            parts.push(locale.format("journal.session", &[("id", &session.id), ("title", &session.title)]));
+        }
+        match (self.from, self.to) {
+            (Some(from), Some(to)) => parts.push(locale.format("journal.between", &[("from", &from), ("to", &to)])),
+            (Some(from), None) => parts.push(locale.format("journal.since", &[("from", &from)])),
+            (None, Some(to)) => parts.push(locale.format("journal.until", &[("to", &to)])),
+            (None, None) => ()
+        }
+        parts.join(", ")
+    }
+}
+
+/// one history entry, ready to be written out
+struct JournalLine<'a> {
+    kind: HistoryKind,
+    author: Option<String>,
+    text: &'a str,
+}
+
+/// history entries grouped by the UTC day they happened on
+fn days<'a>(campaign: &'a Campaign, start: u64, end: u64) -> Vec<(String, Vec<JournalLine<'a>>)> {
+    let mut days: Vec<(String, Vec<JournalLine>)> = Vec::new();
+
+    for entry in campaign.history.iter().filter(|h| (start..=end).contains(&h.timestamp)) {
+        let day = day_of(entry);
+        let line = JournalLine {
+            kind: entry.kind,
+            author: entry.player_id.and_then(|id| campaign.player(id)).map(|p| p.name.clone()),
+            text: &entry.text
+        };
+
+        match days.last_mut() {
+            Some((current, lines)) if *current == day => lines.push(line),
+            _ => days.push((day, vec![line]))
+        }
+    }
+
+    days
+}
+
+fn day_of(entry: &HistoryEntry) -> String {
+    DateTime::from_timestamp(entry.timestamp as i64, 0)
+        .map(|t| t.format("%Y-%m-%d").to_string())
+        .unwrap_or_default()
+}
+
+/// writes the campaign's story, rolls and milestones as a document. fails when the filter
+/// doesn't match anything, so nobody gets sent an empty file
+pub fn render(campaign: &Campaign, filter: &JournalFilter, format: JournalFormat, locale: &Locale) -> Result<String, String> {
+    let (start, end) = filter.window(campaign, locale)?;
+    let days = days(campaign, start, end);
+    if days.is_empty() {
+        return Err(locale.text("journal.empty"))
+    }
+
+    let subtitle = filter.describe(campaign, locale);
+    Ok(match format {
+        JournalFormat::Markdown => markdown(&subtitle, &days, locale),
+        JournalFormat::Html => html(&subtitle, &days, locale)
+    })
+}
+
+fn markdown(subtitle: &str, days: &[(String, Vec<JournalLine>)], locale: &Locale) -> String {
+    let mut document = format!("# {}\n", locale.text("journal.title"));
+    if !subtitle.is_empty() {
+        document.push_str(&format!("\n*{}*\n", subtitle));
+    }
+
+    for (day, lines) in days {
+        document.push_str(&format!("\n## {}\n", day));
+        for line in lines {
+            let quoted = line.text.replace('\n', "\n> ");
+            let block = match line.kind {
+                HistoryKind::Action => format!("**{}**: {}", line.author.clone().unwrap_or(locale.text("journal.a_player")), line.text),
+                HistoryKind::Narration => line.text.to_string(),
+                HistoryKind::Roll => format!("> **{}** {}", locale.text("journal.roll"), quoted),
+                HistoryKind::Resolution => format!("> {}", quoted),
+                HistoryKind::Quest => format!("**{}:** {}", locale.text("journal.quest"), line.text),
+                HistoryKind::Experience | HistoryKind::LevelUp => format!("*{}*", line.text)
+            };
+            document.push_str(&format!("\n{}\n", block));
+        }
+    }
+
+    document
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+        .replace('\n', "<br>")
+}
+
+const HTML_STYLE: &str = "body{font-family:Georgia,serif;max-width:46em;margin:2em auto;padding:0 1em;line-height:1.5;color:#222}\
+h1{margin-bottom:0}.subtitle{color:#666;font-style:italic}h2{border-bottom:1px solid #ccc;margin-top:2em}\
+blockquote{margin:.5em 0;padding:.25em 1em;border-left:3px solid #bbb;color:#555;font-family:monospace}\
+.quest{color:#8a5a00;font-weight:bold}.progress{color:#2a6f2a;font-style:italic}";
+
+/// a standalone page with the styles inlined, so the file opens the same anywhere
+fn html(subtitle: &str, days: &[(String, Vec<JournalLine>)], locale: &Locale) -> String {
+    let title = escape_html(&locale.text("journal.title"));
+    let mut document = format!("<!DOCTYPE html>\n<html lang=\"{}\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+        locale.text("journal.language"), title, HTML_STYLE, title);
+    if !subtitle.is_empty() {
+        document.push_str(&format!("<p class=\"subtitle\">{}</p>\n", escape_html(subtitle)));
+    }
+
+    for (day, lines) in days {
+        document.push_str(&format!("<h2>{}</h2>\n", escape_html(day)));
+        for line in lines {
+            let text = escape_html(line.text);
+            let block = match line.kind {
+                HistoryKind::Action => format!("<p class=\"action\"><strong>{}</strong>: {}</p>", escape_html(&line.author.clone().unwrap_or(locale.text("journal.a_player"))), text),
+                HistoryKind::Narration => format!("<p class=\"narration\">{}</p>", text),
+                HistoryKind::Roll => format!("<blockquote class=\"roll\"><strong>{}</strong> {}</blockquote>", locale.text("journal.roll"), text),
+                HistoryKind::Resolution => format!("<blockquote class=\"resolution\">{}</blockquote>", text),
+                HistoryKind::Quest => format!("<p class=\"quest\">{}: {}</p>", locale.text("journal.quest"), text),
+                HistoryKind::Experience | HistoryKind::LevelUp => format!("<p class=\"progress\">{}</p>", text)
+            };
+            document.push_str(&block);
+            document.push('\n');
+        }
+    }
+
+    document.push_str("</body>\n</html>\n");
+    document
+}
+
+/// answers with a deferred response and uploads the journal into it
+pub fn export_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let format = match interaction.option_str("format") {
+        Some(key) => match JournalFormat::from_key(key) {
+            Some(f) => f,
+            None => return ResponseOject::ephemeral(locale.text("journal.bad_format"))
+        },
+        None => JournalFormat::Markdown
+    };
+    let session = match interaction.option_i64("session") {
+        Some(s) if s < 0 => return ResponseOject::ephemeral(locale.text("journal.no_session")),
+        s => s.map(|s| s as u32)
+    };
+    let filter = match JournalFilter::parse(session, interaction.option_str("from"), interaction.option_str("to"), &locale) {
+        Ok(f) => f,
+        Err(e) => return ResponseOject::ephemeral(e)
+    };
+
+    let mut document: Option<String> = None;
+    let response = with_campaign(state, interaction, |campaign| {
+        match render(campaign, &filter, format, &locale) {
+            Ok(d) => {
+                document = Some(d);
+                ResponseOject::deferred()
+            },
+            Err(e) => ResponseOject::ephemeral(e)
+        }
+    });
+
+    let document = match document {
+        Some(d) => d,
+        None => return response
+    };
+
+    let discord = state.discord.clone();
+    let application_id = interaction.application_id;
+    let token = interaction.token.clone();
+    let filename = format!("journal.{}", format.extension());
+    let content = locale.text("journal.uploaded");
+
+    tokio::spawn(async move {
+        let message = MessageObject {
+            content,
+            ..Default::default()
+        };
+
+        if let Err(e) = discord.edit_original_response_with_file(application_id, &token, message, &filename, document.into_bytes()).await {
+            log::error!("could not upload campaign journal\n{}", e);
+        }
+    });
+
+    response
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JournalQuery {
+    pub format: Option<String>,
+    pub session: Option<u32>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// GET /admin/journal/{campaign_id}, the server side of the export CLI. only answers when
+/// ADMIN_TOKEN is set and sent as a bearer token
+pub async fn journal_route(
+    State(state): State<Arc<AppState>>,
+    Path(campaign_id): Path<u32>,
+    Query(query): Query<JournalQuery>,
+    headers: HeaderMap) -> Response {
+
+    let authorized = match &state.admin_token {
+        Some(token) => headers.get(header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .is_some_and(|h| h == format!("Bearer {}", token)),
+        None => false
+    };
+    if !authorized {
+        return (StatusCode::UNAUTHORIZED, String::from("unauthorized")).into_response()
+    }
+
+    let format = match query.format.as_deref().map(JournalFormat::from_key) {
+        Some(Some(f)) => f,
+        Some(None) => return (StatusCode::BAD_REQUEST, String::from("format must be markdown or html")).into_response(),
+        None => JournalFormat::Markdown
+    };
+    // the admin route isn't tied to a discord user, so it answers in the default language
+    let locale = Locale::new(None, None);
+    let filter = match JournalFilter::parse(query.session, query.from.as_deref(), query.to.as_deref(), &locale) {
+        Ok(f) => f,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response()
+    };
+
+    let result = match state.campaigns.lock() {
+        Ok(lock) => match lock.get(campaign_id) {
+            Some(campaign) => render(campaign, &filter, format, &locale).map_err(|e| (StatusCode::NOT_FOUND, e)),
+            None => Err((StatusCode::NOT_FOUND, format!("there is no campaign #{}", campaign_id)))
+        },
+        Err(e) => {
+            log::error!("unable to obtain lock for app state\n{}", e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, String::from("unable to process request")))
+        }
+    };
+
+    match result {
+        Ok(document) => (StatusCode::OK, [(header::CONTENT_TYPE, format.content_type())], document).into_response(),
+        Err(e) => e.into_response()
+    }
+}
+
+const CLI_USAGE: &str = "usage: discord_gm_bot export <campaign id> [--format markdown|html] [--session <id>] [--from YYYY-MM-DD] [--to YYYY-MM-DD] [--out <file>]\n\
+asks the running bot at ADMIN_URL (default http://127.0.0.1:3000) for the journal, authenticating with ADMIN_TOKEN";
+
+/// `discord_gm_bot export ...`: fetches a campaign journal from the running bot and writes
+/// it to a file, or to stdout without --out
+pub async fn export_cli(args: &[String]) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut campaign_id: Option<&str> = None;
+    let mut query: Vec<(&str, &str)> = Vec::new();
+    let mut out: Option<&str> = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let flag = match arg.as_str() {
+            "--format" => "format",
+            "--session" => "session",
+            "--from" => "from",
+            "--to" => "to",
+            "--out" => "out",
+            "-h" | "--help" => {
+                println!("{}", CLI_USAGE);
+                return Ok(())
+            },
+            a if !a.starts_with("--") && campaign_id.is_none() => {
+                campaign_id = Some(a);
+                continue
+            },
+            a => return Err(format!("unexpected argument {}\n{}", a, CLI_USAGE).into())
+        };
+
+        let value = match args.next() {
+            Some(v) => v.as_str(),
+            None => return Err(format!("{} needs a value\n{}", arg, CLI_USAGE).into())
+        };
+        match flag {
+            "out" => out = Some(value),
+            _ => query.push((flag, value))
+        }
+    }
+
+    let campaign_id = match campaign_id {
+        Some(c) => c,
+        None => return Err(CLI_USAGE.into())
+    };
+    let token = std::env::var("ADMIN_TOKEN").map_err(|_| "ADMIN_TOKEN is not set")?;
+    let base_url = std::env::var("ADMIN_URL").unwrap_or(String::from(DEFAULT_ADMIN_URL));
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/journal/{}", base_url.trim_end_matches('/'), campaign_id))
+        .query(&query)
+        .bearer_auth(token)
+        .send()
+        .await?;
+
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        return Err(format!("the bot returned {}: {}", status, body).into())
+    }
+
+    match out {
+        Some(path) => {
+            std::fs::write(path, body)?;
+            println!("wrote {}", path);
+        },
+        None => print!("{}", body)
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snowflake::Snowflake;
+    use crate::player::Player;
+    use crate::ruleset::RulesetId;
+    use crate::session::Session;
+
+    fn entry(timestamp: u64, kind: HistoryKind, player_id: Option<u64>, text: &str) -> HistoryEntry {
+        HistoryEntry { timestamp, kind, player_id: player_id.map(Snowflake::new), text: text.to_string() }
+    }
+
+    fn campaign() -> Campaign {
+        let mut campaign = Campaign::new(Snowflake::new(10), RulesetId::D20);
+        let player = Player::new(Snowflake::new(1), "Ayla", "", campaign.ruleset.get());
+        campaign.players.push(player);
+
+        // 2030-07-01 18:00 UTC and the day after
+        let day = 1_909_159_200;
+        campaign.history = vec![
+            entry(day, HistoryKind::Action, Some(1), "I open the <door>"),
+            entry(day + 60, HistoryKind::Roll, Some(1), "Ayla rolls Strength: 17"),
+            entry(day + 120, HistoryKind::Narration, None, "the door creaks open"),
+            entry(day + 86_400, HistoryKind::Quest, None, "the party completed the quest the lost lantern")
+        ];
+        campaign.sessions.push(Session {
+            id: 0,
+            title: String::from("the mill"),
+            start: day - 600,
+            duration_minutes: 30,
+            timezone: String::from("UTC"),
+            rsvps: Vec::new(),
+            reminders_sent: Vec::new(),
+            message_id: None
+        });
+        campaign
+    }
+
+    #[test]
+    fn markdown_groups_entries_by_day() {
+        let campaign = campaign();
+        let document = render(&campaign, &JournalFilter::default(), JournalFormat::Markdown, &Locale::new(None, None)).unwrap();
+
+        assert!(document.contains("## 2030-07-01\n\n**Ayla**: I open the <door>\n\n> **roll** Ayla rolls Strength: 17\n\nthe door creaks open\n"));
+        assert!(document.contains("## 2030-07-02\n\n**quest:** the party completed"));
+    }
+
+    #[test]
+    fn filters_narrow_the_range() {
+        let campaign = campaign();
+        let locale = Locale::new(None, None);
+
+        let session = JournalFilter::parse(Some(0), None, None, &locale).unwrap();
+        let document = render(&campaign, &session, JournalFormat::Html, &locale).unwrap();
+        assert!(document.contains("session #0: the mill"));
+        assert!(document.contains("I open the &lt;door&gt;"));
+        assert!(!document.contains("lost lantern"));
+
+        let dates = JournalFilter::parse(None, Some("2030-07-02"), Some("2030-07-02"), &locale).unwrap();
+        let document = render(&campaign, &dates, JournalFormat::Markdown, &locale).unwrap();
+        assert!(!document.contains("Ayla"));
+        assert!(document.contains("lost lantern"));
+
+        assert!(render(&campaign, &JournalFilter::parse(Some(3), None, None, &locale).unwrap(), JournalFormat::Markdown, &locale).is_err());
+        assert!(render(&campaign, &JournalFilter::parse(None, Some("2031-01-01"), None, &locale).unwrap(), JournalFormat::Markdown, &locale).is_err());
+        assert!(JournalFilter::parse(None, Some("2030-07-02"), Some("2030-07-01"), &locale).is_err());
+        assert!(JournalFilter::parse(None, Some("yesterday"), None, &locale).is_err());
+    }
+}
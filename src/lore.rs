@@ -0,0 +1,298 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use super::campaign::{Campaign, with_campaign};
+use super::discord_data_structs::{
+    AutocompleteResponse,
+    CommandOptionChoice,
+    Interaction,
+    ResponseOject,
+    MAX_AUTOCOMPLETE_CHOICES
+};
+use super::i18n::{self, Locale};
+use super::AppState;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LoreKind {
+    Location,
+    Npc,
+    Faction,
+    Lore,
+}
+
+impl LoreKind {
+    pub fn from_key(key: &str) -> Option<LoreKind> {
+        match key.trim().to_lowercase().as_str() {
+            "location" => Some(LoreKind::Location),
+            "npc" => Some(LoreKind::Npc),
+            "faction" => Some(LoreKind::Faction),
+            "lore" => Some(LoreKind::Lore),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for LoreKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            LoreKind::Location => "location",
+            LoreKind::Npc => "NPC",
+            LoreKind::Faction => "faction",
+            LoreKind::Lore => "lore"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// one page of the campaign's world wiki
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LoreEntry {
+    pub name: String,
+    pub kind: LoreKind,
+    pub description: String,
+    pub tags: Vec<String>,
+
+    /// only shown to the GM, and to the narrator when they share it
+    pub secret: Option<String>,
+
+    /// whether the GM lets the narrator know the secret
+    #[serde(default)]
+    pub share_secret: bool,
+}
+
+impl LoreEntry {
+    pub fn describe(&self, with_secret: bool, locale: &Locale) -> String {
+        let mut text = format!("**{}** ({})\n{}", self.name, locale.term(&self.kind.to_string()), self.description);
+        if !self.tags.is_empty() {
+            text.push_str(&locale.format("lore.tags", &[("tags", &self.tags.join(", "))]));
+        }
+        if with_secret && let Some(secret) = &self.secret {
+            text.push_str(&locale.format("lore.secret", &[("secret", secret)]));
+            if self.share_secret {
+                text.push_str(&locale.text("lore.secret_shared"));
+            }
+        }
+        text
+    }
+
+    /// one line for the narrator prompt. the secret is only in it when the GM shares it, so
+    /// the story can stay consistent with it
+    pub fn prompt_line(&self) -> String {
+        let mut line = format!("- {} ({}): {}", self.name, self.kind, self.description);
+        if self.share_secret && let Some(secret) = &self.secret {
+            line.push_str(&format!(" Secret, never reveal it outright: {}", secret));
+        }
+        line
+    }
+
+    fn matches(&self, query: &str) -> bool {
+        self.name.to_lowercase().contains(query)
+            || self.description.to_lowercase().contains(query)
+            || self.tags.iter().any(|t| t.to_lowercase() == query)
+    }
+}
+
+/// lore entries ordered for the narrator: the ones named in `text` first, then the rest
+pub fn relevant_lore<'a>(campaign: &'a Campaign, text: &str) -> Vec<&'a LoreEntry> {
+    let text = text.to_lowercase();
+    let (mut mentioned, rest): (Vec<&LoreEntry>, Vec<&LoreEntry>) = campaign.lore.iter()
+        .partition(|e| text.contains(&e.name.to_lowercase()));
+    mentioned.extend(rest);
+    mentioned
+}
+
+fn find<'a>(campaign: &'a Campaign, name: &str) -> Option<&'a LoreEntry> {
+    campaign.lore.iter().find(|e| e.name.eq_ignore_ascii_case(name.trim()))
+}
+
+pub fn lore_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    match interaction.subcommand() {
+        Some("add") => add(state, interaction),
+        Some("show") => show(state, interaction),
+        Some("search") => search(state, interaction),
+        Some("remove") => remove(state, interaction),
+        _ => ResponseOject::ephemeral(i18n::locale(state, interaction).format("error.unknown_command", &[("command", &"lore")]))
+    }
+}
+
+/// adds an entry, or replaces the one with the same name
+fn add(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let (kind, name, description) = match (interaction.option_str("kind").and_then(LoreKind::from_key), interaction.option_str("name"), interaction.option_str("description")) {
+        (Some(k), Some(n), Some(d)) => (k, n.trim().to_string(), d.trim().to_string()),
+        _ => return ResponseOject::ephemeral(locale.text("lore.add_required"))
+    };
+    let tags: Vec<String> = interaction.option_str("tags")
+        .map(|t| t.split(',').map(|t| t.trim().to_lowercase()).filter(|t| !t.is_empty()).collect())
+        .unwrap_or_default();
+    let secret = interaction.option_str("secret")
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+    let share_secret = interaction.option_bool("share_secret").unwrap_or(false);
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("lore.not_gm_add"))
+        }
+
+        let entry = LoreEntry {
+            name: name.clone(),
+            kind,
+            description: description.clone(),
+            tags: tags.clone(),
+            secret: secret.clone(),
+            share_secret
+        };
+        let text = entry.describe(true, &locale);
+
+        match campaign.lore.iter_mut().find(|e| e.name.eq_ignore_ascii_case(&name)) {
+            Some(existing) => {
+                *existing = entry;
+                ResponseOject::ephemeral(locale.format("lore.updated", &[("entry", &text)]))
+            },
+            None => {
+                campaign.lore.push(entry);
+                ResponseOject::ephemeral(locale.format("lore.added", &[("entry", &text)]))
+            }
+        }
+    })
+}
+
+/// posts an entry for everyone, or privately with its secret for the GM
+fn show(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let name = match interaction.option_str("name") {
+        Some(n) => n,
+        None => return ResponseOject::ephemeral(locale.text("lore.name_required"))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        let entry = match find(campaign, name) {
+            Some(e) => e,
+            None => return ResponseOject::ephemeral(locale.format("lore.unknown", &[("name", &name)]))
+        };
+
+        if campaign.is_gm(interaction) {
+            ResponseOject::ephemeral(entry.describe(true, &locale))
+        } else {
+            ResponseOject::new(entry.describe(false, &locale))
+        }
+    })
+}
+
+fn search(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let query = interaction.option_str("query").unwrap_or("").trim().to_lowercase();
+    let kind = interaction.option_str("kind").and_then(LoreKind::from_key);
+
+    with_campaign(state, interaction, |campaign| {
+        let results: Vec<String> = campaign.lore.iter()
+            .filter(|e| kind.is_none_or(|k| e.kind == k))
+            .filter(|e| e.matches(&query))
+            .map(|e| format!("- **{}** ({}){}", e.name, locale.term(&e.kind.to_string()),
+                if e.tags.is_empty() { String::new() } else { format!(" [{}]", e.tags.join(", ")) }))
+            .collect();
+
+        if results.is_empty() {
+            ResponseOject::ephemeral(locale.text("lore.nothing_found"))
+        } else {
+            ResponseOject::ephemeral(results.join("\n"))
+        }
+    })
+}
+
+fn remove(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let name = match interaction.option_str("name") {
+        Some(n) => n.trim().to_string(),
+        None => return ResponseOject::ephemeral(locale.text("lore.name_required"))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("lore.not_gm_remove"))
+        }
+
+        match campaign.lore.iter().position(|e| e.name.eq_ignore_ascii_case(&name)) {
+            Some(index) => {
+                let entry = campaign.lore.remove(index);
+                ResponseOject::ephemeral(locale.format("lore.removed", &[("name", &entry.name)]))
+            },
+            None => ResponseOject::ephemeral(locale.format("lore.unknown", &[("name", &name)]))
+        }
+    })
+}
+
+pub fn lore_autocomplete(state: &AppState, interaction: &Interaction) -> AutocompleteResponse {
+    let partial = interaction.focused_option()
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .to_lowercase();
+
+    let choices: Vec<CommandOptionChoice> = match state.campaigns.lock() {
+        Ok(lock) => match interaction.channel_id.and_then(|c| lock.in_channel(c)) {
+            Some(campaign) => campaign.lore.iter()
+                .filter(|e| e.name.to_lowercase().contains(&partial))
+                .take(MAX_AUTOCOMPLETE_CHOICES)
+                .map(|e| i18n::choice(serde_json::Value::String(e.name.clone()), |_| e.name.clone()))
+                .collect(),
+            None => Vec::new()
+        },
+        Err(e) => {
+            log::error!("unable to obtain lock for app state\n{}", e);
+            Vec::new()
+        }
+    };
+
+    AutocompleteResponse::new(choices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ruleset::RulesetId;
+    use crate::snowflake::Snowflake;
+
+    fn entry(name: &str, secret: Option<&str>) -> LoreEntry {
+        LoreEntry {
+            name: name.to_string(),
+            kind: LoreKind::Npc,
+            description: String::from("keeps the lighthouse"),
+            tags: vec![String::from("harrow")],
+            secret: secret.map(|s| s.to_string()),
+            share_secret: false
+        }
+    }
+
+    #[test]
+    fn secrets_reach_the_narrator_only_when_shared() {
+        assert_eq!(entry("Old Mara", None).prompt_line(), "- Old Mara (NPC): keeps the lighthouse");
+
+        let mut mara = entry("Old Mara", Some("she is the smuggler"));
+        assert_eq!(mara.prompt_line(), "- Old Mara (NPC): keeps the lighthouse");
+
+        mara.share_secret = true;
+        assert_eq!(mara.prompt_line(), "- Old Mara (NPC): keeps the lighthouse Secret, never reveal it outright: she is the smuggler");
+
+        let locale = Locale::new(None, None);
+        assert!(mara.describe(true, &locale).ends_with(&locale.text("lore.secret_shared")));
+        assert!(!mara.describe(false, &locale).contains("smuggler"));
+
+        let archived: LoreEntry = serde_json::from_str(r#"{"name":"Old Mara","kind":"npc","description":"","tags":[],"secret":"she is the smuggler"}"#).unwrap();
+        assert!(!archived.share_secret);
+    }
+
+    #[test]
+    fn entries_named_in_the_text_come_first() {
+        let mut campaign = Campaign::new(Snowflake::new(10), RulesetId::D20);
+        campaign.lore.push(entry("Old Mara", None));
+        campaign.lore.push(entry("Brother Tam", None));
+
+        let names: Vec<&str> = relevant_lore(&campaign, "I ask BROTHER TAM about the light").iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["Brother Tam", "Old Mara"]);
+
+        let names: Vec<&str> = relevant_lore(&campaign, "I wait").iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["Old Mara", "Brother Tam"]);
+    }
+}
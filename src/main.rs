@@ -1,5 +1,4 @@
 use tokio::{self, net::TcpListener};
-use log;
 use std::fs;
 use std::str::FromStr;
 use std::{
@@ -9,7 +8,6 @@ use std::{
     env
 };
 use bytes::Bytes;
-use env_logger;
 use axum::{
     Router, 
     body::Body, 
@@ -18,6 +16,7 @@ use axum::{
         header::HeaderMap
     }, 
     routing::{
+        get,
         post
     },
     extract::{
@@ -35,19 +34,73 @@ use std::sync::{
 };
 pub mod campaign;
 pub mod player;
+pub mod item;
+pub mod inventory;
+pub mod currency;
+pub mod shop;
+pub mod trade;
+pub mod history;
+pub mod advancement;
+pub mod dice;
+pub mod checks;
+pub mod discord_api;
+pub mod ruleset;
+pub mod d20_ruleset;
+pub mod pbta_ruleset;
+pub mod combat;
+pub mod narrator;
+pub mod context;
+pub mod intent;
+pub mod action;
+pub mod stream;
+pub mod approval;
+pub mod round;
+pub mod session;
+pub mod lore;
+pub mod quest;
+pub mod journal;
+pub mod archive;
+pub mod thread;
+pub mod permissions;
+pub mod guild_config;
+pub mod i18n;
+pub mod snowflake;
+pub mod gateway;
+#[allow(non_snake_case)]
 pub mod pingVerifier;
 use pingVerifier::PingVerifier;
 
 pub mod discord_data_structs;
-use discord_data_structs::Interaction;
-use crate::discord_data_structs::Pong;
+use discord_data_structs::{Interaction, InteractionData};
 use crate::{
-    campaign::Campaign, 
+    campaign::{
+        Campaign,
+        Campaigns,
+        with_campaign
+    },
+    player::Player,
+    item::ItemCatalog,
+    discord_api::DiscordClient,
+    advancement::AdvancementConfig,
+    ruleset::RulesetId,
+    permissions::Permissions,
+    snowflake::Snowflake,
+    context::ContextConfig,
+    session::ReminderConfig,
+    gateway::GatewayConfig,
+    guild_config::{
+        GuildConfigs,
+        DEFAULT_GUILD_CONFIG_PATH
+    },
+    narrator::{
+        Narrator,
+        NarratorConfig,
+        OpenAiNarrator
+    },
     discord_data_structs::{
-        MessageObject, 
+        AutocompleteResponse,
         ResponseOject,
-        Commands,
-        Command
+        Commands
     }
 };
 use reqwest::{
@@ -59,15 +112,31 @@ use reqwest::{
 
 
 
-struct AppState {
-    campaigns: Mutex<Vec<Campaign>>
+pub struct AppState {
+    campaigns: Mutex<Campaigns>,
+    guild_configs: Mutex<GuildConfigs>,
+    catalog: ItemCatalog,
+    advancement: AdvancementConfig,
+    discord: DiscordClient,
+    narrator: Option<Arc<dyn Narrator>>,
+    context: ContextConfig,
+    reminders: ReminderConfig,
+
+    /// bearer token for the admin routes, which stay closed without one
+    admin_token: Option<String>
+}
+
+impl AppState {
+    /// the narrator for a campaign, unless there is none or the campaign turned it off
+    fn narrator_for(&self, campaign: &Campaign) -> Option<Arc<dyn Narrator>> {
+        self.narrator.clone().filter(|_| campaign.narrator)
+    }
 }
 
 enum AppResponse {
-    PongInstance(Pong),
-    PongInstanceFailed(Pong),
     ResponseInstance(ResponseOject),
-    ResponseInstanceFailed(ResponseOject)
+    ResponseInstanceFailed(ResponseOject),
+    Unauthorized(ResponseOject)
 }
 
 impl IntoResponse for AppResponse {
@@ -75,17 +144,14 @@ impl IntoResponse for AppResponse {
     fn into_response(self) -> Response {
 
         match self {
-            AppResponse::PongInstance(p) => {
-                (StatusCode::OK, Json(p)).into_response() 
-            },
-            AppResponse::PongInstanceFailed(p) => {
-                (StatusCode::UNAUTHORIZED, Json(p)).into_response()
-            },
             AppResponse::ResponseInstance(r) => {
                 (StatusCode::OK, Json(r)).into_response()
             },
             AppResponse::ResponseInstanceFailed(r) => {
                 (StatusCode::INTERNAL_SERVER_ERROR, Json(r)).into_response()
+            },
+            AppResponse::Unauthorized(r) => {
+                (StatusCode::UNAUTHORIZED, Json(r)).into_response()
             }
             
         }
@@ -101,9 +167,9 @@ async fn install_commands(){
 
     let commands_path = Path::new("commands.json");
     
-    let mut commands_string: String = fs::read_to_string(&commands_path).expect("could not read commands.json file");
+    let commands_string: String = fs::read_to_string(commands_path).expect("could not read commands.json file");
 
-    let mut current_commands: Commands = serde_json::from_str(&commands_string).expect("commands.json file not correctly formatted");
+    let current_commands: Commands = serde_json::from_str(&commands_string).expect("commands.json file not correctly formatted");
 
     let client: Client = Client::new();
 
@@ -144,16 +210,65 @@ async fn install_commands(){
 async fn main() {
     env_logger::init();
     
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(|a| a.as_str()) == Some("export") {
+        if let Err(e) = journal::export_cli(&args[2..]).await {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return
+    }
+
     log::info!("starting discord dm bot");
 
-    let mut campaigns: Vec<Campaign> = Vec::new();
+    let campaigns = Campaigns::new();
+
+    let guild_config_path = env::var("GUILD_CONFIG").unwrap_or(String::from(DEFAULT_GUILD_CONFIG_PATH));
+    let guild_configs = GuildConfigs::load(Path::new(&guild_config_path))
+        .expect("could not load guild settings");
+
+    let catalog: ItemCatalog = ItemCatalog::load(Path::new("items.json"))
+        .expect("could not load items.json item catalog");
+
+    let advancement: AdvancementConfig = AdvancementConfig::load(Path::new("advancement.json"))
+        .expect("could not load advancement.json rules");
     
-    let mut app_state = Arc::new(
+    let narrator: Option<Arc<dyn Narrator>> = match NarratorConfig::from_env() {
+        Some(config) => {
+            log::info!("narrating with {} at {}", config.model, config.base_url);
+            Some(Arc::new(OpenAiNarrator::new(config)))
+        },
+        None => {
+            log::info!("NARRATOR_URL not set, narration is off");
+            None
+        }
+    };
+
+    let app_state = Arc::new(
         AppState {
-            campaigns: Mutex::new(campaigns)
+            campaigns: Mutex::new(campaigns),
+            guild_configs: Mutex::new(guild_configs),
+            catalog,
+            advancement,
+            discord: DiscordClient::from_env(),
+            narrator,
+            context: ContextConfig::from_env(),
+            reminders: ReminderConfig::from_env(),
+            admin_token: env::var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty())
         }
     );
 
+    round::spawn_deadline_task(app_state.clone());
+    session::spawn_reminder_task(app_state.clone());
+
+    match GatewayConfig::from_env() {
+        Some(config) => {
+            log::info!("connecting to the gateway at {}", config.url);
+            gateway::spawn_gateway(app_state.clone(), config);
+        },
+        None => log::info!("GATEWAY_URL not set, only interactions are handled")
+    }
+
     //install commands
     install_commands().await;
  
@@ -161,23 +276,23 @@ async fn main() {
         .route("/interactions", post(pong))
         .route("/init", post(init))
         .route("/action", post(action))
+        .route("/admin/journal/{campaign_id}", get(journal::journal_route))
         .with_state(app_state);
 
     let listener: TcpListener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
     axum::serve(listener, app).await.unwrap();
 }
 
-async fn pong(header: HeaderMap, body: Body) -> impl IntoResponse {
+async fn pong(app_state: State<Arc<AppState>>, header: HeaderMap, body: Body) -> impl IntoResponse {
     
     log::info!("VERIFICAITON OF PING BEGIN");
     log::debug!("{:?}", body);
 
-    let pong = Pong { r#type: 1 };
     let body_bytes: Bytes = match axum::body::to_bytes(body, usize::MAX).await {
         Ok(b) => b,
         Err(e) => {
             log::error!("could not convert body to bytes\n{}", e);
-            return AppResponse::PongInstance(pong) 
+            return AppResponse::ResponseInstance(ResponseOject::Pong)
         }
     };
 
@@ -188,12 +303,12 @@ async fn pong(header: HeaderMap, body: Body) -> impl IntoResponse {
         Ok(p_s) => p_s,
         Err(e) => {
             log::error!("unable to create payload and signature for verification\n{}", e);
-            return AppResponse::PongInstanceFailed(pong) 
+            return AppResponse::Unauthorized(ResponseOject::Pong)
         }
     };
 
     if !ping_verifier.verify(&payload_sig.0, &payload_sig.1) {
-        return AppResponse::PongInstanceFailed(pong) 
+        return AppResponse::Unauthorized(ResponseOject::Pong)
     }
 
     log::info!("VERIFICAITON OF PING SUCCESSFUL");
@@ -208,21 +323,30 @@ async fn pong(header: HeaderMap, body: Body) -> impl IntoResponse {
         }
     };
     
-    match body_json.r#type {
-        1 => AppResponse::PongInstance(pong),
-        2 => {
-            let message = String::from("app command acepted");
-            let r = ResponseOject::new(message);
+    match &body_json.data {
+        InteractionData::Ping => AppResponse::ResponseInstance(ResponseOject::Pong),
+        InteractionData::ApplicationCommand(_) => {
+            let r = run_command(&app_state, &body_json);
             log::debug!("response object {:?}", r);
             AppResponse::ResponseInstance(r)
         },
-        3 => {
-            let message = String::from("app command acepted");
-            let r = ResponseOject::new(message);
+        InteractionData::MessageComponent(_) => {
+            let r = run_component(&app_state, &body_json);
             log::debug!("response object {:?}", r);
             AppResponse::ResponseInstance(r)
         },
-        _ => {
+        InteractionData::Autocomplete(_) => {
+            let a = autocomplete(&app_state, &body_json);
+            log::debug!("autocomplete response {:?}", a);
+            AppResponse::ResponseInstance(ResponseOject::Autocomplete(a))
+        },
+        InteractionData::ModalSubmit(_) => {
+            let r = run_modal(&app_state, &body_json);
+            log::debug!("response object {:?}", r);
+            AppResponse::ResponseInstance(r)
+        },
+        InteractionData::Unknown(t, _) => {
+            log::warn!("unknown interaction type {}", t);
             let message = String::from("unable to process request");
             let r = ResponseOject::new(message);
             AppResponse::ResponseInstanceFailed(r)
@@ -232,102 +356,294 @@ async fn pong(header: HeaderMap, body: Body) -> impl IntoResponse {
 }
 
 
+/// routes an application command to its handler by command name
+fn run_command(app_state: &Arc<AppState>, interaction: &Interaction) -> ResponseOject {
+    let name = match interaction.command_name() {
+        Some(n) => n,
+        None => return ResponseOject::ephemeral(i18n::locale(app_state, interaction).text("error.unable"))
+    };
+
+    log::info!("running command {}", name);
+
+    match name {
+        "init" => create_campaign(app_state, interaction),
+        "join" => join(app_state, interaction),
+        "start" => start(app_state, interaction),
+        "end" => end(app_state, interaction),
+        "inventory" => inventory::inventory_command(app_state, interaction),
+        "give" => inventory::give_command(app_state, interaction),
+        "use" => inventory::use_command(app_state, interaction),
+        "equip" => inventory::equip_command(app_state, interaction),
+        "shop" => shop::shop_command(app_state, interaction),
+        "buy" => shop::buy_command(app_state, interaction),
+        "sell" => shop::sell_command(app_state, interaction),
+        "purse" => shop::purse_command(app_state, interaction),
+        "trade" => trade::trade_command(app_state, interaction),
+        "xp" => advancement::xp_command(app_state, interaction),
+        "levelup" => advancement::levelup_command(app_state, interaction),
+        "check" => checks::check_command(app_state, interaction),
+        "stats" => checks::stats_command(app_state, interaction),
+        "roll" => checks::roll_command(app_state, interaction),
+        "attack" => combat::attack_command(app_state, interaction),
+        "combat" => combat::combat_command(app_state, interaction),
+        "action" => action::action_command(app_state, interaction),
+        "facts" => context::facts_command(app_state, interaction),
+        "gm" => approval::gm_command(app_state, interaction),
+        "round" => round::round_command(app_state, interaction),
+        "session" => session::session_command(app_state, interaction),
+        "lore" => lore::lore_command(app_state, interaction),
+        "quest" => quest::quest_command(app_state, interaction),
+        "quests" => quest::quests_command(app_state, interaction),
+        "export" => journal::export_command(app_state, interaction),
+        "campaign" => campaign::campaign_command(app_state, interaction),
+        "config" => guild_config::config_command(app_state, interaction),
+        "sidequest" => thread::sidequest_command(app_state, interaction),
+        _ => {
+            log::warn!("unknown command {}", name);
+            ResponseOject::ephemeral(i18n::locale(app_state, interaction).format("error.unknown_command", &[("command", &name)]))
+        }
+    }
+}
+
+/// routes a button click to its handler. custom ids look like `<feature>:<action>:<id>`
+fn run_component(app_state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let custom_id = match interaction.custom_id() {
+        Some(c) => c,
+        None => return ResponseOject::ephemeral(i18n::locale(app_state, interaction).text("error.unable"))
+    };
+
+    log::info!("running component {}", custom_id);
+
+    let parts: Vec<&str> = custom_id.split(':').collect();
+    match parts.as_slice() {
+        ["trade", action, id] => match id.parse::<u32>() {
+            Ok(trade_id) => trade::trade_button(app_state, interaction, action, trade_id),
+            Err(_) => ResponseOject::ephemeral(i18n::locale(app_state, interaction).text("error.unable"))
+        },
+        ["levelup", player_id, option] => match (player_id.parse::<Snowflake>(), option.parse::<usize>()) {
+            (Ok(player_id), Ok(option)) => advancement::levelup_button(app_state, interaction, player_id, option),
+            _ => ResponseOject::ephemeral(i18n::locale(app_state, interaction).text("error.unable"))
+        },
+        ["approval", action, id] => match id.parse::<u32>() {
+            Ok(id) => approval::approval_button(app_state, interaction, action, id),
+            Err(_) => ResponseOject::ephemeral(i18n::locale(app_state, interaction).text("error.unable"))
+        },
+        ["session", answer, id] => match id.parse::<u32>() {
+            Ok(id) => session::rsvp_button(app_state, interaction, answer, id),
+            Err(_) => ResponseOject::ephemeral(i18n::locale(app_state, interaction).text("error.unable"))
+        },
+        _ => {
+            log::warn!("unknown component {}", custom_id);
+            ResponseOject::ephemeral(i18n::locale(app_state, interaction).text("error.unable"))
+        }
+    }
+}
+
+/// routes a submitted modal to its handler, using the same custom id format as components
+fn run_modal(app_state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let custom_id = match interaction.custom_id() {
+        Some(c) => c,
+        None => return ResponseOject::ephemeral(i18n::locale(app_state, interaction).text("error.unable"))
+    };
+
+    log::info!("running modal {}", custom_id);
+
+    let parts: Vec<&str> = custom_id.split(':').collect();
+    match parts.as_slice() {
+        ["approval", "edited", id] => match id.parse::<u32>() {
+            Ok(id) => approval::approval_modal(app_state, interaction, id),
+            Err(_) => ResponseOject::ephemeral(i18n::locale(app_state, interaction).text("error.unable"))
+        },
+        _ => {
+            log::warn!("unknown modal {}", custom_id);
+            ResponseOject::ephemeral(i18n::locale(app_state, interaction).text("error.unable"))
+        }
+    }
+}
+
+fn autocomplete(app_state: &AppState, interaction: &Interaction) -> AutocompleteResponse {
+    match interaction.command_name() {
+        Some("join") => advancement::class_autocomplete(app_state, interaction),
+        Some("lore") => lore::lore_autocomplete(app_state, interaction),
+        Some("quest") => quest::quest_autocomplete(app_state, interaction),
+        Some("campaign") => campaign::campaign_autocomplete(app_state, interaction),
+        Some("check") | Some("stats") => checks::check_autocomplete(app_state, interaction),
+        Some("give") | Some("use") | Some("equip") | Some("shop") | Some("buy") | Some("sell") | Some("trade") => {
+            inventory::item_autocomplete(app_state, interaction)
+        },
+        _ => AutocompleteResponse::new(Vec::new())
+    }
+}
+
 async fn init(
     app_state: State<Arc<AppState>>, 
-    header: HeaderMap, 
+    _header: HeaderMap, 
     body: Json<Interaction> ) -> impl IntoResponse {
 
-    let interaction_type = body.r#type;
-    let channel_id = match &body.channel_id{
-        Some(c) => c.clone(),
-        None => String::from("")
+    let res_object = create_campaign(&app_state, &body);
+    (StatusCode::OK, Json(res_object))
+}
+
+/// creates a campaign and makes it the one the channel plays, optionally in a thread of
+/// its own. a campaign the channel played before is kept and can be picked again with
+/// /campaign switch
+fn create_campaign(app_state: &Arc<AppState>, body: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(app_state, body);
+    let channel_id = match body.channel_id {
+        Some(c) => c,
+        None => return ResponseOject::ephemeral(locale.text("error.no_channel"))
     };
-    
 
-    {
-        log::debug!("not ping interation");
-        match app_state.campaigns.lock() {
-            Ok(lock) => {
-                for campaign in &*lock{
-                    log::debug!("{:?}", campaign);
-                    if campaign.channel_id == channel_id {
-                        if campaign.active == true { 
-                            let res_message = MessageObject {
-                                content: String::from("A campaign for this channel already exisits and is currently active")
-                            };
-
-                            let res_object = ResponseOject{
-                                r#type: 4,
-                                data: Some(res_message)
-                            };
-                            return (StatusCode::OK, Json(res_object))
- 
-                        }else {
-                            let res_message: MessageObject = MessageObject { 
-                                content: String::from("A campaign for this channel already exists, but is not active. use command /start to begin!")  
-                            };
-                            let res_object: ResponseOject = ResponseOject { 
-                                r#type: 4, 
-                                data: Some(res_message) 
-                            };
-
-                            return (StatusCode::OK, Json(res_object))
-                        }
-                    }
-                }
-                
-            },
-            Err(e) => {
-                log::error!("unable to obtain lock for app state");
-                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseOject{ r#type: 255, data: None}))
-            }
-        };
-         
+    let config = match app_state.guild_configs.lock() {
+        Ok(lock) => lock.get(body.guild_id),
+        Err(e) => {
+            log::error!("unable to obtain lock for app state\n{}", e);
+            return ResponseOject::ephemeral(locale.text("error.retry"))
+        }
+    };
+
+    // threads count as the channel they were started in
+    let parent = thread::thread_parent(body).unwrap_or(channel_id);
+    if !config.allows_channel(parent) {
+        let channels: Vec<String> = config.allowed_channels.iter().map(|c| format!("<#{}>", c)).collect();
+        return ResponseOject::ephemeral(locale.format("init.channel_not_allowed", &[("channels", &channels.join(", "))]))
     }
-    {
-        log::info!("Creating new campaign for channel {}", channel_id);
 
-        match app_state.campaigns.lock().as_mut() {
-            Ok(lock) => {
-                let campaign: Campaign = Campaign::new(&channel_id);
+    let ruleset = match body.option_str("ruleset") {
+        Some(key) => match RulesetId::from_key(key) {
+            Some(r) => r,
+            None => return ResponseOject::ephemeral(locale.format("init.unknown_ruleset", &[("ruleset", &key)]))
+        },
+        None => config.ruleset
+    };
 
-                lock.push(campaign);
+    let thread = body.option_bool("thread").unwrap_or(false);
+    if thread && body.channel.as_ref().is_some_and(|c| c.is_thread()) {
+        return ResponseOject::ephemeral(locale.text("init.thread_in_thread"))
+    }
 
-                let mes_obj: MessageObject = MessageObject { 
-                    content: String::from("new campaign created for channel! use command /join to joing the campaign. use command /start to beign campaign")
-                };
+    let missing = body.app_permissions().missing(Permissions::CREATE_PUBLIC_THREADS | Permissions::SEND_MESSAGES_IN_THREADS);
+    if thread && !missing.is_empty() {
+        return ResponseOject::ephemeral(locale.format("init.missing_permissions", &[("permissions", &missing)]))
+    }
 
-                let res_obj: ResponseOject = ResponseOject { 
-                    r#type: 4, 
-                    data: Some(mes_obj) 
-                };
+    let user_id = match body.user_id() {
+        Some(u) => u,
+        None => return ResponseOject::ephemeral(locale.text("error.no_user"))
+    };
 
-                return (StatusCode::OK, Json(res_obj))
-            },
-             Err(e) => {
-                log::error!("unable to obtain lock for app state");
-                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseOject{ r#type: 255, data: None}))
+    let mut campaign = Campaign::new(channel_id, ruleset);
+    campaign.name = body.option_str("name").map(|n| n.trim().to_string()).unwrap_or_default();
+    campaign.guild_id = body.guild_id;
+    campaign.gm_ids.push(user_id);
+    campaign.gm_role = body.option_snowflake("gm_role").or(config.gm_role);
+    campaign.narrator = config.narrator;
+    campaign.max_players = config.max_players;
+
+    let (id, name, previous) = match app_state.campaigns.lock() {
+        Ok(mut lock) => {
+            let current = lock.in_channel(channel_id);
+            if !campaign::can_take_channel(body, config.gm_role, current) {
+                return ResponseOject::ephemeral(locale.text("init.not_allowed"))
             }
+
+            let previous = current.map(|c| c.name.clone());
+            let id = lock.insert(campaign);
+            let name = lock.get(id).map(|c| c.name.clone()).unwrap_or_default();
+            (id, name, previous)
+        },
+        Err(e) => {
+            log::error!("unable to obtain lock for app state\n{}", e);
+            return ResponseOject::ephemeral(locale.text("error.retry"))
         }
+    };
+    log::info!("created campaign {} for channel {}", id, channel_id);
+
+    let mut message = locale.format("init.created", &[("ruleset", &ruleset.get().name()), ("name", &name)]);
+    if thread {
+        thread::spawn_campaign_thread(app_state, &locale, id, channel_id, &name);
+        message = locale.format("init.created_thread", &[("ruleset", &ruleset.get().name()), ("name", &name)]);
+    }
+    if let Some(previous) = previous {
+        message.push('\n');
+        message.push_str(&locale.format("init.previous_paused", &[("name", &previous)]));
     }
+    ResponseOject::new(message)
 }
 
-async fn start() {
+fn start(app_state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(app_state, interaction);
+    with_campaign(app_state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("start.not_gm"))
+        }
+        if campaign.active {
+            return ResponseOject::ephemeral(locale.text("start.already_active"))
+        }
 
+        campaign.active = true;
+        ResponseOject::new(locale.format("start.begun", &[("players", &campaign.players.len())]))
+    })
 }
 
-async fn join() {
-    
+/// puts the campaign on hold: actions and rounds wait until the GM starts it again
+fn end(app_state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(app_state, interaction);
+    with_campaign(app_state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("end.not_gm"))
+        }
+        if !campaign.active {
+            return ResponseOject::ephemeral(locale.text("end.not_started"))
+        }
+
+        campaign.active = false;
+        campaign.combat = None;
+        ResponseOject::new(locale.format("end.on_hold", &[("name", &campaign.name)]))
+    })
+}
+
+fn join(app_state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(app_state, interaction);
+    let user_id = match interaction.user_id() {
+        Some(u) => u,
+        None => return ResponseOject::ephemeral(locale.text("error.no_user"))
+    };
+    let user_name = interaction.user_name();
+
+    let class_option = interaction.option_str("class");
+
+    with_campaign(app_state, interaction, |campaign| {
+        if campaign.player(user_id).is_some() {
+            return ResponseOject::ephemeral(locale.text("join.already_joined"))
+        }
+        if campaign.max_players.is_some_and(|max| campaign.players.len() >= max as usize) {
+            return ResponseOject::ephemeral(locale.text("join.full"))
+        }
+
+        let classes = app_state.advancement.classes(campaign.ruleset);
+        let class = match (class_option, classes) {
+            (Some(name), Some(c)) => match c.class(name) {
+                Some(c) => c.name.clone(),
+                None => return ResponseOject::ephemeral(locale.format("join.unknown_class", &[("class", &name)]))
+            },
+            (Some(name), None) => name.to_string(),
+            (None, Some(c)) => c.classes.first().map(|c| c.name.clone()).unwrap_or_default(),
+            (None, None) => String::new()
+        };
+
+        campaign.players.push(Player::new(user_id, &user_name, &class, campaign.ruleset.get()));
+        ResponseOject::new(locale.format("join.joined", &[("name", &user_name), ("class", &class)]))
+    })
 }
 
 async fn action(
-    app_state: State<Arc<AppState>>,
-    headers: HeaderMap,
+    _app_state: State<Arc<AppState>>,
+    _headers: HeaderMap,
     Json(body): Json<Interaction>
     ) -> impl IntoResponse {
 
-    let mut file_name: String = String::from("application_test.json");
-    let interaction_type = body.r#type;
+    let file_name: String = String::from("application_test.json");
 
 
 
@@ -342,14 +658,14 @@ async fn action(
     };
     
     let buf = serde_json::to_string_pretty(&body).expect("couldn't convert struct to string");
-    match file.write_all(&buf.as_bytes()){
-        Ok(a) => {
+    match file.write_all(buf.as_bytes()){
+        Ok(_) => {
             log::info!("file written");
-            (StatusCode::OK, Json(discord_data_structs::ResponseOject { r#type: 1, data: None}))
+            (StatusCode::OK, Json(ResponseOject::Pong))
         },
         Err(e) => {
             log::error!("couldn't write to file {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(discord_data_structs::ResponseOject { r#type: 1, data: None}))
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(ResponseOject::Pong))
         }
     }
 
@@ -0,0 +1,591 @@
+use std::env;
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use reqwest::{
+    Client,
+    Url,
+    header
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
+use super::history::HistoryKind;
+use super::intent::{self, Intent};
+
+pub type NarratorError = Box<dyn Error + Send + Sync>;
+
+pub type NarratorFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, NarratorError>> + Send + 'a>>;
+
+const SUMMARY_PROMPT: &str = "You keep the campaign log for a tabletop roleplaying game. \
+    Rewrite the story so far together with the new events into one short summary of at most two paragraphs. \
+    Keep names, places, unresolved threads and anything the party promised or was promised.";
+
+const OUTCOME_PROMPT: &str = "(game) The rules resolved what you asked for:";
+
+const OUTCOME_INSTRUCTION: &str = "Now narrate what happens. Keep to these results exactly and don't invent other rolls, damage or items.";
+
+/// the narrator's answer to an action: the story, and the mechanics it wants the engine
+/// to resolve
+#[derive(Clone, Debug, Default)]
+pub struct Narration {
+    pub text: String,
+    pub intents: Vec<Intent>,
+}
+
+/// a backend that writes the GM's narrative response to a player's action
+pub trait Narrator: Send + Sync {
+    fn narrate<'a>(&'a self, request: &'a NarrationRequest) -> NarratorFuture<'a, Narration>;
+
+    /// like `narrate`, sending the text through `sender` as it is written, up to the first
+    /// intent, since what follows is told again once the intents are resolved. backends
+    /// that can't stream send it all at once, or nothing when there are intents
+    fn narrate_stream<'a>(&'a self, request: &'a NarrationRequest, sender: UnboundedSender<String>) -> NarratorFuture<'a, Narration> {
+        Box::pin(async move {
+            let narration = self.narrate(request).await?;
+            if narration.intents.is_empty() {
+                let _ = sender.send(narration.text.clone());
+            }
+            Ok(narration)
+        })
+    }
+
+    /// folds `turns` into the `previous` summary, which is empty for the first one
+    fn summarize<'a>(&'a self, previous: &'a str, turns: &'a [Turn]) -> NarratorFuture<'a, String>;
+}
+
+/// chat messages asking for a new rolling summary
+pub fn summary_messages(previous: &str, turns: &[Turn]) -> Vec<ChatMessage> {
+    let mut messages: Vec<ChatMessage> = vec![ChatMessage::new("system", SUMMARY_PROMPT)];
+    if !previous.is_empty() {
+        messages.push(ChatMessage::new("user", &format!("The story so far:\n{}", previous)));
+    }
+
+    let events: Vec<String> = turns.iter()
+        .map(|t| match &t.author {
+            Some(author) => format!("- {}: {}", author, t.text),
+            None => format!("- {}", t.text)
+        })
+        .collect();
+    messages.push(ChatMessage::new("user", &format!("New events:\n{}", events.join("\n"))));
+    messages
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PartyMember {
+    pub name: String,
+    pub class: String,
+    pub level: u8,
+    pub health: u8,
+    pub max_health: u8,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Turn {
+    pub kind: HistoryKind,
+    pub author: Option<String>,
+    pub text: String,
+}
+
+impl Turn {
+    /// narrations are the assistant's own words, resolutions, rolls and quest milestones
+    /// come from the game and anything else came from a player
+    pub fn message(&self) -> ChatMessage {
+        match self.kind {
+            HistoryKind::Narration => ChatMessage::new("assistant", &self.text),
+            HistoryKind::Resolution | HistoryKind::Roll | HistoryKind::Quest => ChatMessage::new("user", &format!("(game) {}", self.text)),
+            _ => {
+                let author = self.author.as_deref().unwrap_or("a player");
+                ChatMessage::new("user", &format!("{}: {}", author, self.text))
+            }
+        }
+    }
+}
+
+/// everything a narrator gets to see when answering an action, built by
+/// `context::build_request`
+#[derive(Clone, Debug, Serialize)]
+pub struct NarrationRequest {
+    /// instructions, world facts, the story so far and character sheets
+    pub system_prompt: String,
+    pub party: Vec<PartyMember>,
+
+    /// previous actions and narrations after the rolling summary, oldest first
+    pub recent_turns: Vec<Turn>,
+    pub player: String,
+    pub action: String,
+
+    /// what the engine made of the intents of a first narration. set when asking the
+    /// narrator to tell the outcome, which offers no tools
+    pub outcomes: Option<Vec<String>>,
+}
+
+impl NarrationRequest {
+    /// chat messages for the request: the system prompt, past turns, the new action and
+    /// any resolved outcomes
+    pub fn messages(&self) -> Vec<ChatMessage> {
+        let mut messages: Vec<ChatMessage> = vec![ChatMessage::new("system", &self.system_prompt)];
+
+        messages.extend(self.recent_turns.iter().map(Turn::message));
+        messages.push(ChatMessage::new("user", &format!("{}: {}", self.player, self.action)));
+        if let Some(outcomes) = &self.outcomes {
+            let results = match outcomes.is_empty() {
+                true => String::from("- none of it was allowed"),
+                false => outcomes.iter().map(|o| format!("- {}", o)).collect::<Vec<String>>().join("\n")
+            };
+            messages.push(ChatMessage::new("user", &format!("{}\n{}\n{}", OUTCOME_PROMPT, results, OUTCOME_INSTRUCTION)));
+        }
+        messages
+    }
+
+    /// tools are only offered until the outcome is known
+    fn tools(&self) -> Option<serde_json::Value> {
+        self.outcomes.is_none().then(intent::tool_definitions)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn new(role: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            role: role.to_string(),
+            content: content.to_string()
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct ChatCompletionRequest<'a> {
+    model: &'a str,
+    messages: Vec<ChatMessage>,
+    temperature: f32,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<serde_json::Value>,
+
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatCompletionResponse {
+    choices: Vec<ChatCompletionChoice>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatCompletionChoice {
+    message: ChatCompletionMessage,
+}
+
+/// an assistant reply, which has no content when it only calls tools
+#[derive(Deserialize, Debug)]
+struct ChatCompletionMessage {
+    content: Option<String>,
+
+    #[serde(default)]
+    tool_calls: Vec<ToolCall>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct ToolCall {
+    function: ToolCallFunction,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct ToolCallFunction {
+    name: String,
+
+    /// json encoded as a string
+    arguments: String,
+}
+
+/// one server sent event of a streamed completion
+#[derive(Deserialize, Debug)]
+struct ChatCompletionChunk {
+    choices: Vec<ChatCompletionChunkChoice>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatCompletionChunkChoice {
+    delta: ChatCompletionDelta,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatCompletionDelta {
+    content: Option<String>,
+
+    #[serde(default)]
+    tool_calls: Vec<ToolCallDelta>,
+}
+
+/// a piece of a tool call. the name arrives once, the arguments a fragment at a time
+#[derive(Deserialize, Debug)]
+struct ToolCallDelta {
+    #[serde(default)]
+    index: usize,
+    function: Option<ToolCallFunctionDelta>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ToolCallFunctionDelta {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+/// turns tool calls into intents. malformed calls are logged and dropped rather than
+/// failing the whole narration
+fn parse_intents(tool_calls: &[ToolCall]) -> Vec<Intent> {
+    tool_calls.iter()
+        .filter_map(|call| match Intent::from_tool_call(&call.function.name, &call.function.arguments) {
+            Ok(i) => Some(i),
+            Err(e) => {
+                log::warn!("ignoring narrator tool call\n{}", e);
+                None
+            }
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+pub struct NarratorConfig {
+    /// base url of the api, such as http://localhost:11434/v1 for ollama
+    pub base_url: String,
+    pub model: String,
+    pub api_key: Option<String>,
+    pub temperature: f32,
+}
+
+impl NarratorConfig {
+    /// reads NARRATOR_URL, NARRATOR_MODEL, NARRATOR_API_KEY and NARRATOR_TEMPERATURE.
+    /// returns `None` when NARRATOR_URL isn't set, which turns narration off
+    pub fn from_env() -> Option<NarratorConfig> {
+        let base_url = env::var("NARRATOR_URL").ok()?;
+
+        Some(NarratorConfig {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model: env::var("NARRATOR_MODEL").unwrap_or(String::from("llama3")),
+            api_key: env::var("NARRATOR_API_KEY").ok(),
+            temperature: env::var("NARRATOR_TEMPERATURE").ok()
+                .and_then(|t| t.parse().ok())
+                .unwrap_or(0.8)
+        })
+    }
+}
+
+/// narrator calling any OpenAI compatible /chat/completions endpoint, such as a local
+/// llama.cpp or ollama server
+pub struct OpenAiNarrator {
+    client: Client,
+    config: NarratorConfig,
+}
+
+impl OpenAiNarrator {
+    pub fn new(config: NarratorConfig) -> OpenAiNarrator {
+        OpenAiNarrator {
+            client: Client::new(),
+            config
+        }
+    }
+
+    async fn send(&self, body: &ChatCompletionRequest<'_>) -> Result<reqwest::Response, NarratorError> {
+        let endpoint = Url::from_str(&format!("{}/chat/completions", self.config.base_url))?;
+
+        let mut builder = self.client.post(endpoint).json(&body);
+        if let Some(key) = &self.config.api_key {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", key));
+        }
+
+        let response = builder.send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            return Err(format!("narrator returned {}\n{}", status, text).into())
+        }
+
+        Ok(response)
+    }
+
+    async fn complete(&self, messages: Vec<ChatMessage>, tools: Option<serde_json::Value>) -> Result<ChatCompletionMessage, NarratorError> {
+        let body = ChatCompletionRequest {
+            model: &self.config.model,
+            messages,
+            temperature: self.config.temperature,
+            tools,
+            stream: false
+        };
+
+        let response = self.send(&body).await?;
+        let completion: ChatCompletionResponse = response.json().await?;
+        match completion.choices.into_iter().next() {
+            Some(choice) => Ok(choice.message),
+            None => Err("narrator returned no choices".into())
+        }
+    }
+
+    /// narration with the mechanics offered as tools
+    async fn narrate_with_tools(&self, request: &NarrationRequest) -> Result<Narration, NarratorError> {
+        let message = self.complete(request.messages(), request.tools()).await?;
+
+        Ok(Narration {
+            text: message.content.unwrap_or_default().trim().to_string(),
+            intents: parse_intents(&message.tool_calls)
+        })
+    }
+
+    /// like `narrate_with_tools`, reading the reply as server sent events and passing the
+    /// text on as it arrives, until the first tool call
+    async fn stream_with_tools(&self, request: &NarrationRequest, sender: UnboundedSender<String>) -> Result<Narration, NarratorError> {
+        let body = ChatCompletionRequest {
+            model: &self.config.model,
+            messages: request.messages(),
+            temperature: self.config.temperature,
+            tools: request.tools(),
+            stream: true
+        };
+
+        let mut response = self.send(&body).await?;
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut text = String::new();
+        let mut tool_calls: Vec<ToolCall> = Vec::new();
+
+        'events: while let Some(chunk) = response.chunk().await? {
+            buffer.extend_from_slice(&chunk);
+
+            while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                let data = match line.trim().strip_prefix("data:") {
+                    Some(d) => d.trim(),
+                    None => continue
+                };
+
+                if data == "[DONE]" {
+                    break 'events
+                }
+
+                let event: ChatCompletionChunk = serde_json::from_str(data)?;
+                for choice in event.choices {
+                    if let Some(content) = choice.delta.content
+                        && !content.is_empty() {
+                        if tool_calls.is_empty() {
+                            // the writer going away only means nobody is watching anymore
+                            let _ = sender.send(content.clone());
+                        }
+                        text.push_str(&content);
+                    }
+
+                    for delta in choice.delta.tool_calls {
+                        if tool_calls.len() <= delta.index {
+                            tool_calls.resize_with(delta.index + 1, ToolCall::default);
+                        }
+                        if let Some(function) = delta.function {
+                            let call = &mut tool_calls[delta.index].function;
+                            call.name.push_str(&function.name.unwrap_or_default());
+                            call.arguments.push_str(&function.arguments.unwrap_or_default());
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(Narration {
+            text: text.trim().to_string(),
+            intents: parse_intents(&tool_calls)
+        })
+    }
+
+    async fn summarize_turns(&self, previous: &str, turns: &[Turn]) -> Result<String, NarratorError> {
+        let message = self.complete(summary_messages(previous, turns), None).await?;
+        Ok(message.content.unwrap_or_default().trim().to_string())
+    }
+}
+
+impl Narrator for OpenAiNarrator {
+    fn narrate<'a>(&'a self, request: &'a NarrationRequest) -> NarratorFuture<'a, Narration> {
+        Box::pin(self.narrate_with_tools(request))
+    }
+
+    fn narrate_stream<'a>(&'a self, request: &'a NarrationRequest, sender: UnboundedSender<String>) -> NarratorFuture<'a, Narration> {
+        Box::pin(self.stream_with_tools(request, sender))
+    }
+
+    fn summarize<'a>(&'a self, previous: &'a str, turns: &'a [Turn]) -> NarratorFuture<'a, String> {
+        Box::pin(self.summarize_turns(previous, turns))
+    }
+}
+
+/// deterministic narrator for tests, echoing the action back in a fixed template along
+/// with a fixed list of intents. told the outcome, it repeats it and asks for nothing more
+#[derive(Default)]
+pub struct MockNarrator {
+    pub intents: Vec<Intent>,
+}
+
+impl Narrator for MockNarrator {
+    fn narrate<'a>(&'a self, request: &'a NarrationRequest) -> NarratorFuture<'a, Narration> {
+        let mut text = format!("{} tries to {}. The party of {} watches as the world answers. ({} earlier turns)",
+            request.player,
+            request.action,
+            request.party.len(),
+            request.recent_turns.len());
+        if let Some(outcomes) = &request.outcomes {
+            text.push_str(&format!(" {}", outcomes.join(". ")));
+        }
+
+        let narration = Narration {
+            text,
+            intents: match request.outcomes {
+                Some(_) => Vec::new(),
+                None => self.intents.clone()
+            }
+        };
+
+        Box::pin(async move { Ok(narration) })
+    }
+
+    fn summarize<'a>(&'a self, previous: &'a str, turns: &'a [Turn]) -> NarratorFuture<'a, String> {
+        let summary = format!("{} {} more turns passed.", previous, turns.len()).trim().to_string();
+
+        Box::pin(async move { Ok(summary) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snowflake::Snowflake;
+    use axum::{Json, Router, handler::Handler, routing::post};
+    use crate::campaign::Campaign;
+    use crate::context::{self, ContextConfig};
+    use crate::item::ItemCatalog;
+    use crate::player::Player;
+    use crate::ruleset::RulesetId;
+
+    fn campaign() -> Campaign {
+        let mut campaign = Campaign::new(Snowflake::new(10), RulesetId::D20);
+        campaign.players.push(Player::new(Snowflake::new(1), "Ayla", "Fighter", RulesetId::D20.get()));
+        campaign.record(HistoryKind::Action, Some(Snowflake::new(1)), "open the door");
+        campaign.record(HistoryKind::Narration, None, "the door creaks open");
+        campaign
+    }
+
+    fn request(campaign: &Campaign) -> NarrationRequest {
+        let before = campaign.turns().len();
+        context::build_request(campaign, &ItemCatalog::default(), &ContextConfig::default(), before, "Ayla", "light a torch")
+    }
+
+    /// a narrator talking to a local server that answers chat completions with `handler`
+    async fn serve<H: Handler<T, ()>, T: 'static>(handler: H) -> OpenAiNarrator {
+        let app = Router::new().route("/v1/chat/completions", post(handler));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        OpenAiNarrator::new(NarratorConfig {
+            base_url: format!("http://{}/v1", address),
+            model: String::from("test"),
+            api_key: None,
+            temperature: 0.0
+        })
+    }
+
+    #[tokio::test]
+    async fn mock_narrator_is_deterministic() {
+        let request = request(&campaign());
+
+        let narrator = MockNarrator::default();
+        let first = narrator.narrate(&request).await.unwrap().text;
+        let second = narrator.narrate(&request).await.unwrap().text;
+
+        assert_eq!(first, second);
+        assert!(first.contains("light a torch"));
+        assert!(first.contains("2 earlier turns"));
+    }
+
+    #[test]
+    fn messages_replay_recent_turns() {
+        let request = request(&campaign());
+        let messages = request.messages();
+
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0].role, "system");
+        assert_eq!(messages[1], ChatMessage::new("user", "Ayla: open the door"));
+        assert_eq!(messages[2], ChatMessage::new("assistant", "the door creaks open"));
+        assert_eq!(messages[3], ChatMessage::new("user", "Ayla: light a torch"));
+    }
+
+    #[tokio::test]
+    async fn openai_narrator_reads_chat_completion() {
+        let narrator = serve(|Json(body): Json<serde_json::Value>| async move {
+            let last = body["messages"].as_array().and_then(|m| m.last()).cloned().unwrap_or_default();
+            Json(serde_json::json!({
+                "choices": [{ "message": { "role": "assistant", "content": format!(" you said {} ", last["content"].as_str().unwrap_or("")) } }]
+            }))
+        }).await;
+
+        let request = request(&campaign());
+        let narration = narrator.narrate(&request).await.unwrap();
+
+        assert_eq!(narration.text, "you said Ayla: light a torch");
+        assert!(narration.intents.is_empty());
+    }
+
+    #[tokio::test]
+    async fn openai_narrator_reads_tool_calls_as_intents() {
+        let narrator = serve(|Json(body): Json<serde_json::Value>| async move {
+            assert!(body["tools"].as_array().is_some_and(|t| !t.is_empty()));
+            Json(serde_json::json!({
+                "choices": [{ "message": { "role": "assistant", "content": null, "tool_calls": [
+                    { "id": "1", "type": "function", "function": { "name": "apply_damage", "arguments": "{\"player\":\"Ayla\",\"damage\":\"1d6\"}" } },
+                    { "id": "2", "type": "function", "function": { "name": "summon_dragon", "arguments": "{}" } }
+                ] } }]
+            }))
+        }).await;
+
+        let narration = narrator.narrate(&request(&campaign())).await.unwrap();
+
+        assert_eq!(narration.text, "");
+        assert_eq!(narration.intents, vec![Intent::ApplyDamage {
+            player: String::from("Ayla"),
+            damage: String::from("1d6"),
+            source: None
+        }]);
+    }
+
+    #[tokio::test]
+    async fn openai_narrator_streams_text_up_to_the_first_tool_call() {
+        let narrator = serve(|Json(body): Json<serde_json::Value>| async move {
+            assert_eq!(body["stream"], true);
+            let events = [
+                r#"{"choices":[{"delta":{"role":"assistant","content":"The door "}}]}"#,
+                r#"{"choices":[{"delta":{"content":"bursts open."}}]}"#,
+                r#"{"choices":[{"delta":{"tool_calls":[{"index":0,"function":{"name":"give_item","arguments":"{\"player\":"}}]}}]}"#,
+                r#"{"choices":[{"delta":{"tool_calls":[{"index":0,"function":{"arguments":"\"Ayla\",\"item\":\"torch\"}"}}]}}]}"#,
+                r#"{"choices":[{"delta":{"content":" A torch lies inside."}}]}"#,
+                "[DONE]"
+            ];
+            events.iter().map(|e| format!("data: {}\n\n", e)).collect::<String>()
+        }).await;
+
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let narration = narrator.narrate_stream(&request(&campaign()), sender).await.unwrap();
+
+        let mut streamed = String::new();
+        while let Some(text) = receiver.recv().await {
+            streamed.push_str(&text);
+        }
+
+        assert_eq!(streamed, "The door bursts open.");
+        assert_eq!(narration.text, "The door bursts open. A torch lies inside.");
+        assert_eq!(narration.intents, vec![Intent::GiveItem {
+            player: String::from("Ayla"),
+            item: String::from("torch"),
+            quantity: 1
+        }]);
+    }
+}
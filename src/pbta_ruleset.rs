@@ -0,0 +1,197 @@
+use super::dice::{self, DiceRoll};
+use super::item::ItemCatalog;
+use super::player::Player;
+use super::ruleset::{
+    AttackKind,
+    AttackOutcome,
+    CheckError,
+    CheckOutcome,
+    Outcome,
+    Ruleset,
+    RulesetId,
+    StatDefinition
+};
+
+const fn stat(key: &'static str, name: &'static str) -> StatDefinition {
+    StatDefinition { key, name, min: -3, max: 3, default: 0 }
+}
+
+pub const STATS: [StatDefinition; 5] = [
+    stat("cool", "cool"),
+    stat("hard", "hard"),
+    stat("hot", "hot"),
+    stat("sharp", "sharp"),
+    stat("weird", "weird"),
+];
+
+/// basic moves and the stat each one rolls
+pub const MOVES: [(&str, &str); 6] = [
+    ("act under fire", "cool"),
+    ("go aggro", "hard"),
+    ("seduce or manipulate", "hot"),
+    ("read a sitch", "sharp"),
+    ("read a person", "sharp"),
+    ("open your brain", "weird"),
+];
+
+pub const STRONG_HIT: i32 = 10;
+pub const WEAK_HIT: i32 = 7;
+
+/// harm dealt on a hit, plus the weapon's attack bonus
+pub const BASE_HARM: i32 = 2;
+
+/// an advance every 5 xp, up to ten of them
+pub const XP_THRESHOLDS: [u32; 10] = [5, 10, 15, 20, 25, 30, 35, 40, 45, 50];
+
+/// 2d6 + stat, 10+ is a strong hit, 7-9 a weak hit and 6- a miss
+fn outcome(roll: &DiceRoll) -> Outcome {
+    if roll.total >= STRONG_HIT {
+        Outcome::Success
+    } else if roll.total >= WEAK_HIT {
+        Outcome::Partial
+    } else {
+        Outcome::Failure
+    }
+}
+
+fn roll_move(modifier: i32) -> DiceRoll {
+    let first = dice::roll_die(6);
+    let second = dice::roll_die(6);
+    DiceRoll {
+        expression: format!("2d6{:+}", modifier),
+        rolls: vec![first, second],
+        total: (first + second) as i32 + modifier
+    }
+}
+
+/// Powered by the Apocalypse style rules: five stats from -3 to +3 rolled as moves
+pub struct PbtaRuleset;
+
+impl PbtaRuleset {
+    fn modifier(&self, player: &Player, name: &str) -> i32 {
+        match self.stat(name) {
+            Some(def) => player.stat(def),
+            None => 0
+        }
+    }
+}
+
+impl Ruleset for PbtaRuleset {
+    fn id(&self) -> RulesetId {
+        RulesetId::Pbta
+    }
+
+    fn name(&self) -> &'static str {
+        "Powered by the Apocalypse"
+    }
+
+    fn stats(&self) -> &'static [StatDefinition] {
+        &STATS
+    }
+
+    fn check_names(&self) -> Vec<&'static str> {
+        MOVES.iter()
+            .map(|(name, _)| *name)
+            .chain(STATS.iter().map(|s| s.name))
+            .collect()
+    }
+
+    /// boxes on the harm clock
+    fn starting_health(&self) -> u8 {
+        6
+    }
+
+    /// moves have no difficulty, so `difficulty` and `save` are ignored
+    fn resolve_check(&self, player: &Player, name: &str, _save: bool, _difficulty: Option<i64>) -> Result<CheckOutcome, CheckError> {
+        let lower = name.trim().to_lowercase();
+        let (label, stat) = match MOVES.iter().find(|(m, _)| *m == lower) {
+            Some((m, stat)) => (format!("{} (+{})", m, stat), *stat),
+            None => match self.stat(&lower) {
+                Some(def) => (format!("roll +{}", def.name), def.key),
+                None => return Err(CheckError::UnknownMove(name.to_string()))
+            }
+        };
+
+        let roll = roll_move(self.modifier(player, stat));
+        let outcome = outcome(&roll);
+
+        Ok(CheckOutcome {
+            label,
+            roll,
+            outcome
+        })
+    }
+
+    /// armor reduces harm rather than avoiding hits
+    fn defense(&self, player: &Player, catalog: &ItemCatalog) -> i32 {
+        player.inventory.ac_bonus(catalog)
+    }
+
+    /// go aggro: 2d6 + hard. a strong hit deals harm, a weak hit trades harm, a miss deals none
+    fn resolve_attack(&self, attacker: &Player, catalog: &ItemCatalog, defense: Option<i32>) -> AttackOutcome {
+        let roll = roll_move(self.modifier(attacker, "hard"));
+        let outcome = outcome(&roll);
+
+        let harm = (BASE_HARM + attacker.attack_bonus(catalog) - defense.unwrap_or(0)).max(0);
+        let damage = match outcome {
+            Outcome::Failure => None,
+            _ => Some(DiceRoll {
+                expression: format!("{} harm", harm),
+                rolls: Vec::new(),
+                total: harm
+            })
+        };
+
+        // a weak hit trades harm: the attacker takes the base harm, less their own armor
+        let recoil = match outcome {
+            Outcome::Partial => Some((BASE_HARM - self.defense(attacker, catalog)).max(0)),
+            _ => None
+        };
+
+        AttackOutcome {
+            kind: AttackKind::Aggro,
+            roll,
+            outcome,
+            damage,
+            recoil: recoil.map(|taken| DiceRoll {
+                expression: format!("{} harm", taken),
+                rolls: Vec::new(),
+                total: taken
+            })
+        }
+    }
+
+    /// the harm clock doesn't grow, advances come as class features and choices
+    fn xp_thresholds(&self) -> &'static [u32] {
+        &XP_THRESHOLDS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i18n::Locale;
+    use crate::snowflake::Snowflake;
+
+    #[test]
+    fn only_a_weak_hit_trades_harm() {
+        let catalog: ItemCatalog = serde_json::from_str(include_str!("../items.json")).unwrap();
+        let mut attacker = Player::new(Snowflake::new(1), "Ayla", "", &PbtaRuleset);
+        attacker.inventory.add("Leather Armor", 1, &Locale::new(None, None)).unwrap();
+        attacker.inventory.equip(catalog.find("leather armor").unwrap(), &Locale::new(None, None)).unwrap();
+
+        for _ in 0..200 {
+            let attack = PbtaRuleset.resolve_attack(&attacker, &catalog, Some(1));
+            match attack.outcome {
+                Outcome::Partial => {
+                    assert!(attack.describe("Ayla", &Locale::new(None, None)).ends_with("deals 1 harm and takes 1 harm in return"));
+                    assert!(attack.describe("Ayla", &Locale::new(Some("de"), None)).ends_with("erleidet 1 Schaden im Gegenzug"));
+                    assert_eq!(attack.recoil.unwrap().total, BASE_HARM - 1);
+                    assert_eq!(attack.damage.unwrap().total, BASE_HARM - 1);
+                },
+                Outcome::Success => assert!(attack.recoil.is_none() && attack.damage.is_some()),
+                _ => assert!(attack.recoil.is_none() && attack.damage.is_none())
+            }
+        }
+    }
+}
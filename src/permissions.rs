@@ -0,0 +1,131 @@
+use std::fmt;
+use std::ops::BitOr;
+
+/// discord permission flags. discord sends them as a decimal string because the bitset
+/// outgrew what javascript numbers hold
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Permissions(u64);
+
+impl Permissions {
+    pub const CREATE_INSTANT_INVITE: Permissions = Permissions(1 << 0);
+    pub const KICK_MEMBERS: Permissions = Permissions(1 << 1);
+    pub const BAN_MEMBERS: Permissions = Permissions(1 << 2);
+    pub const ADMINISTRATOR: Permissions = Permissions(1 << 3);
+    pub const MANAGE_CHANNELS: Permissions = Permissions(1 << 4);
+    pub const MANAGE_GUILD: Permissions = Permissions(1 << 5);
+    pub const ADD_REACTIONS: Permissions = Permissions(1 << 6);
+    pub const VIEW_CHANNEL: Permissions = Permissions(1 << 10);
+    pub const SEND_MESSAGES: Permissions = Permissions(1 << 11);
+    pub const MANAGE_MESSAGES: Permissions = Permissions(1 << 13);
+    pub const EMBED_LINKS: Permissions = Permissions(1 << 14);
+    pub const ATTACH_FILES: Permissions = Permissions(1 << 15);
+    pub const READ_MESSAGE_HISTORY: Permissions = Permissions(1 << 16);
+    pub const MENTION_EVERYONE: Permissions = Permissions(1 << 17);
+    pub const MANAGE_ROLES: Permissions = Permissions(1 << 28);
+    pub const USE_APPLICATION_COMMANDS: Permissions = Permissions(1 << 31);
+    pub const MANAGE_THREADS: Permissions = Permissions(1 << 34);
+    pub const CREATE_PUBLIC_THREADS: Permissions = Permissions(1 << 35);
+    pub const CREATE_PRIVATE_THREADS: Permissions = Permissions(1 << 36);
+    pub const SEND_MESSAGES_IN_THREADS: Permissions = Permissions(1 << 38);
+
+    /// flags with a name, used to describe what is missing
+    const NAMED: &'static [(Permissions, &'static str)] = &[
+        (Permissions::CREATE_INSTANT_INVITE, "Create Invite"),
+        (Permissions::KICK_MEMBERS, "Kick Members"),
+        (Permissions::BAN_MEMBERS, "Ban Members"),
+        (Permissions::ADMINISTRATOR, "Administrator"),
+        (Permissions::MANAGE_CHANNELS, "Manage Channels"),
+        (Permissions::MANAGE_GUILD, "Manage Server"),
+        (Permissions::ADD_REACTIONS, "Add Reactions"),
+        (Permissions::VIEW_CHANNEL, "View Channel"),
+        (Permissions::SEND_MESSAGES, "Send Messages"),
+        (Permissions::MANAGE_MESSAGES, "Manage Messages"),
+        (Permissions::EMBED_LINKS, "Embed Links"),
+        (Permissions::ATTACH_FILES, "Attach Files"),
+        (Permissions::READ_MESSAGE_HISTORY, "Read Message History"),
+        (Permissions::MENTION_EVERYONE, "Mention Everyone"),
+        (Permissions::MANAGE_ROLES, "Manage Roles"),
+        (Permissions::USE_APPLICATION_COMMANDS, "Use Application Commands"),
+        (Permissions::MANAGE_THREADS, "Manage Threads"),
+        (Permissions::CREATE_PUBLIC_THREADS, "Create Public Threads"),
+        (Permissions::CREATE_PRIVATE_THREADS, "Create Private Threads"),
+        (Permissions::SEND_MESSAGES_IN_THREADS, "Send Messages in Threads"),
+    ];
+
+    pub const fn empty() -> Permissions {
+        Permissions(0)
+    }
+
+    /// reads the decimal string discord sends. anything unreadable grants nothing
+    pub fn parse(value: &str) -> Permissions {
+        match value.trim().parse::<u64>() {
+            Ok(bits) => Permissions(bits),
+            Err(e) => {
+                log::warn!("could not parse permissions {:?}\n{}", value, e);
+                Permissions::empty()
+            }
+        }
+    }
+
+    /// whether every flag in `other` is set. administrators implicitly hold every permission
+    pub fn contains(self, other: Permissions) -> bool {
+        self.0 & Permissions::ADMINISTRATOR.0 != 0 || self.0 & other.0 == other.0
+    }
+
+    /// the flags of `required` this set lacks
+    pub fn missing(self, required: Permissions) -> Permissions {
+        if self.contains(required) {
+            return Permissions::empty()
+        }
+        Permissions(required.0 & !self.0)
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl BitOr for Permissions {
+    type Output = Permissions;
+
+    fn bitor(self, other: Permissions) -> Permissions {
+        Permissions(self.0 | other.0)
+    }
+}
+
+impl fmt::Display for Permissions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut names: Vec<String> = Permissions::NAMED.iter()
+            .filter(|(flag, _)| self.0 & flag.0 != 0)
+            .map(|(_, name)| name.to_string())
+            .collect();
+
+        let known = Permissions::NAMED.iter().fold(0, |bits, (flag, _)| bits | flag.0);
+        if self.0 & !known != 0 {
+            names.push(format!("0x{:x}", self.0 & !known));
+        }
+        write!(f, "{}", names.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_discord_strings_and_honours_administrator() {
+        let member = Permissions::parse("2048");
+        assert!(member.contains(Permissions::SEND_MESSAGES));
+        assert!(!member.contains(Permissions::SEND_MESSAGES | Permissions::MANAGE_GUILD));
+        assert_eq!(member.missing(Permissions::SEND_MESSAGES | Permissions::MANAGE_GUILD).to_string(), "Manage Server");
+
+        let threads = Permissions::parse(&(1u64 << 36).to_string());
+        assert!(threads.contains(Permissions::CREATE_PRIVATE_THREADS));
+
+        let admin = Permissions::parse("8");
+        assert!(admin.contains(Permissions::MANAGE_GUILD | Permissions::CREATE_PRIVATE_THREADS));
+        assert!(admin.missing(Permissions::MANAGE_THREADS).is_empty());
+
+        assert!(Permissions::parse("not a number").is_empty());
+    }
+}
@@ -1,4 +1,3 @@
-use hex;
 use std::env;
 use std::error::Error;
 use axum::http::{
@@ -6,18 +5,21 @@ use axum::http::{
 };
 use bytes::Bytes;
 use ed25519_dalek::{
-    Verifier, 
     VerifyingKey,
     Signature,
     SIGNATURE_LENGTH, 
     PUBLIC_KEY_LENGTH
 };
-use serde_json;
-use super::discord_data_structs;
 pub struct PingVerifier {
     pub verifying_key: VerifyingKey
 }
 
+impl Default for PingVerifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl PingVerifier{
     pub fn new() -> PingVerifier {
         let discord_key = env::var("DISCORD_API_KEY")
@@ -27,7 +29,7 @@ impl PingVerifier{
 
         let mut byte_array: [u8; PUBLIC_KEY_LENGTH] = [0; PUBLIC_KEY_LENGTH];
         
-        hex::decode_to_slice(&discord_key, &mut byte_array)
+        hex::decode_to_slice(discord_key, &mut byte_array)
             .expect("COULD NOT CONVERT API KEY TO BYTE ARRAY");
 
         let v_key: VerifyingKey = VerifyingKey::from_bytes(&byte_array)
@@ -38,10 +40,10 @@ impl PingVerifier{
         } 
     }
 
-    pub fn verify(&self, payload: &Vec<u8>, signature: &str) -> bool {
+    pub fn verify(&self, payload: &[u8], signature: &str) -> bool {
         let mut sig_byte_array: [u8; SIGNATURE_LENGTH] = [0; SIGNATURE_LENGTH];
 
-        match hex::decode_to_slice(&signature, &mut sig_byte_array) {
+        match hex::decode_to_slice(signature, &mut sig_byte_array) {
             Ok(()) => log::info!("successfully decoded provided X-Signature-Ed25519"),
             Err(e) => {
                 log::error!("could not decode provide X-Signature-Ed25519\n{}\n{}", signature, e);
@@ -53,7 +55,7 @@ impl PingVerifier{
 
         // log::debug!("signature\n{}\npayload\n{}\n\t", sig, payload);
 
-        match self.verifying_key.verify_strict(&payload, &sig) {
+        match self.verifying_key.verify_strict(payload, &sig) {
             Ok(_) => true,
             Err(e) => {
                 log::warn!("could not verify signature from ping request\n{:?}", e);
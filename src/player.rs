@@ -1,7 +1,79 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use super::inventory::Inventory;
+use super::currency::Purse;
+use super::advancement::LevelChoice;
+use super::ruleset::{Ruleset, StatDefinition};
+use super::item::ItemCatalog;
+use super::snowflake::Snowflake;
 
-
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Player {
-    id: String,
-    health: u8,
-}
\ No newline at end of file
+    pub id: Snowflake,
+    pub name: String,
+    pub health: u8,
+    pub max_health: u8,
+    pub inventory: Inventory,
+    pub purse: Purse,
+    pub class: String,
+    pub level: u8,
+    pub xp: u32,
+
+    /// class features gained and choices made while levelling up
+    pub features: Vec<String>,
+    pub pending_choices: Vec<LevelChoice>,
+
+    /// character sheet stats of the campaign's ruleset, keyed by short name such as "dex"
+    pub stats: HashMap<String, i32>,
+
+    /// skills and saves the player adds their proficiency bonus to, such as "stealth" or "dex save"
+    pub proficiencies: Vec<String>,
+}
+
+impl Player {
+    pub fn new(id: Snowflake, name: &str, class: &str, ruleset: &dyn Ruleset) -> Player {
+        let stats: HashMap<String, i32> = ruleset.stats().iter()
+            .map(|s| (s.key.to_string(), s.default))
+            .collect();
+
+        Player {
+            id,
+            name: name.to_string(),
+            health: ruleset.starting_health(),
+            max_health: ruleset.starting_health(),
+            inventory: Inventory::new(),
+            purse: Purse::new(),
+            class: class.to_string(),
+            level: 1,
+            xp: 0,
+            features: Vec::new(),
+            pending_choices: Vec::new(),
+            stats,
+            proficiencies: Vec::new()
+        }
+    }
+
+    pub fn attack_bonus(&self, catalog: &ItemCatalog) -> i32 {
+        self.inventory.attack_bonus(catalog)
+    }
+
+    pub fn stat(&self, stat: &StatDefinition) -> i32 {
+        self.stats.get(stat.key).copied().unwrap_or(stat.default)
+    }
+
+    pub fn is_proficient(&self, proficiency: &str) -> bool {
+        self.proficiencies.iter().any(|p| p.eq_ignore_ascii_case(proficiency))
+    }
+
+    /// +2 at level 1, going up by one every four levels
+    pub fn proficiency_bonus(&self) -> i32 {
+        2 + (self.level.max(1) as i32 - 1) / 4
+    }
+
+    /// heals the player, never going above max health. returns the amount healed
+    pub fn heal(&mut self, amount: u8) -> u8 {
+        let before = self.health;
+        self.health = self.health.saturating_add(amount).min(self.max_health);
+        self.health - before
+    }
+}
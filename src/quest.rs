@@ -0,0 +1,578 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use super::advancement::{award_xp, ClassCatalog};
+use super::campaign::{Campaign, with_campaign};
+use super::currency::Purse;
+use super::discord_data_structs::{
+    AutocompleteResponse,
+    CommandOptionChoice,
+    Embed,
+    EmbedField,
+    EmbedFooter,
+    Interaction,
+    ResponseOject,
+    MAX_AUTOCOMPLETE_CHOICES
+};
+use super::history::HistoryKind;
+use super::inventory::MAX_QUANTITY;
+use super::inventory::InventoryEntry;
+use super::lore::LoreKind;
+use super::snowflake::Snowflake;
+use super::i18n::{self, Locale};
+use super::AppState;
+
+/// discord shows at most ten embeds on a message
+pub const MAX_QUEST_EMBEDS: usize = 10;
+
+const ACTIVE_QUEST_COLOR: u32 = 0xE6A23C;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QuestState {
+    /// offered to the party but not taken on yet
+    Available,
+    Active,
+    Completed,
+    Failed,
+}
+
+impl fmt::Display for QuestState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            QuestState::Available => "available",
+            QuestState::Active => "active",
+            QuestState::Completed => "completed",
+            QuestState::Failed => "failed"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Objective {
+    pub description: String,
+    pub done: bool,
+}
+
+/// what every participant receives when the quest is completed
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+pub struct QuestReward {
+    pub xp: u32,
+    pub items: Vec<InventoryEntry>,
+    pub coins: Purse,
+}
+
+impl QuestReward {
+    fn describe(&self, locale: &Locale) -> String {
+        let mut parts: Vec<String> = Vec::new();
+        if self.xp > 0 {
+            parts.push(locale.format("quest.reward_xp", &[("xp", &self.xp)]));
+        }
+        if !self.coins.is_empty() {
+            parts.push(self.coins.to_string());
+        }
+        for entry in &self.items {
+            parts.push(format!("{}x {}", entry.quantity, locale.term(&entry.item)));
+        }
+
+        if parts.is_empty() {
+            locale.text("quest.reward_none")
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Quest {
+    pub id: u32,
+    pub title: String,
+    pub description: String,
+
+    /// name of the NPC who handed out the quest, usually one of the campaign's lore entries
+    pub giver: Option<String>,
+    pub state: QuestState,
+    pub objectives: Vec<Objective>,
+    pub reward: QuestReward,
+
+    /// ids of the players who took the quest on. when nobody joined, the whole party counts
+    pub participants: Vec<Snowflake>,
+}
+
+impl Quest {
+    fn summary(&self, locale: &Locale) -> String {
+        format!("#{} **{}** ({})", self.id, self.title, locale.term(&self.state.to_string()))
+    }
+
+    fn checklist(&self) -> String {
+        self.objectives.iter()
+            .enumerate()
+            .map(|(i, o)| format!("{} {}. {}", if o.done { "☑" } else { "☐" }, i + 1, o.description))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn embed(&self, campaign: &Campaign, locale: &Locale) -> Embed {
+        let mut description = self.description.clone();
+        if !self.objectives.is_empty() {
+            description.push_str(&format!("\n\n{}", self.checklist()));
+        }
+
+        let party = match self.participants.is_empty() {
+            true => locale.text("quest.whole_party"),
+            false => self.participants.iter()
+                .filter_map(|id| campaign.player(*id))
+                .map(|p| p.name.clone())
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+
+        let mut fields = vec![
+            EmbedField { name: locale.text("quest.field_reward"), value: self.reward.describe(locale), inline: Some(true) },
+            EmbedField { name: locale.text("quest.field_party"), value: party, inline: Some(true) }
+        ];
+        if let Some(giver) = &self.giver {
+            fields.insert(0, EmbedField { name: locale.text("quest.field_giver"), value: giver.clone(), inline: Some(true) });
+        }
+
+        Embed {
+            title: Some(format!("#{} {}", self.id, self.title)),
+            description: Some(description),
+            color: Some(ACTIVE_QUEST_COLOR),
+            fields: Some(fields),
+            ..Default::default()
+        }
+    }
+}
+
+/// marks a quest completed and hands its reward to every participant, or to every player if
+/// nobody joined. returns a line per change for the channel
+pub fn complete_quest(campaign: &mut Campaign, classes: Option<&ClassCatalog>, locale: &Locale, quest_id: u32) -> Result<Vec<String>, String> {
+    let quest = find_mut(campaign, quest_id, locale)?;
+    if quest.state != QuestState::Active {
+        return Err(locale.format("quest.not_active", &[("title", &quest.title), ("state", &locale.term(&quest.state.to_string()))]))
+    }
+
+    quest.state = QuestState::Completed;
+    for objective in quest.objectives.iter_mut() {
+        objective.done = true;
+    }
+    let quest = quest.clone();
+
+    let mut lines = vec![locale.format("quest.completed", &[("title", &quest.title)])];
+    campaign.record(HistoryKind::Quest, None, &locale.format("quest.completed_history", &[("title", &quest.title)]));
+
+    let participants: Vec<Snowflake> = match quest.participants.is_empty() {
+        true => campaign.players.iter().map(|p| p.id).collect(),
+        false => quest.participants.clone()
+    };
+
+    for player_id in participants {
+        let player = match campaign.player_mut(player_id) {
+            Some(p) => p,
+            None => continue
+        };
+
+        for entry in &quest.reward.items {
+            if let Err(e) = player.inventory.add(&entry.item, entry.quantity, locale) {
+                lines.push(locale.format("quest.no_items", &[("name", &player.name), ("error", &e)]));
+            }
+        }
+        if let Err(e) = player.purse.add(&quest.reward.coins) {
+            lines.push(locale.format("quest.no_coins", &[("name", &player.name), ("error", &e.describe(locale))]));
+        }
+        if !quest.reward.items.is_empty() || !quest.reward.coins.is_empty() {
+            let reward = QuestReward { xp: 0, ..quest.reward.clone() }.describe(locale);
+            lines.push(locale.format("quest.receives", &[("name", &player.name), ("reward", &reward)]));
+        }
+
+        if quest.reward.xp > 0 {
+            lines.extend(award_xp(campaign, classes, locale, player_id, quest.reward.xp));
+        }
+    }
+
+    Ok(lines)
+}
+
+fn find_mut<'a>(campaign: &'a mut Campaign, id: u32, locale: &Locale) -> Result<&'a mut Quest, String> {
+    campaign.quests.iter_mut()
+        .find(|q| q.id == id)
+        .ok_or_else(|| locale.format("quest.unknown", &[("id", &id)]))
+}
+
+fn quest_id(interaction: &Interaction, locale: &Locale) -> Result<u32, String> {
+    match interaction.option_i64("quest") {
+        Some(id) if id >= 0 => Ok(id as u32),
+        _ => Err(locale.text("quest.required"))
+    }
+}
+
+pub fn quest_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    match interaction.subcommand() {
+        Some("create") => create(state, interaction),
+        Some("objective") => add_objective(state, interaction),
+        Some("progress") => progress(state, interaction),
+        Some("start") => start(state, interaction),
+        Some("join") => join(state, interaction),
+        Some("complete") => complete(state, interaction),
+        Some("fail") => fail(state, interaction),
+        Some("show") => show(state, interaction),
+        _ => ResponseOject::ephemeral(i18n::locale(state, interaction).format("error.unknown_command", &[("command", &"quest")]))
+    }
+}
+
+fn create(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let (title, description) = match (interaction.option_str("title"), interaction.option_str("description")) {
+        (Some(t), Some(d)) => (t.trim().to_string(), d.trim().to_string()),
+        _ => return ResponseOject::ephemeral(locale.text("quest.create_required"))
+    };
+    let giver = interaction.option_str("giver")
+        .map(|g| g.trim().to_string())
+        .filter(|g| !g.is_empty());
+
+    let xp = match interaction.option_i64("xp") {
+        Some(x) if x < 0 => return ResponseOject::ephemeral(locale.text("quest.negative_xp")),
+        Some(x) => x as u32,
+        None => 0
+    };
+    let coins = match interaction.option_str("coins").map(Purse::parse) {
+        Some(Ok(p)) => p,
+        Some(Err(e)) => return ResponseOject::ephemeral(e.describe(&locale)),
+        None => Purse::new()
+    };
+    let items = match interaction.option_str("item") {
+        Some(name) => match state.catalog.find(name) {
+            Some(item) => vec![InventoryEntry {
+                item: item.name.clone(),
+                quantity: interaction.option_i64("quantity").unwrap_or(1).clamp(1, MAX_QUANTITY as i64) as u32
+            }],
+            None => return ResponseOject::ephemeral(locale.format("item.unknown", &[("item", &name)]))
+        },
+        None => Vec::new()
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("quest.not_gm_create"))
+        }
+
+        let id = campaign.next_quest_id;
+        campaign.next_quest_id += 1;
+
+        let quest = Quest {
+            id,
+            title: title.clone(),
+            description: description.clone(),
+            giver: giver.clone(),
+            state: QuestState::Available,
+            objectives: Vec::new(),
+            reward: QuestReward { xp, items: items.clone(), coins: coins.clone() },
+            participants: Vec::new()
+        };
+
+        let summary = quest.summary(&locale);
+        let reward = quest.reward.describe(&locale);
+        let message = match &quest.giver {
+            Some(giver) => locale.format("quest.offered", &[("giver", giver), ("summary", &summary), ("description", &quest.description), ("reward", &reward)]),
+            None => locale.format("quest.created", &[("summary", &summary), ("description", &quest.description), ("reward", &reward)])
+        };
+        campaign.quests.push(quest);
+        ResponseOject::new(message)
+    })
+}
+
+fn add_objective(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let id = match quest_id(interaction, &locale) {
+        Ok(i) => i,
+        Err(e) => return ResponseOject::ephemeral(e)
+    };
+    let description = match interaction.option_str("description") {
+        Some(d) if !d.trim().is_empty() => d.trim().to_string(),
+        _ => return ResponseOject::ephemeral(locale.text("quest.description_required"))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("quest.not_gm_change"))
+        }
+
+        let quest = match find_mut(campaign, id, &locale) {
+            Ok(q) => q,
+            Err(e) => return ResponseOject::ephemeral(e)
+        };
+        quest.objectives.push(Objective { description: description.clone(), done: false });
+        ResponseOject::ephemeral(format!("{}\n{}", quest.summary(&locale), quest.checklist()))
+    })
+}
+
+/// ticks off an objective, numbered from 1
+fn progress(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let id = match quest_id(interaction, &locale) {
+        Ok(i) => i,
+        Err(e) => return ResponseOject::ephemeral(e)
+    };
+    let number = interaction.option_i64("objective").unwrap_or(0);
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("quest.not_gm_change"))
+        }
+
+        let quest = match find_mut(campaign, id, &locale) {
+            Ok(q) => q,
+            Err(e) => return ResponseOject::ephemeral(e)
+        };
+        if quest.state != QuestState::Active {
+            return ResponseOject::ephemeral(locale.format("quest.state", &[("title", &quest.title), ("state", &locale.term(&quest.state.to_string()))]))
+        }
+
+        let objective = match usize::try_from(number).ok().and_then(|n| n.checked_sub(1)).and_then(|n| quest.objectives.get_mut(n)) {
+            Some(o) => o,
+            None => return ResponseOject::ephemeral(locale.format("quest.no_objective", &[("title", &quest.title), ("number", &number)]))
+        };
+        objective.done = true;
+
+        let mut message = format!("{}\n{}", quest.summary(&locale), quest.checklist());
+        if quest.objectives.iter().all(|o| o.done) {
+            message.push_str(&format!("\n{}", locale.text("quest.all_done")));
+        }
+        ResponseOject::new(message)
+    })
+}
+
+fn start(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let id = match quest_id(interaction, &locale) {
+        Ok(i) => i,
+        Err(e) => return ResponseOject::ephemeral(e)
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("quest.not_gm_start"))
+        }
+
+        let quest = match find_mut(campaign, id, &locale) {
+            Ok(q) => q,
+            Err(e) => return ResponseOject::ephemeral(e)
+        };
+        if quest.state != QuestState::Available {
+            return ResponseOject::ephemeral(locale.format("quest.already", &[("title", &quest.title), ("state", &locale.term(&quest.state.to_string()))]))
+        }
+
+        quest.state = QuestState::Active;
+        let message = locale.format("quest.taken", &[("summary", &quest.summary(&locale))]);
+        campaign.record(HistoryKind::Quest, None, &message);
+        ResponseOject::new(message)
+    })
+}
+
+fn join(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let id = match quest_id(interaction, &locale) {
+        Ok(i) => i,
+        Err(e) => return ResponseOject::ephemeral(e)
+    };
+    let user_id = match interaction.user_id() {
+        Some(u) => u,
+        None => return ResponseOject::ephemeral(locale.text("error.no_user"))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        let name = match campaign.player(user_id) {
+            Some(p) => p.name.clone(),
+            None => return ResponseOject::ephemeral(locale.text("player.not_joined"))
+        };
+
+        let quest = match find_mut(campaign, id, &locale) {
+            Ok(q) => q,
+            Err(e) => return ResponseOject::ephemeral(e)
+        };
+        if !matches!(quest.state, QuestState::Available | QuestState::Active) {
+            return ResponseOject::ephemeral(locale.format("quest.already", &[("title", &quest.title), ("state", &locale.term(&quest.state.to_string()))]))
+        }
+        if quest.participants.contains(&user_id) {
+            return ResponseOject::ephemeral(locale.format("quest.already_joined", &[("title", &quest.title)]))
+        }
+
+        quest.participants.push(user_id);
+        ResponseOject::new(locale.format("quest.joins", &[("name", &name), ("summary", &quest.summary(&locale))]))
+    })
+}
+
+fn complete(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let id = match quest_id(interaction, &locale) {
+        Ok(i) => i,
+        Err(e) => return ResponseOject::ephemeral(e)
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("quest.not_gm_complete"))
+        }
+
+        let classes = state.advancement.classes(campaign.ruleset);
+        match complete_quest(campaign, classes, &locale, id) {
+            Ok(lines) => ResponseOject::new(lines.join("\n")),
+            Err(e) => ResponseOject::ephemeral(e)
+        }
+    })
+}
+
+fn fail(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let id = match quest_id(interaction, &locale) {
+        Ok(i) => i,
+        Err(e) => return ResponseOject::ephemeral(e)
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("quest.not_gm_fail"))
+        }
+
+        let quest = match find_mut(campaign, id, &locale) {
+            Ok(q) => q,
+            Err(e) => return ResponseOject::ephemeral(e)
+        };
+        if !matches!(quest.state, QuestState::Available | QuestState::Active) {
+            return ResponseOject::ephemeral(locale.format("quest.already", &[("title", &quest.title), ("state", &locale.term(&quest.state.to_string()))]))
+        }
+
+        quest.state = QuestState::Failed;
+        let title = quest.title.clone();
+        campaign.record(HistoryKind::Quest, None, &locale.format("quest.failed_history", &[("title", &title)]));
+        ResponseOject::new(locale.format("quest.failed", &[("title", &title)]))
+    })
+}
+
+fn show(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let id = match quest_id(interaction, &locale) {
+        Ok(i) => i,
+        Err(e) => return ResponseOject::ephemeral(e)
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        match campaign.quests.iter().find(|q| q.id == id) {
+            Some(quest) => {
+                let mut embed = quest.embed(campaign, &locale);
+                embed.footer = Some(EmbedFooter { text: locale.term(&quest.state.to_string()) });
+                ResponseOject::with_embeds(String::new(), vec![embed])
+            },
+            None => ResponseOject::ephemeral(locale.format("quest.unknown", &[("id", &id)]))
+        }
+    })
+}
+
+/// the party's current goals, one embed per active quest
+pub fn quests_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    with_campaign(state, interaction, |campaign| {
+        let active: Vec<&Quest> = campaign.quests.iter()
+            .filter(|q| q.state == QuestState::Active)
+            .collect();
+        let available: Vec<String> = campaign.quests.iter()
+            .filter(|q| q.state == QuestState::Available)
+            .map(|q| q.summary(&locale))
+            .collect();
+
+        let mut content = match active.is_empty() {
+            true => locale.text("quest.none_active"),
+            false => String::new()
+        };
+        if !available.is_empty() {
+            content.push_str(&format!("\n{}", locale.format("quest.available", &[("quests", &available.join(", "))])));
+        }
+        if active.len() > MAX_QUEST_EMBEDS {
+            content.push_str(&format!("\n{}", locale.format("quest.showing", &[("shown", &MAX_QUEST_EMBEDS), ("total", &active.len())])));
+        }
+
+        let embeds: Vec<Embed> = active.iter()
+            .take(MAX_QUEST_EMBEDS)
+            .map(|q| q.embed(campaign, &locale))
+            .collect();
+        ResponseOject::with_embeds(content.trim().to_string(), embeds)
+    })
+}
+
+/// suggests quests for the `quest` option and NPCs from the lore for `giver`
+pub fn quest_autocomplete(state: &AppState, interaction: &Interaction) -> AutocompleteResponse {
+    let focused = match interaction.focused_option() {
+        Some(f) => f,
+        None => return AutocompleteResponse::new(Vec::new())
+    };
+    let partial = focused.value.as_ref()
+        .map(|v| v.as_str().map(|s| s.to_string()).unwrap_or(v.to_string()))
+        .unwrap_or_default()
+        .to_lowercase();
+
+    let choices: Vec<CommandOptionChoice> = match state.campaigns.lock() {
+        Ok(lock) => match interaction.channel_id.and_then(|c| lock.in_channel(c)) {
+            Some(campaign) if focused.name == "giver" => campaign.lore.iter()
+                .filter(|e| e.kind == LoreKind::Npc)
+                .filter(|e| e.name.to_lowercase().contains(&partial))
+                .take(MAX_AUTOCOMPLETE_CHOICES)
+                .map(|e| i18n::choice(serde_json::Value::String(e.name.clone()), |_| e.name.clone()))
+                .collect(),
+            Some(campaign) => campaign.quests.iter()
+                .filter(|q| q.title.to_lowercase().contains(&partial) || q.id.to_string() == partial)
+                .take(MAX_AUTOCOMPLETE_CHOICES)
+                .map(|q| i18n::choice(serde_json::Value::from(q.id), |locale| format!("#{} {} ({})", q.id, q.title, locale.term(&q.state.to_string()))))
+                .collect(),
+            None => Vec::new()
+        },
+        Err(e) => {
+            log::error!("unable to obtain lock for app state\n{}", e);
+            Vec::new()
+        }
+    };
+
+    AutocompleteResponse::new(choices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::Player;
+    use crate::ruleset::RulesetId;
+
+    #[test]
+    fn completing_rewards_only_participants() {
+        let mut campaign = Campaign::new(Snowflake::new(10), RulesetId::D20);
+        for (id, name) in [(1, "Ayla"), (2, "Bren")] {
+            let player = Player::new(Snowflake::new(id), name, "", campaign.ruleset.get());
+            campaign.players.push(player);
+        }
+        campaign.quests.push(Quest {
+            id: 0,
+            title: String::from("the lost lantern"),
+            description: String::from("find it"),
+            giver: None,
+            state: QuestState::Available,
+            objectives: vec![Objective { description: String::from("search the mill"), done: false }],
+            reward: QuestReward {
+                xp: 0,
+                items: vec![InventoryEntry { item: String::from("Lantern"), quantity: 1 }],
+                coins: Purse::parse("5gp").unwrap()
+            },
+            participants: vec![Snowflake::new(2)]
+        });
+
+        let locale = Locale::new(None, None);
+        assert!(complete_quest(&mut campaign, None, &locale, 0).is_err());
+        campaign.quests[0].state = QuestState::Active;
+        let lines = complete_quest(&mut campaign, None, &locale, 0).unwrap();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(campaign.quests[0].state, QuestState::Completed);
+        assert!(campaign.quests[0].objectives[0].done);
+        assert_eq!(campaign.player(Snowflake::new(2)).unwrap().inventory.quantity("Lantern"), 1);
+        assert_eq!(campaign.player(Snowflake::new(1)).unwrap().inventory.quantity("Lantern"), 0);
+        assert!(campaign.player(Snowflake::new(1)).unwrap().purse.total_copper() < campaign.player(Snowflake::new(2)).unwrap().purse.total_copper());
+    }
+}
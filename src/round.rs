@@ -0,0 +1,425 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use serde::{Deserialize, Serialize};
+use super::action;
+use super::approval::{self, CampaignMode};
+use super::campaign::{Campaign, Campaigns, with_campaign};
+use super::context;
+use super::discord_data_structs::{Interaction, MessageObject, ResponseOject};
+use super::history::{self, HistoryKind};
+use super::i18n::{self, Locale};
+use super::snowflake::Snowflake;
+use super::AppState;
+
+/// how often open rounds are checked against their deadline
+pub const DEADLINE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+pub const DEFAULT_ROUND_HOURS: i64 = 24;
+
+/// longest a round may run, a little over a month
+pub const MAX_ROUND_HOURS: i64 = 24 * 31;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RoundAction {
+    pub player_id: Snowflake,
+    pub player: String,
+    pub text: String,
+}
+
+/// a play-by-post round: every player submits one action privately, then they are all
+/// resolved together
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Round {
+    pub number: u32,
+
+    /// unix timestamp in seconds after which the round resolves without the stragglers
+    pub deadline: u64,
+    pub submissions: Vec<RoundAction>,
+}
+
+impl Round {
+    /// players who haven't submitted an action yet
+    pub fn stragglers(&self, campaign: &Campaign) -> Vec<Snowflake> {
+        campaign.players.iter()
+            .filter(|p| !self.submissions.iter().any(|s| s.player_id == p.id))
+            .map(|p| p.id)
+            .collect()
+    }
+}
+
+fn mentions(ids: &[Snowflake]) -> String {
+    ids.iter().map(|id| format!("<@{}>", id)).collect::<Vec<String>>().join(", ")
+}
+
+fn describe_deadline(deadline: u64, locale: &Locale) -> String {
+    let remaining = deadline.saturating_sub(history::now());
+    locale.format("round.deadline", &[("deadline", &deadline), ("hours", &(remaining / 3600)), ("minutes", &(remaining % 3600 / 60))])
+}
+
+pub fn round_command(state: &Arc<AppState>, interaction: &Interaction) -> ResponseOject {
+    match interaction.subcommand() {
+        Some("start") => start_round(state, interaction),
+        Some("status") => round_status(state, interaction),
+        Some("resolve") => force_resolve(state, interaction),
+        _ => ResponseOject::ephemeral(i18n::locale(state, interaction).format("error.unknown_command", &[("command", &"round")]))
+    }
+}
+
+fn start_round(state: &Arc<AppState>, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let hours = interaction.option_i64("hours").unwrap_or(DEFAULT_ROUND_HOURS);
+    if !(1..=MAX_ROUND_HOURS).contains(&hours) {
+        return ResponseOject::ephemeral(locale.format("round.hours", &[("max", &MAX_ROUND_HOURS)]))
+    }
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("round.not_gm_start"))
+        }
+        if !campaign.active {
+            return ResponseOject::ephemeral(locale.text("campaign.not_started"))
+        }
+        if campaign.round.is_some() {
+            return ResponseOject::ephemeral(locale.text("round.already_open"))
+        }
+        if campaign.players.is_empty() {
+            return ResponseOject::ephemeral(locale.text("round.nobody_joined"))
+        }
+
+        campaign.round_number += 1;
+        let round = Round {
+            number: campaign.round_number,
+            deadline: history::now() + hours as u64 * 3600,
+            submissions: Vec::new()
+        };
+
+        let message = locale.format("round.started", &[
+            ("round", &round.number),
+            ("deadline", &describe_deadline(round.deadline, &locale)),
+            ("waiting", &mentions(&round.stragglers(campaign)))
+        ]);
+        campaign.round = Some(round);
+        ResponseOject::new(message)
+    })
+}
+
+fn round_status(state: &Arc<AppState>, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    with_campaign(state, interaction, |campaign| {
+        match &campaign.round {
+            Some(round) => {
+                let waiting: Vec<String> = round.stragglers(campaign).iter()
+                    .filter_map(|id| campaign.player(*id))
+                    .map(|p| p.name.clone())
+                    .collect();
+                ResponseOject::ephemeral(locale.format("round.status", &[
+                    ("round", &round.number),
+                    ("submitted", &round.submissions.len()),
+                    ("players", &campaign.players.len()),
+                    ("deadline", &describe_deadline(round.deadline, &locale)),
+                    ("waiting", &if waiting.is_empty() { locale.text("round.nobody") } else { waiting.join(", ") })
+                ]))
+            },
+            None => ResponseOject::ephemeral(locale.text("round.none_start"))
+        }
+    })
+}
+
+fn force_resolve(state: &Arc<AppState>, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let mut open: Option<u32> = None;
+    let response = with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("round.not_gm_resolve"))
+        }
+        if campaign.round.is_none() {
+            return ResponseOject::ephemeral(locale.text("round.none"))
+        }
+
+        open = Some(campaign.id);
+        ResponseOject::ephemeral(locale.text("round.resolving"))
+    });
+
+    if let Some(campaign_id) = open {
+        resolve_round(state, campaign_id);
+    }
+    response
+}
+
+/// stores a player's action for the open round and tells the channel who is still missing.
+/// returns the reply to the player and whether the round is now complete
+pub fn submit_action(state: &AppState, locale: &Locale, campaign: &mut Campaign, player_id: Snowflake, player: &str, text: &str) -> (ResponseOject, bool) {
+    let round = match add_submission(campaign, locale, player_id, player, text) {
+        Ok(r) => r,
+        Err(e) => return (ResponseOject::ephemeral(e), false)
+    };
+
+    let stragglers = round.stragglers(campaign);
+    if !stragglers.is_empty() {
+        // names rather than mentions, stragglers only get pinged once the deadline passes
+        let waiting: Vec<String> = stragglers.iter()
+            .filter_map(|id| campaign.player(*id))
+            .map(|p| p.name.clone())
+            .collect();
+        state.discord.spawn_message(campaign.announce_channel(), MessageObject {
+            content: locale.format("round.acted", &[("player", &player), ("round", &round.number), ("waiting", &waiting.join(", "))]),
+            ..Default::default()
+        });
+    }
+
+    (ResponseOject::ephemeral(locale.format("round.submitted", &[("round", &round.number), ("submitted", &round.submissions.len()), ("players", &campaign.players.len())])), stragglers.is_empty())
+}
+
+/// adds the player's action to the open round, one per player. returns the round with it
+fn add_submission(campaign: &mut Campaign, locale: &Locale, player_id: Snowflake, player: &str, text: &str) -> Result<Round, String> {
+    let round = campaign.round.as_mut().ok_or(locale.text("round.none"))?;
+
+    if round.submissions.iter().any(|s| s.player_id == player_id) {
+        return Err(locale.format("round.already_acted", &[("round", &round.number)]))
+    }
+
+    round.submissions.push(RoundAction {
+        player_id,
+        player: player.to_string(),
+        text: text.to_string()
+    });
+    Ok(round.clone())
+}
+
+/// takes the open round off the campaign and hands its actions on: to the GM's approval
+/// queue in human GM mode, into the history otherwise. returns the round and its
+/// announcement, which pings the players who didn't act
+fn close_round(campaign: &mut Campaign, locale: &Locale) -> Option<(Round, String)> {
+    let round = campaign.round.take()?;
+
+    let stragglers = round.stragglers(campaign);
+    let mut content = locale.format("round.title", &[("round", &round.number)]);
+    for submission in &round.submissions {
+        content.push_str(&format!("\n**{}**: {}", submission.player, submission.text));
+    }
+    if !stragglers.is_empty() {
+        content.push_str(&locale.format("round.no_action", &[("players", &mentions(&stragglers))]));
+    }
+
+    if round.submissions.is_empty() {
+        return Some((round, content))
+    }
+
+    if campaign.mode == CampaignMode::HumanGm {
+        for submission in &round.submissions {
+            approval::queue_action(campaign, submission.player_id, &submission.player, &submission.text);
+        }
+        content.push_str(&locale.text("round.gm_resolving"));
+    } else {
+        for submission in &round.submissions {
+            campaign.record(HistoryKind::Action, Some(submission.player_id), &submission.text);
+        }
+    }
+
+    Some((round, content))
+}
+
+/// running campaigns whose open round ran past its deadline at `now`. a round of a
+/// campaign on hold waits until it starts again
+fn expired_rounds(campaigns: &Campaigns, now: u64) -> Vec<u32> {
+    campaigns.iter()
+        .filter(|c| c.active && c.round.as_ref().is_some_and(|r| r.deadline <= now))
+        .map(|c| c.id)
+        .collect()
+}
+
+/// closes the open round of a campaign and resolves every submitted action together: with
+/// the GM's approval queue in human GM mode, otherwise with the narrator if there is one.
+/// players who didn't act are pinged
+pub fn resolve_round(state: &Arc<AppState>, campaign_id: u32) {
+    let mut lock = match state.campaigns.lock() {
+        Ok(l) => l,
+        Err(e) => {
+            log::error!("unable to obtain lock for app state\n{}", e);
+            return
+        }
+    };
+
+    let campaign = match lock.get_mut(campaign_id) {
+        Some(c) => c,
+        None => return
+    };
+    let channel_id = campaign.announce_channel();
+    let locale = i18n::guild_locale(state, campaign.guild_id);
+
+    let before = campaign.turns().len();
+    let (round, mut content) = match close_round(campaign, &locale) {
+        Some(closed) => closed,
+        None => return
+    };
+
+    if round.submissions.is_empty() || campaign.mode == CampaignMode::HumanGm {
+        state.discord.spawn_long_message(channel_id, &content);
+        return
+    }
+
+    let narrator = match state.narrator_for(campaign) {
+        Some(n) => n,
+        None => {
+            state.discord.spawn_long_message(channel_id, &content);
+            return
+        }
+    };
+
+    let actions: Vec<String> = round.submissions.iter()
+        .map(|s| format!("{}: {}", s.player, s.text))
+        .collect();
+    let state = state.clone();
+    drop(lock);
+
+    tokio::spawn(async move {
+        context::fold_history(&state, &narrator, campaign_id, before).await;
+
+        let request = match state.campaigns.lock() {
+            Ok(lock) => lock.get(campaign_id)
+                .map(|c| context::build_request(c, &state.catalog, &state.context, before, "the party", &actions.join("\n"))),
+            Err(e) => {
+                log::error!("unable to obtain lock for app state\n{}", e);
+                None
+            }
+        };
+
+        if let Some(request) = request {
+            let (sender, mut receiver) = mpsc::unbounded_channel();
+            match action::narrate_resolved(&state.campaigns, &state.catalog, &locale, narrator.as_ref(), campaign_id, &request, sender).await {
+                Ok(outcomes) => {
+                    let mut text = String::new();
+                    while let Ok(part) = receiver.try_recv() {
+                        text.push_str(&part);
+                    }
+                    content.push_str(&format!("\n\n{}", text.trim()));
+                    for outcome in outcomes {
+                        content.push_str(&format!("\n> {}", outcome));
+                    }
+                },
+                Err(e) => {
+                    log::error!("narrator failed for campaign {}\n{}", campaign_id, e);
+                    content.push_str(&format!("\n\n{}", locale.text("narrator.unavailable")));
+                }
+            }
+        }
+
+        state.discord.spawn_long_message(channel_id, &content);
+    });
+}
+
+/// resolves rounds whose deadline has passed, checking every `DEADLINE_CHECK_INTERVAL`
+pub fn spawn_deadline_task(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(DEADLINE_CHECK_INTERVAL);
+
+        loop {
+            ticker.tick().await;
+
+            let expired: Vec<u32> = match state.campaigns.lock() {
+                Ok(lock) => expired_rounds(&lock, history::now()),
+                Err(e) => {
+                    log::error!("unable to obtain lock for app state\n{}", e);
+                    Vec::new()
+                }
+            };
+
+            for campaign_id in expired {
+                log::info!("round deadline passed in campaign {}", campaign_id);
+                resolve_round(&state, campaign_id);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::Player;
+    use crate::ruleset::RulesetId;
+
+    const AYLA: Snowflake = Snowflake::new(1);
+    const BRAM: Snowflake = Snowflake::new(2);
+
+    fn campaign(deadline: u64) -> Campaign {
+        let mut campaign = Campaign::new(Snowflake::new(10), RulesetId::D20);
+        for (id, name) in [(AYLA, "Ayla"), (BRAM, "Bram")] {
+            campaign.players.push(Player::new(id, name, "", campaign.ruleset.get()));
+        }
+        campaign.round = Some(Round { number: 3, deadline, submissions: Vec::new() });
+        campaign.active = true;
+        campaign
+    }
+
+    #[test]
+    fn the_round_is_complete_once_everyone_has_acted() {
+        let locale = Locale::new(None, None);
+        let mut campaign = campaign(u64::MAX);
+
+        let round = add_submission(&mut campaign, &locale, AYLA, "Ayla", "scout ahead").unwrap();
+        assert_eq!(round.stragglers(&campaign), vec![BRAM]);
+        assert_eq!(add_submission(&mut campaign, &locale, AYLA, "Ayla", "scout again").unwrap_err(), locale.format("round.already_acted", &[("round", &3)]));
+
+        let round = add_submission(&mut campaign, &locale, BRAM, "Bram", "guard the camp").unwrap();
+        assert!(round.stragglers(&campaign).is_empty());
+
+        let (round, content) = close_round(&mut campaign, &locale).unwrap();
+        assert_eq!(round.submissions.len(), 2);
+        assert!(campaign.round.is_none());
+        assert_eq!(content, "**round 3**\n**Ayla**: scout ahead\n**Bram**: guard the camp");
+        assert_eq!(campaign.history.len(), 2);
+        assert!(campaign.history.iter().all(|h| h.kind == HistoryKind::Action));
+
+        assert_eq!(add_submission(&mut campaign, &locale, AYLA, "Ayla", "rest").unwrap_err(), locale.text("round.none"));
+        assert!(close_round(&mut campaign, &locale).is_none());
+    }
+
+    #[test]
+    fn a_passed_deadline_resolves_without_the_stragglers() {
+        let locale = Locale::new(None, None);
+        let mut campaigns = Campaigns::new();
+        let late = campaigns.insert(campaign(100));
+        campaigns.insert(campaign(200));
+        let mut idle = campaign(100);
+        idle.round = None;
+        campaigns.insert(idle);
+        let mut paused = campaign(100);
+        paused.active = false;
+        campaigns.insert(paused);
+
+        assert_eq!(expired_rounds(&campaigns, 99), Vec::<u32>::new());
+        assert_eq!(expired_rounds(&campaigns, 150), vec![late]);
+
+        let campaign = campaigns.get_mut(late).unwrap();
+        add_submission(campaign, &locale, AYLA, "Ayla", "scout ahead").unwrap();
+        let (_, content) = close_round(campaign, &locale).unwrap();
+        assert!(content.ends_with(&locale.format("round.no_action", &[("players", &"<@2>")])));
+        assert_eq!(campaign.history.len(), 1);
+        assert_eq!(campaign.history[0].player_id, Some(AYLA));
+    }
+
+    #[test]
+    fn a_human_gm_gets_the_actions_to_approve() {
+        let locale = Locale::new(None, None);
+        let mut campaign = campaign(u64::MAX);
+        campaign.mode = CampaignMode::HumanGm;
+        add_submission(&mut campaign, &locale, BRAM, "Bram", "guard the camp").unwrap();
+
+        let (_, content) = close_round(&mut campaign, &locale).unwrap();
+        assert!(content.ends_with(&locale.text("round.gm_resolving")));
+        assert!(campaign.history.is_empty());
+        assert_eq!(campaign.pending_actions.len(), 1);
+        assert_eq!(campaign.pending_actions[0].text, "guard the camp");
+    }
+
+    #[test]
+    fn a_round_nobody_acted_in_only_pings_everyone() {
+        let locale = Locale::new(None, None);
+        let mut campaign = campaign(0);
+
+        let (_, content) = close_round(&mut campaign, &locale).unwrap();
+        assert!(content.ends_with(&locale.format("round.no_action", &[("players", &"<@1>, <@2>")])));
+        assert!(campaign.history.is_empty() && campaign.pending_actions.is_empty());
+    }
+}
@@ -0,0 +1,254 @@
+use serde::{Deserialize, Serialize};
+use super::advancement::ClassDefinition;
+use super::dice::{self, DiceRoll};
+use super::i18n::Locale;
+use super::item::ItemCatalog;
+use super::player::Player;
+use super::d20_ruleset::D20Ruleset;
+use super::pbta_ruleset::PbtaRuleset;
+
+/// one stat on the character sheet of a ruleset
+#[derive(Clone, Copy, Debug)]
+pub struct StatDefinition {
+    /// key the value is stored under on the player, such as "dex"
+    pub key: &'static str,
+    pub name: &'static str,
+    pub min: i32,
+    pub max: i32,
+    pub default: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Success,
+    /// success at a cost, such as a 7-9 in PbtA
+    Partial,
+    Failure,
+    /// no difficulty was given, so the roll only has a total
+    Unresolved,
+}
+
+impl Outcome {
+    pub fn describe(&self, locale: &Locale) -> String {
+        match self {
+            Outcome::Success => locale.text("outcome.success"),
+            Outcome::Partial => locale.text("outcome.partial"),
+            Outcome::Failure => locale.text("outcome.failure"),
+            Outcome::Unresolved => locale.text("outcome.unresolved")
+        }
+    }
+}
+
+/// why a check can't be rolled, or can't be made a proficiency
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CheckError {
+    /// a skill was named for a saving throw, which uses an ability
+    SaveWithSkill(String),
+    UnknownCheck(String),
+    UnknownMove(String),
+    /// the ruleset, named here, doesn't track proficiencies
+    NoProficiencies(&'static str),
+    /// a plain ability check, which has no proficiency of its own
+    NoProficiency,
+}
+
+impl CheckError {
+    pub fn describe(&self, locale: &Locale) -> String {
+        match self {
+            CheckError::SaveWithSkill(name) => locale.format("check.save_with_skill", &[("check", &locale.term(name))]),
+            CheckError::UnknownCheck(name) => locale.format("check.unknown", &[("check", name)]),
+            CheckError::UnknownMove(name) => locale.format("check.unknown_move", &[("check", name)]),
+            CheckError::NoProficiencies(ruleset) => locale.format("check.no_proficiencies", &[("ruleset", ruleset)]),
+            CheckError::NoProficiency => locale.text("check.no_proficiency")
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CheckOutcome {
+    /// what was rolled, such as "stealth (DEX)"
+    pub label: String,
+    pub roll: DiceRoll,
+    pub outcome: Outcome,
+}
+
+/// how an attack was made, which decides how it is told
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AttackKind {
+    /// an attack roll against armor class with a weapon, `None` for bare hands. a natural
+    /// 20 is critical
+    Strike { weapon: Option<String>, critical: bool, armor_class: Option<i32> },
+    /// the PbtA go aggro move
+    Aggro,
+}
+
+#[derive(Clone, Debug)]
+pub struct AttackOutcome {
+    pub kind: AttackKind,
+    pub roll: DiceRoll,
+    pub outcome: Outcome,
+    pub damage: Option<DiceRoll>,
+
+    /// harm the attacker takes in return, such as on a PbtA weak hit
+    pub recoil: Option<DiceRoll>,
+}
+
+impl AttackOutcome {
+    /// the roll and what it did, `attacker` being the name of who attacked
+    pub fn describe(&self, attacker: &str, locale: &Locale) -> String {
+        let harm = |roll: &Option<DiceRoll>| roll.as_ref().map(|r| r.total).unwrap_or(0);
+
+        match &self.kind {
+            AttackKind::Strike { weapon, critical, armor_class } => {
+                let weapon = match weapon {
+                    Some(w) => locale.term(w),
+                    None => locale.text("attack.bare_hands")
+                };
+
+                let mut summary = locale.format("attack.strike", &[("player", &attacker), ("weapon", &weapon), ("roll", &self.roll)]);
+                if *critical {
+                    summary.push_str(&locale.text("attack.critical"));
+                }
+                match (self.outcome, armor_class) {
+                    (Outcome::Success, Some(ac)) => summary.push_str(&locale.format("attack.hits", &[("ac", ac)])),
+                    (Outcome::Failure, Some(ac)) => summary.push_str(&locale.format("attack.misses_ac", &[("ac", ac)])),
+                    (Outcome::Failure, None) => summary.push_str(&locale.text("attack.misses")),
+                    _ => {}
+                }
+                if let Some(d) = &self.damage {
+                    summary.push_str(&locale.format("attack.damage", &[("damage", d)]));
+                }
+                summary
+            },
+            AttackKind::Aggro => match (self.outcome, &self.recoil) {
+                (Outcome::Success, _) => locale.format("attack.aggro_strong", &[("player", &attacker), ("roll", &self.roll), ("harm", &harm(&self.damage))]),
+                (Outcome::Partial, Some(taken)) => locale.format("attack.aggro_weak", &[("player", &attacker), ("roll", &self.roll), ("harm", &harm(&self.damage)), ("taken", &taken.total)]),
+                _ => locale.format("attack.aggro_miss", &[("player", &attacker), ("roll", &self.roll)])
+            }
+        }
+    }
+}
+
+/// what a player gained by reaching a new level
+#[derive(Clone, Debug)]
+pub struct LevelUp {
+    pub level: u8,
+    pub health: u8,
+    pub features: Vec<String>,
+}
+
+/// the rules of a game system: what a character sheet holds and how checks, attacks
+/// and advancement are resolved
+pub trait Ruleset: Send + Sync {
+    fn id(&self) -> RulesetId;
+
+    fn name(&self) -> &'static str;
+
+    fn stats(&self) -> &'static [StatDefinition];
+
+    /// names that can be passed to `resolve_check`, used for autocomplete
+    fn check_names(&self) -> Vec<&'static str>;
+
+    fn starting_health(&self) -> u8;
+
+    /// rolls a check for the player. `difficulty` is the DC where the ruleset uses one
+    fn resolve_check(&self, player: &Player, name: &str, save: bool, difficulty: Option<i64>) -> Result<CheckOutcome, CheckError>;
+
+    /// the defense an attack against the player has to beat
+    fn defense(&self, player: &Player, catalog: &ItemCatalog) -> i32;
+
+    fn resolve_attack(&self, attacker: &Player, catalog: &ItemCatalog, defense: Option<i32>) -> AttackOutcome;
+
+    /// total xp needed to reach level 2, 3, ... in order
+    fn xp_thresholds(&self) -> &'static [u32];
+
+    /// max health gained with every level
+    fn health_per_level(&self, class: Option<&ClassDefinition>) -> u8 {
+        let _ = class;
+        0
+    }
+
+    /// level reached with `xp` experience points
+    fn level_for(&self, xp: u32) -> u8 {
+        let reached = self.xp_thresholds().iter().take_while(|t| xp >= **t).count();
+        (reached + 1).min(u8::MAX as usize) as u8
+    }
+
+    /// xp needed for the level after `level`, `None` at the level cap
+    fn next_threshold(&self, level: u8) -> Option<u32> {
+        self.xp_thresholds().get((level as usize).saturating_sub(1)).copied()
+    }
+
+    /// raises the player one level: more max health, the features the class gains at the
+    /// new level and the choices it has to make there
+    fn level_up(&self, player: &mut Player, class: Option<&ClassDefinition>) -> LevelUp {
+        player.level = player.level.saturating_add(1);
+
+        let health = self.health_per_level(class);
+        player.max_health = player.max_health.saturating_add(health);
+        player.health = player.health.saturating_add(health).min(player.max_health);
+
+        let mut features: Vec<String> = Vec::new();
+        if let Some(class) = class {
+            for feature in class.features.iter().filter(|f| f.level == player.level) {
+                player.features.push(feature.name.clone());
+                features.push(feature.name.clone());
+            }
+
+            for choice in class.choices.iter().filter(|c| c.level == player.level) {
+                player.pending_choices.push(choice.clone());
+            }
+        }
+
+        LevelUp {
+            level: player.level,
+            health,
+            features
+        }
+    }
+
+    /// initiative roll when combat starts, higher goes first
+    fn initiative(&self, player: &Player) -> DiceRoll {
+        let _ = player;
+        dice::roll_d20(0)
+    }
+
+    /// name under which a proficiency in the check is stored on the player
+    fn proficiency(&self, name: &str, save: bool) -> Result<String, CheckError> {
+        let _ = (name, save);
+        Err(CheckError::NoProficiencies(self.name()))
+    }
+
+    fn stat(&self, name: &str) -> Option<&'static StatDefinition> {
+        let name = name.trim().to_lowercase();
+        self.stats().iter().find(|s| s.key == name || s.name.to_lowercase() == name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RulesetId {
+    #[default]
+    D20,
+    Pbta,
+}
+
+static D20: D20Ruleset = D20Ruleset;
+static PBTA: PbtaRuleset = PbtaRuleset;
+
+impl RulesetId {
+    pub fn get(&self) -> &'static dyn Ruleset {
+        match self {
+            RulesetId::D20 => &D20,
+            RulesetId::Pbta => &PBTA
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<RulesetId> {
+        match key.trim().to_lowercase().as_str() {
+            "d20" => Some(RulesetId::D20),
+            "pbta" => Some(RulesetId::Pbta),
+            _ => None
+        }
+    }
+}
@@ -0,0 +1,536 @@
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use super::campaign::{Campaign, with_campaign};
+use super::discord_data_structs::{
+    Component,
+    Interaction,
+    Message,
+    MessageObject,
+    ResponseOject,
+    BUTTON_STYLE_DANGER,
+    BUTTON_STYLE_SECONDARY,
+    BUTTON_STYLE_SUCCESS
+};
+use super::history;
+use super::i18n::{self, Locale};
+use super::snowflake::Snowflake;
+use super::AppState;
+
+/// how often upcoming sessions are checked for due reminders
+pub const REMINDER_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// minutes before a session that reminders go out, unless SESSION_REMINDERS says otherwise
+pub const DEFAULT_REMINDER_OFFSETS: [u64; 2] = [24 * 60, 60];
+
+pub const DEFAULT_SESSION_MINUTES: i64 = 180;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Rsvp {
+    Yes,
+    Maybe,
+    No,
+}
+
+impl Rsvp {
+    pub fn from_key(key: &str) -> Option<Rsvp> {
+        match key {
+            "yes" => Some(Rsvp::Yes),
+            "maybe" => Some(Rsvp::Maybe),
+            "no" => Some(Rsvp::No),
+            _ => None
+        }
+    }
+
+    /// the answer a reaction on an announcement stands for
+    pub fn from_emoji(emoji: &str) -> Option<Rsvp> {
+        match emoji {
+            "✅" => Some(Rsvp::Yes),
+            "🤔" => Some(Rsvp::Maybe),
+            "❌" => Some(Rsvp::No),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Session {
+    pub id: u32,
+    pub title: String,
+
+    /// unix timestamp in seconds
+    pub start: u64,
+    pub duration_minutes: u64,
+
+    /// IANA name of the timezone the session was scheduled in, such as Europe/Berlin
+    pub timezone: String,
+
+    /// user ids with their answer, in the order they answered
+    pub rsvps: Vec<(Snowflake, Rsvp)>,
+
+    /// reminder offsets in minutes that have already gone out
+    pub reminders_sent: Vec<u64>,
+
+    /// the announcement, once the gateway saw it posted. reactions on it count as answers
+    #[serde(default)]
+    pub message_id: Option<Snowflake>,
+}
+
+impl Session {
+    /// records a user's answer, replacing the one they gave before
+    pub fn answer(&mut self, user_id: Snowflake, rsvp: Rsvp) {
+        match self.rsvps.iter_mut().find(|(u, _)| *u == user_id) {
+            Some(entry) => entry.1 = rsvp,
+            None => self.rsvps.push((user_id, rsvp))
+        }
+    }
+
+    /// drops a user's answer, unless they've since given a different one
+    pub fn withdraw(&mut self, user_id: Snowflake, rsvp: Rsvp) {
+        self.rsvps.retain(|entry| *entry != (user_id, rsvp));
+    }
+
+    pub fn attendees(&self, rsvp: Rsvp) -> Vec<Snowflake> {
+        self.rsvps.iter()
+            .filter(|(_, r)| *r == rsvp)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// the announcement with discord timestamps, which every user sees in their own timezone
+    pub fn describe(&self, locale: &Locale) -> String {
+        let list = |ids: Vec<Snowflake>| if ids.is_empty() {
+            String::from("-")
+        } else {
+            ids.iter().map(|id| format!("<@{}>", id)).collect::<Vec<String>>().join(", ")
+        };
+
+        locale.format("session.announcement", &[
+            ("id", &self.id),
+            ("title", &self.title),
+            ("start", &self.start),
+            ("minutes", &self.duration_minutes),
+            ("timezone", &self.timezone),
+            ("yes", &list(self.attendees(Rsvp::Yes))),
+            ("maybe", &list(self.attendees(Rsvp::Maybe))),
+            ("no", &list(self.attendees(Rsvp::No)))
+        ])
+    }
+
+    fn buttons(&self, locale: &Locale) -> Vec<Component> {
+        vec![Component::action_row(vec![
+            Component::button(BUTTON_STYLE_SUCCESS, &locale.text("session.coming"), &format!("session:yes:{}", self.id)),
+            Component::button(BUTTON_STYLE_SECONDARY, &locale.text("session.maybe"), &format!("session:maybe:{}", self.id)),
+            Component::button(BUTTON_STYLE_DANGER, &locale.text("session.not_coming"), &format!("session:no:{}", self.id))
+        ])]
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ReminderConfig {
+    /// minutes before a session, largest first
+    pub offsets: Vec<u64>,
+}
+
+impl ReminderConfig {
+    /// reads SESSION_REMINDERS, a comma separated list of minutes such as "1440,60"
+    pub fn from_env() -> ReminderConfig {
+        let mut offsets: Vec<u64> = match env::var("SESSION_REMINDERS") {
+            Ok(v) => v.split(',').filter_map(|m| m.trim().parse().ok()).collect(),
+            Err(_) => DEFAULT_REMINDER_OFFSETS.to_vec()
+        };
+        offsets.sort_unstable_by(|a, b| b.cmp(a));
+        offsets.dedup();
+
+        ReminderConfig { offsets }
+    }
+}
+
+/// turns a local date and time in an IANA timezone into a unix timestamp. times skipped by
+/// a daylight saving change are refused, repeated ones resolve to the earlier instant
+pub fn parse_start(date: &str, time: &str, timezone: &str, locale: &Locale) -> Result<(u64, Tz), String> {
+    let tz: Tz = timezone.trim().parse()
+        .map_err(|_| locale.format("session.bad_timezone", &[("timezone", &timezone)]))?;
+    let date = NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+        .map_err(|_| locale.format("session.bad_date", &[("date", &date)]))?;
+    let time = NaiveTime::parse_from_str(time.trim(), "%H:%M")
+        .map_err(|_| locale.format("session.bad_time", &[("time", &time)]))?;
+
+    let start = tz.from_local_datetime(&date.and_time(time))
+        .earliest()
+        .ok_or(locale.format("session.skipped_time", &[("date", &date), ("time", &time), ("timezone", &tz)]))?;
+
+    u64::try_from(start.timestamp())
+        .map(|s| (s, tz))
+        .map_err(|_| locale.text("session.before_1970"))
+}
+
+fn ics_time(timestamp: u64) -> String {
+    DateTime::<Utc>::from_timestamp(timestamp as i64, 0)
+        .unwrap_or_default()
+        .format("%Y%m%dT%H%M%SZ")
+        .to_string()
+}
+
+fn ics_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// iCalendar file with every upcoming session of the campaign
+pub fn calendar(campaign: &Campaign, now: u64) -> String {
+    let mut lines: Vec<String> = vec![
+        String::from("BEGIN:VCALENDAR"),
+        String::from("VERSION:2.0"),
+        String::from("PRODID:-//discord_gm_bot//sessions//EN"),
+        String::from("CALSCALE:GREGORIAN")
+    ];
+
+    for session in campaign.sessions.iter().filter(|s| s.start >= now) {
+        lines.push(String::from("BEGIN:VEVENT"));
+        lines.push(format!("UID:session-{}-{}@discord_gm_bot", campaign.channel_id, session.id));
+        lines.push(format!("DTSTAMP:{}", ics_time(now)));
+        lines.push(format!("DTSTART:{}", ics_time(session.start)));
+        lines.push(format!("DTEND:{}", ics_time(session.start + session.duration_minutes * 60)));
+        lines.push(format!("SUMMARY:{}", ics_escape(&session.title)));
+        lines.push(String::from("END:VEVENT"));
+    }
+
+    lines.push(String::from("END:VCALENDAR"));
+    lines.join("\r\n") + "\r\n"
+}
+
+pub fn session_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    match interaction.subcommand() {
+        Some("schedule") => schedule(state, interaction),
+        Some("list") => list(state, interaction),
+        Some("cancel") => cancel(state, interaction),
+        Some("export") => export(state, interaction),
+        _ => ResponseOject::ephemeral(i18n::locale(state, interaction).format("error.unknown_command", &[("command", &"session")]))
+    }
+}
+
+fn schedule(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let (date, time, timezone) = match (interaction.option_str("date"), interaction.option_str("time"), interaction.option_str("timezone")) {
+        (Some(d), Some(t), Some(z)) => (d, t, z),
+        _ => return ResponseOject::ephemeral(locale.text("session.schedule_required"))
+    };
+    let title = interaction.option_str("title").map(|t| t.trim().to_string()).unwrap_or(locale.text("session.default_title"));
+    let duration = interaction.option_i64("minutes").unwrap_or(DEFAULT_SESSION_MINUTES).clamp(15, 24 * 60) as u64;
+
+    let (start, tz) = match parse_start(date, time, timezone, &locale) {
+        Ok(s) => s,
+        Err(e) => return ResponseOject::ephemeral(e)
+    };
+    if start <= history::now() {
+        return ResponseOject::ephemeral(locale.text("session.passed"))
+    }
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("session.not_gm_schedule"))
+        }
+
+        let session = Session {
+            id: campaign.next_session_id,
+            title: title.clone(),
+            start,
+            duration_minutes: duration,
+            timezone: tz.name().to_string(),
+            rsvps: Vec::new(),
+            reminders_sent: Vec::new(),
+            message_id: None
+        };
+        campaign.next_session_id += 1;
+
+        // the announcement is shared, so it speaks the server's language
+        let announcement = i18n::guild_locale(state, campaign.guild_id);
+        let response = ResponseOject::with_components(session.describe(&announcement), session.buttons(&announcement));
+        campaign.sessions.push(session);
+        response
+    })
+}
+
+fn list(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let now = history::now();
+
+    with_campaign(state, interaction, |campaign| {
+        let mut upcoming: Vec<&Session> = campaign.sessions.iter().filter(|s| s.start >= now).collect();
+        if upcoming.is_empty() {
+            return ResponseOject::ephemeral(locale.text("session.none"))
+        }
+
+        upcoming.sort_by_key(|s| s.start);
+        let sessions: Vec<String> = upcoming.iter().map(|s| s.describe(&locale)).collect();
+        ResponseOject::ephemeral(sessions.join("\n\n"))
+    })
+}
+
+fn cancel(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let id = match interaction.option_i64("id") {
+        Some(i) => i as u32,
+        None => return ResponseOject::ephemeral(locale.text("session.id_required"))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("session.not_gm_cancel"))
+        }
+
+        match campaign.sessions.iter().position(|s| s.id == id) {
+            Some(index) => {
+                let session = campaign.sessions.remove(index);
+                ResponseOject::new(locale.format("session.cancelled", &[("id", &session.id), ("title", &session.title), ("start", &session.start)]))
+            },
+            None => ResponseOject::ephemeral(locale.format("journal.unknown_session", &[("id", &id)]))
+        }
+    })
+}
+
+/// answers with a deferred response and uploads the calendar file into it
+fn export(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let mut ics: Option<String> = None;
+    let response = with_campaign(state, interaction, |campaign| {
+        ics = Some(calendar(campaign, history::now()));
+        ResponseOject::deferred_ephemeral()
+    });
+
+    let ics = match ics {
+        Some(i) => i,
+        None => return response
+    };
+
+    let discord = state.discord.clone();
+    let application_id = interaction.application_id;
+    let token = interaction.token.clone();
+    let content = locale.text("session.export");
+
+    tokio::spawn(async move {
+        let message = MessageObject {
+            content,
+            ..Default::default()
+        };
+
+        if let Err(e) = discord.edit_original_response_with_file(application_id, &token, message, "sessions.ics", ics.into_bytes()).await {
+            log::error!("could not upload session calendar\n{}", e);
+        }
+    });
+
+    response
+}
+
+/// records an RSVP and refreshes the announcement
+pub fn rsvp_button(state: &AppState, interaction: &Interaction, answer: &str, id: u32) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let (user_id, rsvp) = match (interaction.user_id(), Rsvp::from_key(answer)) {
+        (Some(u), Some(r)) => (u, r),
+        _ => return ResponseOject::ephemeral(locale.text("error.unable"))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        let announcement = i18n::guild_locale(state, campaign.guild_id);
+        let session = match campaign.sessions.iter_mut().find(|s| s.id == id) {
+            Some(s) => s,
+            None => return ResponseOject::update(locale.text("session.was_cancelled"), Vec::new())
+        };
+
+        session.answer(user_id, rsvp);
+        ResponseOject::update(session.describe(&announcement), session.buttons(&announcement))
+    })
+}
+
+/// remembers the message announcing a session, found by the RSVP buttons it carries
+pub fn track_announcement(campaign: &mut Campaign, message: &Message) {
+    let id = message.components.iter()
+        .flat_map(|row| row.components.iter().flatten())
+        .filter_map(|c| c.custom_id.as_deref())
+        .find_map(|c| c.strip_prefix("session:yes:"))
+        .and_then(|id| id.parse::<u32>().ok());
+
+    if let Some(session) = id.and_then(|id| campaign.sessions.iter_mut().find(|s| s.id == id)) {
+        session.message_id = Some(message.id);
+    }
+}
+
+/// records an answer given by reacting to an announcement, returning the refreshed
+/// announcement. `None` when the message announces no session
+pub fn rsvp_reaction(campaign: &mut Campaign, message_id: Snowflake, user_id: Snowflake, rsvp: Rsvp, locale: &Locale) -> Option<String> {
+    let session = campaign.sessions.iter_mut().find(|s| s.message_id == Some(message_id))?;
+    session.answer(user_id, rsvp);
+    Some(session.describe(locale))
+}
+
+/// withdraws the answer a removed reaction stood for, returning the refreshed
+/// announcement. `None` when the message announces no session
+pub fn rsvp_reaction_removed(campaign: &mut Campaign, message_id: Snowflake, user_id: Snowflake, rsvp: Rsvp, locale: &Locale) -> Option<String> {
+    let session = campaign.sessions.iter_mut().find(|s| s.message_id == Some(message_id))?;
+    session.withdraw(user_id, rsvp);
+    Some(session.describe(locale))
+}
+
+/// reminders due for a session, marking them as sent. only one message goes out even when
+/// several offsets are due at once, such as for a session scheduled at short notice
+fn due_reminder(session: &mut Session, offsets: &[u64], now: u64) -> bool {
+    if session.start <= now {
+        return false
+    }
+
+    let due: Vec<u64> = offsets.iter()
+        .copied()
+        .filter(|o| session.start.saturating_sub(o * 60) <= now && !session.reminders_sent.contains(o))
+        .collect();
+
+    session.reminders_sent.extend(&due);
+    !due.is_empty()
+}
+
+/// posts reminders mentioning everyone coming or maybe coming, checking every
+/// `REMINDER_CHECK_INTERVAL`
+pub fn spawn_reminder_task(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(REMINDER_CHECK_INTERVAL);
+
+        loop {
+            ticker.tick().await;
+            let now = history::now();
+
+            let mut lock = match state.campaigns.lock() {
+                Ok(l) => l,
+                Err(e) => {
+                    log::error!("unable to obtain lock for app state\n{}", e);
+                    continue
+                }
+            };
+
+            for campaign in lock.iter_mut() {
+                let channel_id = campaign.announce_channel();
+                let locale = i18n::guild_locale(&state, campaign.guild_id);
+                for session in campaign.sessions.iter_mut() {
+                    if !due_reminder(session, &state.reminders.offsets, now) {
+                        continue
+                    }
+
+                    let mut attendees = session.attendees(Rsvp::Yes);
+                    attendees.extend(session.attendees(Rsvp::Maybe));
+                    let mentions: Vec<String> = attendees.iter().map(|id| format!("<@{}>", id)).collect();
+
+                    state.discord.spawn_message(channel_id, MessageObject {
+                        content: locale.format("session.reminder", &[("title", &session.title), ("start", &session.start), ("players", &mentions.join(" "))]),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ruleset::RulesetId;
+
+    #[test]
+    fn start_is_read_in_the_given_timezone() {
+        let (start, _) = parse_start("2030-07-01", "19:30", "Europe/Berlin", &Locale::new(None, None)).unwrap();
+        assert_eq!(ics_time(start), "20300701T173000Z");
+
+        assert!(parse_start("2030-03-31", "02:30", "Europe/Berlin", &Locale::new(None, None)).is_err());
+        assert!(parse_start("2030-07-01", "19:30", "Mars/Olympus", &Locale::new(None, None)).is_err());
+    }
+
+    #[test]
+    fn calendar_lists_upcoming_sessions() {
+        let mut campaign = Campaign::new(Snowflake::new(10), RulesetId::D20);
+        for (id, start) in [(0, 100), (1, 10_000)] {
+            campaign.sessions.push(Session {
+                id,
+                title: String::from("dungeon, part 2"),
+                start,
+                duration_minutes: 60,
+                timezone: String::from("UTC"),
+                rsvps: Vec::new(),
+                reminders_sent: Vec::new(),
+                message_id: None
+            });
+        }
+
+        let ics = calendar(&campaign, 5_000);
+
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 1);
+        assert!(ics.contains("SUMMARY:dungeon\\, part 2\r\n"));
+        assert!(ics.contains("DTEND:19700101T034640Z"));
+    }
+
+    #[test]
+    fn reminders_go_out_once() {
+        let mut session = Session {
+            id: 0,
+            title: String::from("game night"),
+            start: 10_000,
+            duration_minutes: 60,
+            timezone: String::from("UTC"),
+            rsvps: Vec::new(),
+            reminders_sent: Vec::new(),
+            message_id: None
+        };
+
+        assert!(!due_reminder(&mut session, &[60], 5_000));
+        assert!(due_reminder(&mut session, &[1440, 60], 7_000));
+        assert!(!due_reminder(&mut session, &[1440, 60], 8_000));
+    }
+
+    #[test]
+    fn reactions_on_the_announcement_count_as_answers() {
+        let mut campaign = Campaign::new(Snowflake::new(10), RulesetId::D20);
+        let mut session = Session {
+            id: 3,
+            title: String::from("game night"),
+            start: 10_000,
+            duration_minutes: 60,
+            timezone: String::from("UTC"),
+            rsvps: Vec::new(),
+            reminders_sent: Vec::new(),
+            message_id: None
+        };
+        session.answer(Snowflake::new(1), Rsvp::No);
+        campaign.sessions.push(session);
+
+        let announcement: Message = serde_json::from_value(serde_json::json!({
+            "id": "1301337999988877766",
+            "channel_id": "10",
+            "content": "**session #3: game night**",
+            "timestamp": "2030-07-01T17:30:00.000000+00:00",
+            "edited_timestamp": null,
+            "components": [{ "type": 1, "components": [
+                { "type": 2, "style": 3, "label": "Coming", "custom_id": "session:yes:3" },
+                { "type": 2, "style": 2, "label": "Maybe", "custom_id": "session:maybe:3" }
+            ] }]
+        })).unwrap();
+        track_announcement(&mut campaign, &announcement);
+
+        let rsvp = Rsvp::from_emoji("✅").unwrap();
+        assert!(rsvp_reaction(&mut campaign, Snowflake::new(99), Snowflake::new(1), rsvp, &Locale::new(None, None)).is_none());
+
+        let description = rsvp_reaction(&mut campaign, announcement.id, Snowflake::new(1), rsvp, &Locale::new(None, None)).unwrap();
+        assert!(description.contains("coming: <@1>"));
+        assert_eq!(campaign.sessions[0].rsvps, vec![(Snowflake::new(1), Rsvp::Yes)]);
+
+        // taking back a reaction only withdraws the answer it still stands for
+        rsvp_reaction(&mut campaign, announcement.id, Snowflake::new(2), Rsvp::Maybe, &Locale::new(None, None));
+        rsvp_reaction(&mut campaign, announcement.id, Snowflake::new(2), Rsvp::No, &Locale::new(None, None));
+        rsvp_reaction_removed(&mut campaign, announcement.id, Snowflake::new(2), Rsvp::Maybe, &Locale::new(None, None));
+        let description = rsvp_reaction_removed(&mut campaign, announcement.id, Snowflake::new(1), rsvp, &Locale::new(None, None)).unwrap();
+        assert!(!description.contains("<@1>"));
+        assert_eq!(campaign.sessions[0].rsvps, vec![(Snowflake::new(2), Rsvp::No)]);
+    }
+}
@@ -0,0 +1,320 @@
+use serde::{Deserialize, Serialize};
+use super::currency::Purse;
+use super::campaign::with_campaign;
+use super::discord_data_structs::{Interaction, ResponseOject};
+use super::i18n::{self, Locale};
+use super::inventory::MAX_QUANTITY;
+use super::AppState;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ShopStock {
+    pub item: String,
+
+    /// price in copper pieces
+    pub price: u32,
+
+    /// how many the shop has left, `None` for an endless supply
+    pub quantity: Option<u32>,
+}
+
+/// a GM defined shop attached to a campaign
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Shop {
+    pub name: String,
+    pub stock: Vec<ShopStock>,
+}
+
+impl Shop {
+    pub fn new(name: &str) -> Shop {
+        Shop {
+            name: name.to_string(),
+            stock: Vec::new()
+        }
+    }
+
+    pub fn stock(&self, item: &str) -> Option<&ShopStock> {
+        self.stock.iter().find(|s| s.item.eq_ignore_ascii_case(item))
+    }
+
+    pub fn stock_mut(&mut self, item: &str) -> Option<&mut ShopStock> {
+        self.stock.iter_mut().find(|s| s.item.eq_ignore_ascii_case(item))
+    }
+
+    /// adds to an item's stock, setting its price. `None` quantity means an endless supply
+    pub fn restock(&mut self, item: &str, price: u32, quantity: Option<u32>) {
+        match self.stock_mut(item) {
+            Some(s) => {
+                s.price = price;
+                s.quantity = match (s.quantity, quantity) {
+                    (Some(have), Some(add)) => Some(have.saturating_add(add)),
+                    _ => None
+                };
+            },
+            None => self.stock.push(ShopStock {
+                item: item.to_string(),
+                price,
+                quantity
+            })
+        }
+    }
+
+    pub fn describe(&self, locale: &Locale) -> String {
+        let mut lines: Vec<String> = vec![format!("**{}**", self.name)];
+
+        if self.stock.is_empty() {
+            lines.push(locale.text("shop.nothing"));
+        }
+
+        for s in &self.stock {
+            let quantity = match s.quantity {
+                Some(q) => locale.format("shop.left", &[("quantity", &q)]),
+                None => locale.text("shop.plenty")
+            };
+            lines.push(format!("- {}: {} ({})", locale.term(&s.item), Purse::from_copper(s.price), quantity));
+        }
+
+        lines.join("\n")
+    }
+}
+
+/// fraction of an item's price a shop pays when buying it from a player
+pub const SELL_PRICE_DIVISOR: u32 = 2;
+
+pub fn shop_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    match interaction.subcommand() {
+        Some("create") => create_shop(state, interaction),
+        Some("stock") => stock_shop(state, interaction),
+        Some("view") => view_shop(state, interaction),
+        _ => ResponseOject::ephemeral(i18n::locale(state, interaction).format("error.unknown_command", &[("command", &"shop")]))
+    }
+}
+
+fn create_shop(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let name = match interaction.option_str("name") {
+        Some(n) => n.trim(),
+        None => return ResponseOject::ephemeral(locale.text("shop.name_required"))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("shop.not_gm_create"))
+        }
+
+        if campaign.shop(name).is_some() {
+            return ResponseOject::ephemeral(locale.format("shop.exists", &[("shop", &name)]))
+        }
+
+        campaign.shops.push(Shop::new(name));
+        ResponseOject::new(locale.format("shop.opened", &[("shop", &name)]))
+    })
+}
+
+fn stock_shop(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let (shop_name, item_name) = match (interaction.option_str("shop"), interaction.option_str("item")) {
+        (Some(s), Some(i)) => (s, i),
+        _ => return ResponseOject::ephemeral(locale.text("shop.item_required"))
+    };
+
+    let item = match state.catalog.find(item_name) {
+        Some(i) => i,
+        None => return ResponseOject::ephemeral(locale.format("item.unknown", &[("item", &item_name)]))
+    };
+
+    let price = match interaction.option_str("price") {
+        Some(p) => match Purse::parse_copper(p) {
+            Ok(copper) => copper,
+            Err(e) => return ResponseOject::ephemeral(e.describe(&locale))
+        },
+        None => item.value
+    };
+    let quantity = interaction.option_i64("quantity").map(|q| q.clamp(0, MAX_QUANTITY as i64) as u32);
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("shop.not_gm_stock"))
+        }
+
+        match campaign.shop_mut(shop_name) {
+            Some(shop) => {
+                shop.restock(&item.name, price, quantity);
+                ResponseOject::new(locale.format("shop.stocked", &[("shop", &shop.name), ("item", &locale.term(&item.name)), ("price", &Purse::from_copper(price))]))
+            },
+            None => ResponseOject::ephemeral(locale.format("shop.unknown", &[("shop", &shop_name)]))
+        }
+    })
+}
+
+fn view_shop(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let shop_name = interaction.option_str("shop");
+
+    with_campaign(state, interaction, |campaign| {
+        match shop_name {
+            Some(name) => match campaign.shop(name) {
+                Some(shop) => ResponseOject::new(shop.describe(&locale)),
+                None => ResponseOject::ephemeral(locale.format("shop.unknown", &[("shop", &name)]))
+            },
+            None => {
+                if campaign.shops.is_empty() {
+                    return ResponseOject::ephemeral(locale.text("shop.none"))
+                }
+
+                let names: Vec<String> = campaign.shops.iter().map(|s| format!("- {}", s.name)).collect();
+                ResponseOject::new(locale.format("shop.list", &[("shops", &names.join("\n"))]))
+            }
+        }
+    })
+}
+
+pub fn buy_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let (user_id, shop_name, item_name) = match (interaction.user_id(), interaction.option_str("shop"), interaction.option_str("item")) {
+        (Some(u), Some(s), Some(i)) => (u, s, i),
+        _ => return ResponseOject::ephemeral(locale.text("shop.item_required"))
+    };
+    let quantity = interaction.option_i64("quantity").unwrap_or(1).clamp(1, MAX_QUANTITY as i64) as u32;
+
+    with_campaign(state, interaction, |campaign| {
+        let (item, price) = match campaign.shop(shop_name) {
+            Some(shop) => match shop.stock(item_name) {
+                Some(s) if s.quantity.is_some_and(|q| q < quantity) => {
+                    return ResponseOject::ephemeral(locale.format("shop.only_left", &[("shop", &shop.name), ("quantity", &s.quantity.unwrap_or(0)), ("item", &locale.term(&s.item))]))
+                },
+                Some(s) => match s.price.checked_mul(quantity) {
+                    Some(price) => (s.item.clone(), price),
+                    None => return ResponseOject::ephemeral(locale.format("shop.too_expensive", &[("item", &locale.term(&s.item)), ("quantity", &quantity)]))
+                },
+                None => return ResponseOject::ephemeral(locale.format("shop.not_sold", &[("shop", &shop.name), ("item", &item_name)]))
+            },
+            None => return ResponseOject::ephemeral(locale.format("shop.unknown", &[("shop", &shop_name)]))
+        };
+
+        let player = match campaign.player_mut(user_id) {
+            Some(p) => p,
+            None => return ResponseOject::ephemeral(locale.text("player.not_joined"))
+        };
+
+        // added to a copy first so the coins stay put if the items don't fit
+        let mut inventory = player.inventory.clone();
+        if let Err(e) = inventory.add(&item, quantity, &locale) {
+            return ResponseOject::ephemeral(e)
+        }
+        if let Err(e) = player.purse.spend(price) {
+            return ResponseOject::ephemeral(e.describe(&locale))
+        }
+        player.inventory = inventory;
+        let message = locale.format("shop.bought", &[("player", &player.name), ("item", &locale.term(&item)), ("quantity", &quantity), ("price", &Purse::from_copper(price))]);
+
+        if let Some(stock) = campaign.shop_mut(shop_name).and_then(|s| s.stock_mut(&item))
+            && let Some(q) = stock.quantity.as_mut() {
+            *q -= quantity;
+        }
+
+        ResponseOject::new(message)
+    })
+}
+
+pub fn sell_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let (user_id, shop_name, item_name) = match (interaction.user_id(), interaction.option_str("shop"), interaction.option_str("item")) {
+        (Some(u), Some(s), Some(i)) => (u, s, i),
+        _ => return ResponseOject::ephemeral(locale.text("shop.item_required"))
+    };
+    let quantity = interaction.option_i64("quantity").unwrap_or(1).clamp(1, MAX_QUANTITY as i64) as u32;
+
+    let item = match state.catalog.find(item_name) {
+        Some(i) => i,
+        None => return ResponseOject::ephemeral(locale.format("item.unknown", &[("item", &item_name)]))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        let unit_price = match campaign.shop(shop_name) {
+            Some(shop) => shop.stock(&item.name).map(|s| s.price).unwrap_or(item.value),
+            None => return ResponseOject::ephemeral(locale.format("shop.unknown", &[("shop", &shop_name)]))
+        };
+        let payment = match unit_price.checked_mul(quantity) {
+            Some(p) => p / SELL_PRICE_DIVISOR,
+            None => return ResponseOject::ephemeral(locale.format("shop.cant_pay", &[("shop", &shop_name), ("item", &locale.term(&item.name)), ("quantity", &quantity)]))
+        };
+
+        let player = match campaign.player_mut(user_id) {
+            Some(p) => p,
+            None => return ResponseOject::ephemeral(locale.text("player.not_joined"))
+        };
+
+        // paid into a copy first so the goods stay put if the purse is full
+        let mut purse = player.purse.clone();
+        if let Err(e) = purse.add_copper(payment) {
+            return ResponseOject::ephemeral(e.describe(&locale))
+        }
+        if let Err(e) = player.inventory.remove(&item.name, quantity, &locale) {
+            return ResponseOject::ephemeral(e)
+        }
+        player.purse = purse;
+        let message = locale.format("shop.sold", &[("player", &player.name), ("item", &locale.term(&item.name)), ("quantity", &quantity), ("price", &Purse::from_copper(payment))]);
+
+        if let Some(shop) = campaign.shop_mut(shop_name) {
+            match shop.stock(&item.name) {
+                Some(s) => {
+                    let price = s.price;
+                    shop.restock(&item.name, price, Some(quantity));
+                },
+                None => shop.restock(&item.name, item.value, Some(quantity))
+            }
+        }
+
+        ResponseOject::new(message)
+    })
+}
+
+pub fn purse_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    match interaction.subcommand() {
+        Some("show") => show_purse(state, interaction),
+        Some("grant") => grant_coins(state, interaction),
+        _ => ResponseOject::ephemeral(i18n::locale(state, interaction).format("error.unknown_command", &[("command", &"purse")]))
+    }
+}
+
+fn show_purse(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let user_id = match interaction.option_snowflake("player").or(interaction.user_id()) {
+        Some(u) => u,
+        None => return ResponseOject::ephemeral(locale.text("error.no_user"))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        match campaign.player(user_id) {
+            Some(p) => ResponseOject::ephemeral(locale.format("purse.carries", &[("player", &p.name), ("coins", &p.purse)])),
+            None => ResponseOject::ephemeral(locale.text("player.other_not_joined"))
+        }
+    })
+}
+
+fn grant_coins(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let (target, amount) = match (interaction.option_snowflake("player"), interaction.option_str("amount")) {
+        (Some(t), Some(a)) => (t, a),
+        _ => return ResponseOject::ephemeral(locale.text("purse.grant_required"))
+    };
+
+    let coins = match Purse::parse(amount) {
+        Ok(c) => c,
+        Err(e) => return ResponseOject::ephemeral(e.describe(&locale))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) {
+            return ResponseOject::ephemeral(locale.text("purse.not_gm_grant"))
+        }
+
+        match campaign.player_mut(target) {
+            Some(player) => match player.purse.add(&coins) {
+                Ok(()) => ResponseOject::new(locale.format("purse.received", &[("player", &player.name), ("coins", &coins)])),
+                Err(e) => ResponseOject::ephemeral(e.describe(&locale))
+            },
+            None => ResponseOject::ephemeral(locale.text("player.other_not_joined"))
+        }
+    })
+}
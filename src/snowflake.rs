@@ -0,0 +1,137 @@
+use std::fmt;
+use std::num::ParseIntError;
+use std::str::FromStr;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::{self, Visitor};
+
+/// first millisecond of 2015, where discord starts counting snowflake timestamps
+pub const DISCORD_EPOCH_MS: u64 = 1_420_070_400_000;
+
+/// a discord id. discord sends them as strings since they outgrow javascript numbers, so
+/// they are written as strings too, but compared and hashed as the u64 they are.
+///
+/// the top 42 bits are milliseconds since `DISCORD_EPOCH_MS`, followed by 5 bits of worker
+/// id, 5 bits of process id and a 12 bit counter
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Snowflake(u64);
+
+impl Snowflake {
+    pub const fn new(id: u64) -> Snowflake {
+        Snowflake(id)
+    }
+
+    pub const fn get(self) -> u64 {
+        self.0
+    }
+
+    /// when the object behind the id was created, in unix milliseconds
+    pub const fn timestamp_ms(self) -> u64 {
+        (self.0 >> 22) + DISCORD_EPOCH_MS
+    }
+
+    /// when the object behind the id was created, in unix seconds
+    pub const fn timestamp(self) -> u64 {
+        self.timestamp_ms() / 1000
+    }
+
+    pub const fn worker_id(self) -> u8 {
+        ((self.0 >> 17) & 0x1f) as u8
+    }
+
+    pub const fn process_id(self) -> u8 {
+        ((self.0 >> 12) & 0x1f) as u8
+    }
+
+    /// counts up for every id the process generated in the same millisecond
+    pub const fn increment(self) -> u16 {
+        (self.0 & 0xfff) as u16
+    }
+}
+
+impl From<u64> for Snowflake {
+    fn from(id: u64) -> Snowflake {
+        Snowflake(id)
+    }
+}
+
+impl FromStr for Snowflake {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Snowflake, ParseIntError> {
+        s.trim().parse::<u64>().map(Snowflake)
+    }
+}
+
+impl fmt::Display for Snowflake {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for Snowflake {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+struct SnowflakeVisitor;
+
+impl Visitor<'_> for SnowflakeVisitor {
+    type Value = Snowflake;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a discord id as a string or an integer")
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Snowflake, E> {
+        Ok(Snowflake(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Snowflake, E> {
+        u64::try_from(value)
+            .map(Snowflake)
+            .map_err(|_| E::invalid_value(de::Unexpected::Signed(value), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Snowflake, E> {
+        value.parse().map_err(|_| E::invalid_value(de::Unexpected::Str(value), &self))
+    }
+}
+
+/// reads both the strings discord sends and plain numbers
+impl<'de> Deserialize<'de> for Snowflake {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Snowflake, D::Error> {
+        deserializer.deserialize_any(SnowflakeVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn reads_the_fields_packed_into_an_id() {
+        // the example from discord's documentation
+        let id: Snowflake = "175928847299117063".parse().unwrap();
+        assert_eq!(id.timestamp_ms(), 1_462_015_105_796);
+        assert_eq!(id.timestamp(), 1_462_015_105);
+        assert_eq!(id.worker_id(), 1);
+        assert_eq!(id.process_id(), 0);
+        assert_eq!(id.increment(), 7);
+    }
+
+    #[test]
+    fn serializes_as_a_string_and_reads_strings_and_numbers() {
+        let id = Snowflake::new(175928847299117063);
+        assert_eq!(serde_json::to_string(&id).unwrap(), "\"175928847299117063\"");
+        assert_eq!(serde_json::from_str::<Snowflake>("\"175928847299117063\"").unwrap(), id);
+        assert_eq!(serde_json::from_str::<Snowflake>("175928847299117063").unwrap(), id);
+        assert!(serde_json::from_str::<Snowflake>("\"tavern\"").is_err());
+        assert!(serde_json::from_str::<Snowflake>("-1").is_err());
+
+        let keyed: HashMap<Snowflake, u32> = serde_json::from_str("{\"42\": 1}").unwrap();
+        assert_eq!(keyed[&Snowflake::new(42)], 1);
+        assert_eq!(serde_json::to_string(&keyed).unwrap(), "{\"42\":1}");
+    }
+}
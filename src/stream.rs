@@ -0,0 +1,186 @@
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::time::{self, MissedTickBehavior};
+use super::discord_api::DiscordClient;
+use super::discord_data_structs::{MessageObject, MAX_MESSAGE_LENGTH};
+use super::snowflake::Snowflake;
+
+/// how often streamed narration is written into discord, keeping well clear of the
+/// webhook rate limit
+pub const EDIT_INTERVAL: Duration = Duration::from_millis(1500);
+
+/// splits a message into pages discord accepts, breaking at the last newline or space
+/// that fits. a page only depends on the text before its end, so the pages of a growing
+/// message never shift once they are full
+pub fn split_message(content: &str) -> Vec<String> {
+    let mut pages: Vec<String> = Vec::new();
+    let mut rest = content;
+
+    while rest.chars().count() > MAX_MESSAGE_LENGTH {
+        let limit = rest.char_indices().nth(MAX_MESSAGE_LENGTH).map(|(i, _)| i).unwrap_or(rest.len());
+        let head = &rest[..limit];
+        let end = head.rfind('\n')
+            .or(head.rfind(' '))
+            .filter(|i| *i > 0)
+            .unwrap_or(limit);
+
+        pages.push(rest[..end].to_string());
+        rest = rest[end..].trim_start_matches(['\n', ' ']);
+    }
+
+    if !rest.is_empty() || pages.is_empty() {
+        pages.push(rest.to_string());
+    }
+    pages
+}
+
+/// writes a growing message into a deferred interaction response, editing the original
+/// response and overflowing into follow-up messages past the character limit
+pub struct MessageStream {
+    discord: DiscordClient,
+    application_id: Snowflake,
+    token: String,
+    content: String,
+
+    /// the page last written to each message, the first being the original response
+    sent: Vec<String>,
+
+    /// ids of follow-up messages, one for every page after the first
+    followups: Vec<Snowflake>,
+}
+
+impl MessageStream {
+    pub fn new(discord: DiscordClient, application_id: Snowflake, token: &str, content: &str) -> MessageStream {
+        MessageStream {
+            discord,
+            application_id,
+            token: token.to_string(),
+            content: content.to_string(),
+            sent: Vec::new(),
+            followups: Vec::new()
+        }
+    }
+
+    pub fn push(&mut self, text: &str) {
+        self.content.push_str(text);
+    }
+
+    /// writes every page that changed since the last flush
+    pub async fn flush(&mut self) {
+        for (index, page) in split_message(&self.content).into_iter().enumerate() {
+            if self.sent.get(index) == Some(&page) {
+                continue
+            }
+
+            let message = MessageObject {
+                content: page.clone(),
+                ..Default::default()
+            };
+
+            let result = match index {
+                0 => self.discord.edit_original_response(self.application_id, &self.token, &message).await,
+                _ => match self.followups.get(index - 1) {
+                    Some(id) => self.discord.edit_webhook_message(self.application_id, &self.token, &id.to_string(), &message).await,
+                    None => match self.discord.create_followup_message(self.application_id, &self.token, &message).await {
+                        Ok(id) => {
+                            self.followups.push(id);
+                            Ok(())
+                        },
+                        Err(e) => Err(e)
+                    }
+                }
+            };
+
+            match result {
+                Ok(()) if index < self.sent.len() => self.sent[index] = page,
+                Ok(()) => self.sent.push(page),
+                Err(e) => {
+                    // later pages would land out of order, try again on the next flush
+                    log::error!("could not write page {} of a streamed message\n{}", index + 1, e);
+                    return
+                }
+            }
+        }
+    }
+
+    /// appends text from `receiver` as it arrives, flushing at most once per `interval`,
+    /// until the sender is dropped
+    pub async fn follow(&mut self, mut receiver: UnboundedReceiver<String>, interval: Duration) {
+        let mut ticker = time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut dirty = false;
+
+        loop {
+            tokio::select! {
+                text = receiver.recv() => match text {
+                    Some(t) => {
+                        self.push(&t);
+                        dirty = true;
+                    },
+                    None => break
+                },
+                _ = ticker.tick(), if dirty => {
+                    self.flush().await;
+                    dirty = false;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use axum::{Json, Router, extract::{Path, State}, routing::{patch, post}};
+
+    #[test]
+    fn long_messages_split_on_word_boundaries() {
+        let content = "word ".repeat(900).trim_end().to_string();
+        let pages = split_message(&content);
+
+        assert_eq!(pages.len(), 3);
+        assert!(pages.iter().all(|p| p.chars().count() <= MAX_MESSAGE_LENGTH));
+        assert!(pages.iter().all(|p| p.starts_with("word") && p.ends_with("word")));
+        assert_eq!(split_message("short"), vec![String::from("short")]);
+    }
+
+    #[tokio::test]
+    async fn overflow_goes_into_followups() {
+        type Log = Arc<Mutex<Vec<(String, usize)>>>;
+        let log: Log = Arc::new(Mutex::new(Vec::new()));
+
+        let app = Router::new()
+            .route("/webhooks/1183455781045665800/token", post(|State(log): State<Log>, Json(body): Json<serde_json::Value>| async move {
+                let length = body["content"].as_str().unwrap_or("").chars().count();
+                log.lock().unwrap().push((String::from("create"), length));
+                Json(serde_json::json!({ "id": "42" }))
+            }))
+            .route("/webhooks/1183455781045665800/token/messages/{id}", patch(|State(log): State<Log>, Path(id): Path<String>, Json(body): Json<serde_json::Value>| async move {
+                let length = body["content"].as_str().unwrap_or("").chars().count();
+                log.lock().unwrap().push((id, length));
+                Json(serde_json::json!({}))
+            }))
+            .with_state(log.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let discord = DiscordClient::new("test", &format!("http://{}", address));
+        let mut stream = MessageStream::new(discord, Snowflake::new(1183455781045665800), "token", "");
+
+        stream.push(&"a ".repeat(500));
+        stream.flush().await;
+        stream.push(&"b ".repeat(1000));
+        stream.flush().await;
+        stream.flush().await;
+
+        let log = log.lock().unwrap().clone();
+        assert_eq!(log, vec![
+            (String::from("@original"), 1000),
+            (String::from("@original"), 1999),
+            (String::from("create"), 1000)
+        ]);
+    }
+}
@@ -0,0 +1,196 @@
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use super::campaign::with_campaign;
+use super::discord_data_structs::{
+    Interaction,
+    MessageObject,
+    ResponseOject,
+    StartThread,
+    ChannelType
+};
+use super::history;
+use super::i18n::{self, Locale};
+use super::permissions::Permissions;
+use super::snowflake::Snowflake;
+use super::AppState;
+
+/// threads archive after a week without messages, the longest discord allows
+pub const THREAD_ARCHIVE_MINUTES: u32 = 10080;
+
+/// user options of /sidequest, the caller comes along on top of these
+pub const MAX_SIDEQUEST_PLAYERS: usize = 4;
+
+/// discord's limit on channel names
+const MAX_THREAD_NAME_LENGTH: usize = 100;
+
+/// a private thread for part of the party
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Sidequest {
+    pub thread_id: Snowflake,
+    pub name: String,
+
+    /// user ids of the players invited into the thread
+    pub players: Vec<Snowflake>,
+
+    /// unix timestamp in seconds
+    pub started: u64,
+}
+
+fn thread_name(name: &str) -> String {
+    name.chars().take(MAX_THREAD_NAME_LENGTH).collect()
+}
+
+/// the text channel new threads go into: the parent when called from inside a thread,
+/// since threads can't hold threads of their own
+pub fn thread_parent(interaction: &Interaction) -> Option<Snowflake> {
+    match &interaction.channel {
+        Some(channel) if channel.is_thread() => channel.parent_id,
+        _ => interaction.channel_id
+    }
+}
+
+/// starts the public thread a new campaign is played in and links it to the campaign
+pub fn spawn_campaign_thread(state: &Arc<AppState>, locale: &Locale, campaign_id: u32, channel_id: Snowflake, name: &str) {
+    let state = state.clone();
+    let locale = locale.clone();
+    let thread = StartThread {
+        name: thread_name(name),
+        r#type: ChannelType::PublicThread,
+        auto_archive_duration: Some(THREAD_ARCHIVE_MINUTES),
+        invitable: None
+    };
+
+    tokio::spawn(async move {
+        let thread = match state.discord.create_thread(channel_id, &thread).await {
+            Ok(t) => t,
+            Err(e) => {
+                log::error!("could not start a thread for campaign {}\n{}", campaign_id, e);
+                state.discord.spawn_message(channel_id, MessageObject {
+                    content: locale.text("thread.failed"),
+                    ..Default::default()
+                });
+                return
+            }
+        };
+
+        match state.campaigns.lock() {
+            Ok(mut lock) => {
+                if let Some(campaign) = lock.get_mut(campaign_id) {
+                    campaign.thread_id = Some(thread.id);
+                }
+                lock.link(thread.id, campaign_id);
+            },
+            Err(e) => {
+                log::error!("unable to obtain lock for app state\n{}", e);
+                return
+            }
+        }
+
+        state.discord.spawn_message(thread.id, MessageObject {
+            content: locale.text("thread.played_here"),
+            ..Default::default()
+        });
+    });
+}
+
+/// spins off a private thread for some of the players. it shares the campaign's state
+/// and history, only the conversation is separate
+pub fn sidequest_command(state: &Arc<AppState>, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let (user_id, name) = match (interaction.user_id(), interaction.option_str("name")) {
+        (Some(u), Some(n)) if !n.trim().is_empty() => (u, n.trim().to_string()),
+        _ => return ResponseOject::ephemeral(locale.text("sidequest.name_required"))
+    };
+    let parent = match thread_parent(interaction) {
+        Some(p) => p,
+        None => return ResponseOject::ephemeral(locale.text("error.no_channel"))
+    };
+
+    let missing = interaction.app_permissions().missing(Permissions::CREATE_PRIVATE_THREADS | Permissions::SEND_MESSAGES_IN_THREADS);
+    if !missing.is_empty() {
+        return ResponseOject::ephemeral(locale.format("sidequest.missing_permissions", &[("permissions", &missing)]))
+    }
+
+    let mut players: Vec<Snowflake> = (1..=MAX_SIDEQUEST_PLAYERS)
+        .filter_map(|i| interaction.option_snowflake(&format!("player{}", i)))
+        .collect();
+    players.sort_unstable();
+    players.dedup();
+
+    let mut campaign_id: Option<u32> = None;
+    let response = with_campaign(state, interaction, |campaign| {
+        if !campaign.can_manage(interaction) && campaign.player(user_id).is_none() {
+            return ResponseOject::ephemeral(locale.text("player.not_joined"))
+        }
+        if let Some(missing) = players.iter().find(|p| campaign.player(**p).is_none()) {
+            return ResponseOject::ephemeral(locale.format("sidequest.not_joined", &[("user", &missing)]))
+        }
+
+        campaign_id = Some(campaign.id);
+        ResponseOject::deferred_ephemeral()
+    });
+
+    let campaign_id = match campaign_id {
+        Some(c) => c,
+        None => return response
+    };
+
+    if !players.contains(&user_id) {
+        players.push(user_id);
+    }
+
+    let state = state.clone();
+    let application_id = interaction.application_id;
+    let token = interaction.token.clone();
+    let thread = StartThread {
+        name: thread_name(&name),
+        r#type: ChannelType::PrivateThread,
+        auto_archive_duration: Some(THREAD_ARCHIVE_MINUTES),
+        invitable: Some(false)
+    };
+
+    tokio::spawn(async move {
+        let content = match state.discord.create_thread(parent, &thread).await {
+            Ok(thread) => {
+                for player in &players {
+                    if let Err(e) = state.discord.add_thread_member(thread.id, *player).await {
+                        log::error!("could not add {} to sidequest thread {}\n{}", player, thread.id, e);
+                    }
+                }
+
+                match state.campaigns.lock() {
+                    Ok(mut lock) => {
+                        if let Some(campaign) = lock.get_mut(campaign_id) {
+                            campaign.sidequests.push(Sidequest {
+                                thread_id: thread.id,
+                                name: name.clone(),
+                                players: players.clone(),
+                                started: history::now()
+                            });
+                        }
+                        lock.link(thread.id, campaign_id);
+                    },
+                    Err(e) => log::error!("unable to obtain lock for app state\n{}", e)
+                }
+
+                let mentions: Vec<String> = players.iter().map(|p| format!("<@{}>", p)).collect();
+                state.discord.spawn_message(thread.id, MessageObject {
+                    content: locale.format("sidequest.intro", &[("name", &name), ("players", &mentions.join(", "))]),
+                    ..Default::default()
+                });
+                locale.format("sidequest.started", &[("thread", &thread.id)])
+            },
+            Err(e) => {
+                log::error!("could not start sidequest thread for campaign {}\n{}", campaign_id, e);
+                locale.text("sidequest.failed")
+            }
+        };
+
+        let message = MessageObject { content, ..Default::default() };
+        if let Err(e) = state.discord.edit_original_response(application_id, &token, &message).await {
+            log::error!("could not answer sidequest command\n{}", e);
+        }
+    });
+
+    response
+}
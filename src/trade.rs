@@ -0,0 +1,316 @@
+use serde::{Deserialize, Serialize};
+use super::campaign::{Campaign, with_campaign};
+use super::currency::Purse;
+use super::discord_data_structs::{
+    Component,
+    Interaction,
+    ResponseOject,
+    BUTTON_STYLE_DANGER,
+    BUTTON_STYLE_SUCCESS
+};
+use super::i18n::{self, Locale};
+use super::inventory::MAX_QUANTITY;
+use super::player::Player;
+use super::snowflake::Snowflake;
+use super::AppState;
+
+/// what one side of a trade hands over
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+pub struct TradeSide {
+    pub item: Option<String>,
+    pub quantity: u32,
+
+    /// coins in copper pieces
+    pub coins: u32,
+}
+
+impl TradeSide {
+    fn describe(&self, locale: &Locale) -> String {
+        let mut parts: Vec<String> = Vec::new();
+        if let Some(item) = &self.item {
+            parts.push(format!("{} x{}", locale.term(item), self.quantity));
+        }
+        if self.coins > 0 {
+            parts.push(Purse::from_copper(self.coins).to_string());
+        }
+
+        if parts.is_empty() {
+            locale.text("trade.nothing")
+        } else {
+            parts.join(&locale.text("trade.and"))
+        }
+    }
+
+    /// checks the player actually holds everything on this side of the trade
+    fn check(&self, player: &Player, locale: &Locale) -> Result<(), String> {
+        if let Some(item) = &self.item
+            && player.inventory.quantity(item) < self.quantity {
+            return Err(locale.format("trade.no_longer_has", &[("player", &player.name), ("goods", &format!("{} x{}", locale.term(item), self.quantity))]))
+        }
+
+        if player.purse.total_copper() < self.coins as u64 {
+            return Err(locale.format("trade.no_longer_has", &[("player", &player.name), ("goods", &Purse::from_copper(self.coins))]))
+        }
+
+        Ok(())
+    }
+}
+
+/// a player to player trade waiting for both sides to confirm
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Trade {
+    pub id: u32,
+    pub from: Snowflake,
+    pub to: Snowflake,
+    pub offer: TradeSide,
+    pub request: TradeSide,
+    pub from_confirmed: bool,
+    pub to_confirmed: bool,
+}
+
+impl Trade {
+    fn describe(&self, campaign: &Campaign, locale: &Locale) -> String {
+        let name = |id: Snowflake| campaign.player(id).map(|p| p.name.clone()).unwrap_or_default();
+        let check = |confirmed: bool| locale.text(if confirmed { "trade.confirmed" } else { "trade.waiting" });
+
+        locale.format("trade.summary", &[
+            ("from", &name(self.from)), ("offer", &self.offer.describe(locale)), ("from_status", &check(self.from_confirmed)),
+            ("to", &name(self.to)), ("request", &self.request.describe(locale)), ("to_status", &check(self.to_confirmed))
+        ])
+    }
+
+    fn buttons(&self, locale: &Locale) -> Vec<Component> {
+        vec![Component::action_row(vec![
+            Component::button(BUTTON_STYLE_SUCCESS, &locale.text("trade.confirm"), &format!("trade:confirm:{}", self.id)),
+            Component::button(BUTTON_STYLE_DANGER, &locale.text("trade.cancel"), &format!("trade:cancel:{}", self.id))
+        ])]
+    }
+}
+
+/// moves both sides of a trade between the players. nothing changes unless both sides can pay
+fn apply_trade(campaign: &mut Campaign, trade: &Trade, locale: &Locale) -> Result<(), String> {
+    let from_index = campaign.players.iter().position(|p| p.id == trade.from);
+    let to_index = campaign.players.iter().position(|p| p.id == trade.to);
+    let (from_index, to_index) = match (from_index, to_index) {
+        (Some(f), Some(t)) => (f, t),
+        _ => return Err(locale.text("trade.player_left"))
+    };
+
+    trade.offer.check(&campaign.players[from_index], locale)?;
+    trade.request.check(&campaign.players[to_index], locale)?;
+
+    // worked on copies so a full purse halfway through leaves both players as they were
+    let mut players = [campaign.players[from_index].clone(), campaign.players[to_index].clone()];
+    for (side, giver, receiver) in [(&trade.offer, 0, 1), (&trade.request, 1, 0)] {
+        if let Some(item) = &side.item {
+            players[giver].inventory.remove(item, side.quantity, locale)?;
+            players[receiver].inventory.add(item, side.quantity, locale)?;
+        }
+        if side.coins > 0 {
+            players[giver].purse.spend(side.coins).map_err(|e| e.describe(locale))?;
+            players[receiver].purse.add_copper(side.coins).map_err(|e| e.describe(locale))?;
+        }
+    }
+
+    let [from, to] = players;
+    campaign.players[from_index] = from;
+    campaign.players[to_index] = to;
+    Ok(())
+}
+
+fn read_side(state: &AppState, interaction: &Interaction, locale: &Locale, prefix: &str) -> Result<TradeSide, String> {
+    let item = match interaction.option_str(&format!("{}_item", prefix)) {
+        Some(name) => match state.catalog.find(name) {
+            Some(i) => Some(i.name.clone()),
+            None => return Err(locale.format("item.unknown", &[("item", &name)]))
+        },
+        None => None
+    };
+
+    let coins = match interaction.option_str(&format!("{}_coins", prefix)) {
+        Some(c) => Purse::parse_copper(c).map_err(|e| e.describe(locale))?,
+        None => 0
+    };
+
+    Ok(TradeSide {
+        item,
+        quantity: interaction.option_i64(&format!("{}_quantity", prefix)).unwrap_or(1).clamp(1, MAX_QUANTITY as i64) as u32,
+        coins
+    })
+}
+
+pub fn trade_command(state: &AppState, interaction: &Interaction) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let (user_id, target) = match (interaction.user_id(), interaction.option_snowflake("player")) {
+        (Some(u), Some(t)) => (u, t),
+        _ => return ResponseOject::ephemeral(locale.text("trade.player_required"))
+    };
+
+    if user_id == target {
+        return ResponseOject::ephemeral(locale.text("trade.self"))
+    }
+
+    let (offer, request) = match (read_side(state, interaction, &locale, "offer"), read_side(state, interaction, &locale, "request")) {
+        (Ok(o), Ok(r)) => (o, r),
+        (Err(e), _) | (_, Err(e)) => return ResponseOject::ephemeral(e)
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        let from = match campaign.player(user_id) {
+            Some(p) => p,
+            None => return ResponseOject::ephemeral(locale.text("player.not_joined"))
+        };
+        if let Err(e) = offer.check(from, &locale) {
+            return ResponseOject::ephemeral(e)
+        }
+        if campaign.player(target).is_none() {
+            return ResponseOject::ephemeral(locale.text("player.other_not_joined"))
+        }
+
+        campaign.next_trade_id += 1;
+        let trade = Trade {
+            id: campaign.next_trade_id,
+            from: user_id,
+            to: target,
+            offer: offer.clone(),
+            request: request.clone(),
+            from_confirmed: false,
+            to_confirmed: false
+        };
+
+        let response = ResponseOject::with_components(trade.describe(campaign, &locale), trade.buttons(&locale));
+        campaign.trades.push(trade);
+        response
+    })
+}
+
+/// handles the confirm and cancel buttons under a trade message
+pub fn trade_button(state: &AppState, interaction: &Interaction, action: &str, trade_id: u32) -> ResponseOject {
+    let locale = i18n::locale(state, interaction);
+    let user_id = match interaction.user_id() {
+        Some(u) => u,
+        None => return ResponseOject::ephemeral(locale.text("error.no_user"))
+    };
+
+    with_campaign(state, interaction, |campaign| {
+        let index = match campaign.trades.iter().position(|t| t.id == trade_id) {
+            Some(i) => i,
+            None => return ResponseOject::update(locale.text("trade.closed"), Vec::new())
+        };
+
+        if campaign.trades[index].from != user_id && campaign.trades[index].to != user_id {
+            return ResponseOject::ephemeral(locale.text("trade.not_yours"))
+        }
+
+        if action == "cancel" {
+            let trade = campaign.trades.remove(index);
+            let message = locale.format("trade.cancelled", &[("trade", &trade.describe(campaign, &locale))]);
+            return ResponseOject::update(message, Vec::new())
+        }
+
+        let trade = &mut campaign.trades[index];
+        if trade.from == user_id {
+            trade.from_confirmed = true;
+        }
+        if trade.to == user_id {
+            trade.to_confirmed = true;
+        }
+
+        if !(trade.from_confirmed && trade.to_confirmed) {
+            let trade = campaign.trades[index].clone();
+            return ResponseOject::update(trade.describe(campaign, &locale), trade.buttons(&locale))
+        }
+
+        let trade = campaign.trades.remove(index);
+        match apply_trade(campaign, &trade, &locale) {
+            Ok(()) => ResponseOject::update(locale.format("trade.complete", &[("trade", &trade.describe(campaign, &locale))]), Vec::new()),
+            Err(e) => ResponseOject::update(locale.format("trade.failed", &[("trade", &trade.describe(campaign, &locale)), ("error", &e)]), Vec::new())
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ruleset::RulesetId;
+
+    const AYLA: Snowflake = Snowflake::new(1);
+    const BRAM: Snowflake = Snowflake::new(2);
+
+    fn campaign() -> Campaign {
+        let mut campaign = Campaign::new(Snowflake::new(10), RulesetId::D20);
+        for (id, name) in [(AYLA, "Ayla"), (BRAM, "Bram")] {
+            campaign.players.push(Player::new(id, name, "Fighter", campaign.ruleset.get()));
+        }
+        campaign.players[0].inventory.add("Rope", 2, &Locale::new(None, None)).unwrap();
+        campaign.players[1].purse = Purse { platinum: 0, gold: 5, silver: 0, copper: 0 };
+        campaign
+    }
+
+    fn trade(offer: TradeSide, request: TradeSide) -> Trade {
+        Trade { id: 1, from: AYLA, to: BRAM, offer, request, from_confirmed: true, to_confirmed: true }
+    }
+
+    #[test]
+    fn both_sides_change_hands() {
+        let mut campaign = campaign();
+        let locale = Locale::new(None, None);
+        let rope = TradeSide { item: Some(String::from("Rope")), quantity: 2, coins: 0 };
+        let gold = TradeSide { item: None, quantity: 1, coins: 300 };
+
+        apply_trade(&mut campaign, &trade(rope, gold), &locale).unwrap();
+
+        let (ayla, bram) = (&campaign.players[0], &campaign.players[1]);
+        assert_eq!(ayla.inventory.quantity("Rope"), 0);
+        assert_eq!(bram.inventory.quantity("Rope"), 2);
+        assert_eq!(ayla.purse.total_copper(), 300);
+        assert_eq!(bram.purse.total_copper(), 200);
+    }
+
+    #[test]
+    fn nothing_moves_when_one_side_no_longer_has_the_goods() {
+        let mut campaign = campaign();
+        let locale = Locale::new(None, None);
+        let rope = TradeSide { item: Some(String::from("Rope")), quantity: 2, coins: 0 };
+        let gold = TradeSide { item: None, quantity: 1, coins: 600 };
+
+        assert!(apply_trade(&mut campaign, &trade(rope.clone(), gold), &locale).is_err());
+        assert_eq!(campaign.players[0].inventory.quantity("Rope"), 2);
+        assert_eq!(campaign.players[1].purse.total_copper(), 500);
+
+        campaign.players[0].inventory.remove("Rope", 1, &locale).unwrap();
+        let gold = TradeSide { item: None, quantity: 1, coins: 100 };
+        assert!(apply_trade(&mut campaign, &trade(rope, gold), &locale).is_err());
+        assert_eq!(campaign.players[0].inventory.quantity("Rope"), 1);
+        assert_eq!(campaign.players[1].inventory.quantity("Rope"), 0);
+        assert_eq!(campaign.players[1].purse.total_copper(), 500);
+    }
+
+    #[test]
+    fn nothing_moves_when_a_purse_is_full() {
+        let mut campaign = campaign();
+        let locale = Locale::new(None, None);
+        campaign.players[0].purse.gold = u32::MAX;
+        let rope = TradeSide { item: Some(String::from("Rope")), quantity: 1, coins: 0 };
+        let gold = TradeSide { item: None, quantity: 1, coins: 100 };
+
+        assert!(apply_trade(&mut campaign, &trade(rope, gold), &locale).is_err());
+        assert_eq!(campaign.players[0].inventory.quantity("Rope"), 2);
+        assert_eq!(campaign.players[1].inventory.quantity("Rope"), 0);
+        assert_eq!(campaign.players[1].purse.total_copper(), 500);
+    }
+
+    #[test]
+    fn nothing_moves_when_an_inventory_is_full() {
+        let mut campaign = campaign();
+        let locale = Locale::new(None, None);
+        campaign.players[1].inventory.add("Rope", u32::MAX, &locale).unwrap();
+        let rope = TradeSide { item: Some(String::from("Rope")), quantity: 1, coins: 0 };
+        let gold = TradeSide { item: None, quantity: 1, coins: 100 };
+
+        assert!(apply_trade(&mut campaign, &trade(rope, gold), &locale).is_err());
+        assert_eq!(campaign.players[0].inventory.quantity("Rope"), 2);
+        assert_eq!(campaign.players[0].purse.total_copper(), 0);
+        assert_eq!(campaign.players[1].purse.total_copper(), 500);
+    }
+}